@@ -0,0 +1,69 @@
+use ratatui::termion::event::{Event, Key};
+
+use dizi::error::AppResult;
+use dizi::request::client::ClientRequest;
+
+use crate::context::AppState;
+use crate::event::AppEvent;
+use crate::run::process_event;
+use crate::ui::AppBackend;
+use crate::ui::views::TuiDeviceMenu;
+use crate::utils::request::send_client_request;
+
+/// Asks the server for its output devices and lets the user pick one to play on
+pub fn select_audio_device(context: &mut AppState, backend: &mut AppBackend) -> AppResult {
+    context.server_state_mut().audio_devices.clear();
+    send_client_request(context, &ClientRequest::AudioDevicesList)?;
+
+    let mut cursor = 0;
+    context.flush_event();
+    loop {
+        backend.render(TuiDeviceMenu::new(context, cursor));
+
+        let Ok(event) = context.poll_event() else {
+            return Ok(());
+        };
+        let len = context.server_state_ref().audio_devices.len();
+        match event {
+            AppEvent::TerminalEvent(Event::Key(key)) => {
+                match key {
+                    Key::Esc | Key::Char('q') => return Ok(()),
+                    Key::Up | Key::Char('k') => cursor = cursor.saturating_sub(1),
+                    Key::Down | Key::Char('j') if cursor + 1 < len => cursor += 1,
+                    Key::Char('\n') => {
+                        let name = context
+                            .server_state_ref()
+                            .audio_devices
+                            .get(cursor)
+                            .map(|device| device.name.clone());
+                        if let Some(name) = name {
+                            let request = ClientRequest::AudioDeviceSelect { name: Some(name) };
+                            send_client_request(context, &request)?;
+                        }
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+                context.flush_event();
+            }
+            AppEvent::TerminalEvent(_) => context.flush_event(),
+            AppEvent::Server(message) => {
+                if let Err(err) = process_event::process_server_event(context, message.as_str()) {
+                    context.message_queue_mut().push_error(err.to_string());
+                }
+                // start on the device that's playing
+                if len == 0 {
+                    let server_state = context.server_state_ref();
+                    cursor = server_state
+                        .audio_devices
+                        .iter()
+                        .position(|device| {
+                            server_state.player.audio_device == Some(device.name.clone())
+                        })
+                        .unwrap_or_default();
+                }
+            }
+            event => process_event::process_noninteractive(event, context),
+        }
+    }
+}
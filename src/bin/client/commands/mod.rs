@@ -1,3 +1,4 @@
+pub mod audio_device;
 pub mod change_directory;
 pub mod command_line;
 pub mod cursor_move;
@@ -9,12 +9,22 @@ use crate::config::option::DisplayOption;
 
 use super::display_raw::DisplayOptionRaw;
 
+const fn default_port() -> u16 {
+    6655
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct ClientConfigRaw {
     #[serde(default)]
     pub socket: String,
     #[serde(default)]
     pub home_dir: Option<String>,
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub token: Option<String>,
 
     #[serde(default, rename = "display")]
     pub display_options: DisplayOptionRaw,
@@ -25,6 +35,9 @@ impl std::default::Default for ClientConfigRaw {
         Self {
             socket: "".to_string(),
             home_dir: None,
+            host: None,
+            port: default_port(),
+            token: None,
             display_options: DisplayOptionRaw::default(),
         }
     }
@@ -42,6 +55,9 @@ impl From<ClientConfigRaw> for ClientConfig {
         Self {
             socket,
             home_dir,
+            host: raw.host,
+            port: raw.port,
+            token: raw.token,
             display_options: DisplayOption::from(raw.display_options),
         }
     }
@@ -51,6 +67,11 @@ impl From<ClientConfigRaw> for ClientConfig {
 pub struct ClientConfig {
     pub socket: PathBuf,
     pub home_dir: Option<PathBuf>,
+    /// connect over TCP to this host instead of the socket
+    pub host: Option<String>,
+    pub port: u16,
+    /// the server's `[server.tcp]` token
+    pub token: Option<String>,
     pub display_options: DisplayOption,
 }
 
@@ -72,6 +93,9 @@ impl std::default::Default for ClientConfig {
         Self {
             socket,
             home_dir: None,
+            host: None,
+            port: default_port(),
+            token: None,
             display_options: DisplayOption::default(),
         }
     }
@@ -15,6 +15,8 @@ pub enum LayoutCompositionRaw {
         border: bool,
         #[serde(default)]
         title: bool,
+        #[serde(default)]
+        style: Option<String>,
     },
     #[serde(rename = "composite")]
     Composite {
@@ -11,6 +11,17 @@ pub enum WidgetType {
     FileBrowser,
     MusicPlayer,
     Playlist,
+    Visualizer(VisualizerStyle),
+}
+
+/// How the `visualizer` widget draws the spectrum
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VisualizerStyle {
+    /// a bar per frequency band
+    #[default]
+    Bars,
+    /// peak and RMS level of each channel
+    Vu,
 }
 
 impl FromStr for WidgetType {
@@ -20,6 +31,7 @@ impl FromStr for WidgetType {
             "file_browser" => Ok(Self::FileBrowser),
             "music_player" => Ok(Self::MusicPlayer),
             "playlist" => Ok(Self::Playlist),
+            "visualizer" => Ok(Self::Visualizer(VisualizerStyle::default())),
             s => Err(DiziError::new(
                 DiziErrorKind::ParseError,
                 format!("Unknown widget type: '{}'", s),
@@ -44,6 +56,16 @@ pub enum LayoutComposition {
 }
 
 impl LayoutComposition {
+    /// Whether `widget` is somewhere in this layout
+    pub fn contains(&self, widget: fn(&WidgetType) -> bool) -> bool {
+        match self {
+            LayoutComposition::Simple { widget: w, .. } => widget(w),
+            LayoutComposition::Composite { widgets, .. } => {
+                widgets.iter().any(|w| w.contains(widget))
+            }
+        }
+    }
+
     pub fn ratio(&self) -> usize {
         match self {
             LayoutComposition::Simple { ratio, .. } => *ratio,
@@ -57,8 +79,14 @@ impl LayoutComposition {
                 ratio,
                 border,
                 title,
+                style,
             } => {
-                let widget = WidgetType::from_str(widget)?;
+                let widget = match (WidgetType::from_str(widget)?, style) {
+                    (WidgetType::Visualizer(_), Some(style)) => {
+                        WidgetType::Visualizer(str_to_visualizer_style(style)?)
+                    }
+                    (widget, _) => widget,
+                };
                 Ok(Self::Simple {
                     widget,
                     ratio: *ratio,
@@ -134,3 +162,14 @@ pub fn str_to_direction(s: &str) -> AppResult<Direction> {
         )),
     }
 }
+
+pub fn str_to_visualizer_style(s: &str) -> AppResult<VisualizerStyle> {
+    match s {
+        "bars" => Ok(VisualizerStyle::Bars),
+        "vu" => Ok(VisualizerStyle::Vu),
+        s => Err(DiziError::new(
+            DiziErrorKind::ParseError,
+            format!("Unknown visualizer style: '{}'", s),
+        )),
+    }
+}
@@ -1,10 +1,11 @@
 use ratatui::layout::Rect;
-use std::io;
-use std::os::unix::net::UnixStream;
+use std::io::{self, BufReader};
 use std::path::PathBuf;
 use std::sync::mpsc;
+use std::time::Duration;
 
-use dizi::utils;
+use dizi::request::client::RequestId;
+use dizi::utils::{self, DiziStream};
 
 use crate::config;
 use crate::config::option::WidgetType;
@@ -30,7 +31,9 @@ pub struct AppState {
     // event loop querying
     pub event_listener: AppEventListener,
     // server unix socket
-    pub stream: UnixStream,
+    pub stream: DiziStream,
+    // what the server sends, with whatever was read past the last line
+    reader: Option<BufReader<DiziStream>>,
     pub view_widget: WidgetType,
     // app config
     config: config::AppConfig,
@@ -47,10 +50,12 @@ pub struct AppState {
     message_queue: MessageQueue,
     // server state
     server_state: ServerState,
+    // id of the last request that wanted a reply
+    request_id: RequestId,
 }
 
 impl AppState {
-    pub fn new(config: config::AppConfig, _cwd: PathBuf, stream: UnixStream) -> Self {
+    pub fn new(config: config::AppConfig, _cwd: PathBuf, stream: DiziStream) -> Self {
         let events = AppEventListener::new();
 
         let commandline_state = CommandLineState::new();
@@ -58,6 +63,7 @@ impl AppState {
             quit: QuitType::DoNot,
             config,
             stream,
+            reader: None,
             view_widget: WidgetType::FileBrowser,
             event_listener: events,
             commandline_state,
@@ -66,13 +72,38 @@ impl AppState {
             ui_context: UiContext { layout: vec![] },
             message_queue: MessageQueue::new(),
             server_state: ServerState::new(),
+            request_id: 0,
         }
     }
 
-    pub fn clone_stream(&self) -> io::Result<UnixStream> {
+    /// An id for a request, to pick out the server's reply to it
+    pub fn next_request_id(&mut self) -> RequestId {
+        self.request_id += 1;
+        self.request_id
+    }
+
+    pub fn clone_stream(&self) -> io::Result<DiziStream> {
         self.stream.try_clone()
     }
 
+    /// Reads what the server sends, the same reader every time
+    /// so nothing it read ahead gets lost in between
+    pub fn reader_mut(&mut self) -> io::Result<&mut BufReader<DiziStream>> {
+        let reader = match self.reader.take() {
+            Some(reader) => reader,
+            None => BufReader::new(self.clone_stream()?),
+        };
+        Ok(self.reader.insert(reader))
+    }
+
+    /// Hands the reader over, along with whatever it already read
+    pub fn take_reader(&mut self) -> io::Result<BufReader<DiziStream>> {
+        match self.reader.take() {
+            Some(reader) => Ok(reader),
+            None => Ok(BufReader::new(self.clone_stream()?)),
+        }
+    }
+
     pub fn flush_stream(&mut self) -> io::Result<()> {
         utils::flush(&mut self.stream)
     }
@@ -81,6 +112,12 @@ impl AppState {
     pub fn poll_event(&self) -> Result<AppEvent, mpsc::RecvError> {
         self.event_listener.next()
     }
+    pub fn poll_event_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<AppEvent, mpsc::RecvTimeoutError> {
+        self.event_listener.next_timeout(timeout)
+    }
     pub fn flush_event(&self) {
         self.event_listener.flush();
     }
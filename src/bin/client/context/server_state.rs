@@ -1,14 +1,74 @@
-use dizi::player::PlayerState;
+use std::time::{Duration, Instant};
+
+use dizi::player::{AudioDevice, PlayerState, PlayerStatus, Spectrum};
+use dizi::protocol::Feature;
 
 #[derive(Clone, Debug)]
 pub struct ServerState {
     pub player: PlayerState,
+    // output devices the server last listed
+    pub audio_devices: Vec<AudioDevice>,
+    // latest levels for the visualizer, if subscribed to them
+    pub spectrum: Option<Spectrum>,
+    // when `player.elapsed` was last brought up to date
+    elapsed_at: Instant,
+    // optional features the server said it has
+    pub features: Vec<Feature>,
 }
 
 impl ServerState {
     pub fn new() -> Self {
         Self {
             player: PlayerState::new(),
+            audio_devices: Vec::new(),
+            spectrum: None,
+            elapsed_at: Instant::now(),
+            features: Vec::new(),
+        }
+    }
+
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    /// How far into the song the player is by now,
+    /// carrying on from the last progress update while playing
+    pub fn elapsed(&self) -> Duration {
+        let elapsed = self.player.elapsed;
+        if self.player.status != PlayerStatus::Playing {
+            return elapsed;
+        }
+        let played = self
+            .elapsed_at
+            .elapsed()
+            .mul_f32(self.player.speed.max(0.0));
+        let elapsed = elapsed + played;
+        match self
+            .player
+            .song
+            .as_ref()
+            .and_then(|song| song.audio_metadata().total_duration)
+        {
+            Some(total_duration) => elapsed.min(total_duration),
+            None => elapsed,
         }
     }
+
+    pub fn set_elapsed(&mut self, elapsed: Duration) {
+        self.player.elapsed = elapsed;
+        self.elapsed_at = Instant::now();
+    }
+
+    /// Replaces the whole player state, like when the server sends all of it
+    pub fn set_player(&mut self, player: PlayerState) {
+        self.player = player;
+        self.elapsed_at = Instant::now();
+    }
+
+    /// Pausing keeps the time reached so far, instead of going back to the last update
+    pub fn set_status(&mut self, status: PlayerStatus) {
+        let elapsed = self.elapsed();
+        self.set_elapsed(elapsed);
+        self.player.status = status;
+    }
 }
@@ -3,6 +3,7 @@ use std::path;
 use std::sync::mpsc;
 use std::sync::mpsc::Sender;
 use std::thread;
+use std::time::Duration;
 
 use ratatui::termion::event::Event;
 
@@ -68,6 +69,12 @@ impl AppEventListener {
         Ok(event)
     }
 
+    /// Like `next()`, giving up after `timeout`
+    pub fn next_timeout(&self, timeout: Duration) -> Result<AppEvent, mpsc::RecvTimeoutError> {
+        let event = self.event_rx.recv_timeout(timeout)?;
+        Ok(event)
+    }
+
     pub fn flush(&self) {
         loop {
             if self.input_tx.send(()).is_ok() {
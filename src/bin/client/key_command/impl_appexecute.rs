@@ -134,6 +134,9 @@ pub fn execute_request(
                 send_client_request(context, &request)?;
             }
         }
+        ClientRequest::AudioDeviceSelect { name: None } => {
+            audio_device::select_audio_device(context, backend)?;
+        }
         ClientRequest::PlaylistPlay { index: None } => {
             let playlist = &context.server_state_ref().player.playlist;
             if let Some(index) = playlist.get_cursor_index() {
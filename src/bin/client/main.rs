@@ -13,6 +13,8 @@ mod traits;
 mod ui;
 mod utils;
 
+use std::io;
+use std::net::TcpStream;
 use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
 use std::process;
@@ -23,12 +25,14 @@ use clap::Parser;
 use lazy_static::lazy_static;
 
 use dizi::error::AppResult;
+use dizi::utils::DiziStream;
 
 use crate::config::{
     AppConfig, AppKeyMapping, AppLayout, AppTheme, JsonConfigFile, TomlConfigFile,
 };
 use crate::context::AppState;
 use crate::tab::JoshutoTab;
+use crate::utils::request::server_hello;
 
 const PROGRAM_NAME: &str = "dizi";
 const CONFIG_HOME: &str = "DIZI_CONFIG_HOME";
@@ -100,6 +104,22 @@ pub struct CommandArgs {
     resume: bool,
     #[arg(long = "toggle-pause")]
     toggle_play: bool,
+    // seek to a position like 1:23.5, or by an offset like +10 or -5
+    #[arg(long = "seek", allow_hyphen_values = true)]
+    seek: Option<String>,
+    // stop playing after a while, like 30m or 1h30m, or off to cancel
+    #[arg(long = "sleep")]
+    sleep: Option<String>,
+
+    // scan loudness of a file or directory, or the current playlist if no path is given
+    #[arg(long = "scan-loudness", num_args = 0..=1)]
+    scan_loudness: Option<Option<PathBuf>>,
+
+    // connect to a server over TCP instead of its socket
+    #[arg(long = "host")]
+    host: Option<String>,
+    #[arg(long = "port")]
+    port: Option<u16>,
 }
 
 fn start_server() -> AppResult {
@@ -112,7 +132,20 @@ fn start_server() -> AppResult {
     Ok(())
 }
 
-fn create_context(config: AppConfig, cwd: &Path, stream: UnixStream) -> AppState {
+/// Connects over TCP if there's a host to connect to, the server's socket otherwise
+fn connect(config: &AppConfig) -> io::Result<DiziStream> {
+    let client = config.client_ref();
+    match client.host.as_deref() {
+        Some(host) => {
+            let stream = TcpStream::connect((host, client.port))?;
+            stream.set_nodelay(true)?;
+            Ok(stream.into())
+        }
+        None => UnixStream::connect(client.socket_ref()).map(DiziStream::from),
+    }
+}
+
+fn create_context(config: AppConfig, cwd: &Path, stream: DiziStream) -> AppState {
     AppState::new(config, cwd.to_path_buf(), stream)
 }
 
@@ -124,7 +157,16 @@ fn run_app(args: CommandArgs) -> AppResult {
         return Ok(());
     }
 
-    let config = AppConfig::get_config(CONFIG_FILE);
+    let mut config = AppConfig::get_config(CONFIG_FILE);
+    if args.host.is_some() {
+        config.client_mut().host = args.host.clone();
+    }
+    if let Some(port) = args.port {
+        config.client_mut().port = port;
+        if config.client_ref().host.is_none() {
+            config.client_mut().host = Some("localhost".to_string());
+        }
+    }
     if let Some(home_dir) = config.client_ref().home_dir.as_ref() {
         std::env::set_current_dir(home_dir)?;
     }
@@ -133,14 +175,16 @@ fn run_app(args: CommandArgs) -> AppResult {
     // query
     if args.query_all {
         // connect to stream
-        let stream = UnixStream::connect(config.client_ref().socket_ref())?;
+        let stream = connect(&config)?;
         let mut context = create_context(config, &cwd, stream);
+        server_hello(&mut context)?;
         run::run_query_all(&mut context)?;
         return Ok(());
     } else if let Some(query) = args.query {
         // connect to stream
-        let stream = UnixStream::connect(config.client_ref().socket_ref())?;
+        let stream = connect(&config)?;
         let mut context = create_context(config, &cwd, stream);
+        server_hello(&mut context)?;
         run::run_query(&mut context, query)?;
         return Ok(());
     } else if args.exit
@@ -149,23 +193,28 @@ fn run_app(args: CommandArgs) -> AppResult {
         || args.pause
         || args.resume
         || args.toggle_play
+        || args.seek.is_some()
+        || args.sleep.is_some()
+        || args.scan_loudness.is_some()
     {
         // connect to stream
-        let stream = UnixStream::connect(config.client_ref().socket_ref())?;
+        let stream = connect(&config)?;
         let mut context = create_context(config, &cwd, stream);
+        server_hello(&mut context)?;
         run::run_control(&mut context, &args)?;
     } else {
         lazy_static::initialize(&HOME_DIR);
         lazy_static::initialize(&THEME_T);
         lazy_static::initialize(&LAYOUT_T);
 
-        let mut stream = UnixStream::connect(config.client_ref().socket_ref());
-        if stream.is_err() {
+        let mut stream = connect(&config);
+        // only a local server can be started for us
+        if stream.is_err() && config.client_ref().host.is_none() {
             start_server()?;
         }
         println!("Connecting to server ...");
         for i in 1..11 {
-            stream = UnixStream::connect(config.client_ref().socket_ref());
+            stream = connect(&config);
             if stream.is_ok() {
                 break;
             }
@@ -178,6 +227,7 @@ fn run_app(args: CommandArgs) -> AppResult {
             Err(_) => eprintln!("Error: Failed to connect to server after 10 retries"),
             Ok(stream) => {
                 let mut context = create_context(config, &cwd, stream);
+                server_hello(&mut context)?;
 
                 let keymap = AppKeyMapping::get_config(KEYMAP_FILE);
                 // eprintln!("keymap: {:#?}", keymap);
@@ -1,5 +1,6 @@
 use std::io;
 use std::path;
+use std::time::Duration;
 
 use dizi::song::DiziSongEntry;
 use ratatui::termion::event::{Event, Key};
@@ -56,8 +57,11 @@ pub fn get_input_while_composite<'a>(
 
 pub fn process_server_event(context: &mut AppState, s: &str) -> AppResult {
     let server_broadcast_event: ServerBroadcastEvent = serde_json::from_str(s)?;
+    process_broadcast_event(context, server_broadcast_event)
+}
 
-    match server_broadcast_event {
+fn process_broadcast_event(context: &mut AppState, event: ServerBroadcastEvent) -> AppResult {
+    match event {
         ServerBroadcastEvent::ServerQuit => {
             context.quit = QuitType::Server;
         }
@@ -66,8 +70,20 @@ pub fn process_server_event(context: &mut AppState, s: &str) -> AppResult {
                 .message_queue_mut()
                 .push_error(format!("Server: {}", msg));
         }
+        ServerBroadcastEvent::Reply { result, .. } => match result {
+            Ok(Some(payload)) => process_broadcast_event(context, *payload)?,
+            Ok(None) => {}
+            Err(msg) => {
+                context
+                    .message_queue_mut()
+                    .push_error(format!("Server: {}", msg));
+            }
+        },
         ServerBroadcastEvent::ServerQuery { .. } => {}
         ServerBroadcastEvent::ServerQueryAll { .. } => {}
+        ServerBroadcastEvent::ServerHello { features, .. } => {
+            context.server_state_mut().features = features;
+        }
         ServerBroadcastEvent::PlayerState { mut state } => {
             if !state.playlist.is_empty() {
                 let old_state = &context.server_state_ref().player;
@@ -86,7 +102,7 @@ pub fn process_server_event(context: &mut AppState, s: &str) -> AppResult {
                     .unwrap_or_else(|| 0);
                 state.playlist.set_cursor_index(Some(new_cursor_index));
             }
-            context.server_state_mut().player = state;
+            context.server_state_mut().set_player(state);
         }
         ServerBroadcastEvent::PlaylistOpen { mut state } => {
             if !state.playlist.is_empty() {
@@ -106,7 +122,7 @@ pub fn process_server_event(context: &mut AppState, s: &str) -> AppResult {
                     .unwrap_or_else(|| 0);
                 state.playlist.set_cursor_index(Some(new_cursor_index));
             }
-            context.server_state_mut().player = state;
+            context.server_state_mut().set_player(state);
             let len = context.server_state_ref().player.playlist.len();
             context
                 .message_queue_mut()
@@ -115,16 +131,19 @@ pub fn process_server_event(context: &mut AppState, s: &str) -> AppResult {
         ServerBroadcastEvent::PlayerFilePlay { file: song } => {
             context.server_state_mut().player.song = Some(song);
             context.server_state_mut().player.status = PlayerStatus::Playing;
+            context.server_state_mut().set_elapsed(Duration::ZERO);
             context.server_state_mut().player.playlist_status = PlaylistType::DirectoryListing;
+            context.server_state_mut().player.loop_a = None;
+            context.server_state_mut().player.loop_b = None;
         }
         ServerBroadcastEvent::PlayerPause => {
-            context.server_state_mut().player.status = PlayerStatus::Paused;
+            context.server_state_mut().set_status(PlayerStatus::Paused);
         }
         ServerBroadcastEvent::PlayerResume => {
-            context.server_state_mut().player.status = PlayerStatus::Playing;
+            context.server_state_mut().set_status(PlayerStatus::Playing);
         }
         ServerBroadcastEvent::PlayerStop => {
-            context.server_state_mut().player.status = PlayerStatus::Stopped;
+            context.server_state_mut().set_status(PlayerStatus::Stopped);
         }
         ServerBroadcastEvent::PlayerShuffle { on } => {
             context.server_state_mut().player.shuffle = on;
@@ -150,11 +169,80 @@ pub fn process_server_event(context: &mut AppState, s: &str) -> AppResult {
                 .message_queue_mut()
                 .push_success(format!("{} {}", setting, status));
         }
+        ServerBroadcastEvent::AudioDevices { devices } => {
+            context.server_state_mut().audio_devices = devices;
+        }
+        ServerBroadcastEvent::AudioDeviceChanged {
+            name,
+            output_format,
+        } => {
+            let player = &mut context.server_state_mut().player;
+            player.audio_device = Some(name.clone());
+            player.output_format = Some(output_format);
+            context
+                .message_queue_mut()
+                .push_success(format!("Playing on {name}"));
+        }
+        ServerBroadcastEvent::LoudnessScanProgress {
+            path,
+            scanned,
+            total,
+            integrated,
+        } => {
+            if integrated.is_none() {
+                context
+                    .message_queue_mut()
+                    .push_error(format!("Failed to scan loudness of {}", path.display()));
+            }
+            if scanned == total {
+                context
+                    .message_queue_mut()
+                    .push_success(format!("Scanned loudness of {total} songs"));
+            }
+        }
+        ServerBroadcastEvent::PlayerSleep { sleep } => {
+            context.server_state_mut().player.sleep = sleep;
+        }
+        ServerBroadcastEvent::PlayerSpectrum { spectrum } => {
+            context.server_state_mut().spectrum = Some(spectrum);
+        }
         ServerBroadcastEvent::PlayerVolumeUpdate { volume } => {
             context.server_state_mut().player.volume = volume;
         }
+        ServerBroadcastEvent::PlayerTrack { track_id } => {
+            if let Some(song) = context.server_state_mut().player.song.as_mut() {
+                let _ = song.audio_metadata.select_track(track_id);
+                let msg = match song.audio_metadata.track() {
+                    Some(track) => format!(
+                        "Audio track {track_id}: {} ({})",
+                        track.language.as_deref().unwrap_or("unknown language"),
+                        track.codec
+                    ),
+                    None => format!("Audio track {track_id}"),
+                };
+                context.message_queue_mut().push_success(msg);
+            }
+        }
+        ServerBroadcastEvent::PlayerLoop { a, b } => {
+            context.server_state_mut().player.loop_a = a;
+            context.server_state_mut().player.loop_b = b;
+        }
+        ServerBroadcastEvent::PlayerSpeed { speed } => {
+            context.server_state_mut().player.speed = speed;
+            context
+                .message_queue_mut()
+                .push_success(format!("Speed: {speed:.2}x"));
+        }
+        ServerBroadcastEvent::PlayerEqualizer { preset } => {
+            let msg = match preset.as_ref() {
+                Some(preset) => format!("Equalizer: {preset}"),
+                None => "Equalizer OFF".to_string(),
+            };
+            context.server_state_mut().player.equalizer = preset;
+            context.message_queue_mut().push_success(msg);
+        }
         ServerBroadcastEvent::PlayerProgressUpdate { elapsed } => {
-            context.server_state_mut().player.elapsed = elapsed;
+            context.server_state_mut().set_elapsed(elapsed);
         }
         ServerBroadcastEvent::PlaylistSwapMove { index1, index2 } => {
             let playlist = &mut context.server_state_mut().player.playlist;
@@ -222,6 +310,7 @@ pub fn process_server_event(context: &mut AppState, s: &str) -> AppResult {
                     player.playlist.set_cursor_index(Some(index));
                 }
                 player.playlist.set_playing_index(Some(index));
+                context.server_state_mut().set_elapsed(Duration::ZERO);
             }
         }
     }
@@ -1,9 +1,10 @@
-use dizi::error::AppResult;
+use dizi::error::{AppResult, DiziError, DiziErrorKind};
 use dizi::request::client::ClientRequest;
 
 use crate::CommandArgs;
 use crate::context::AppState;
-use crate::utils::request::send_client_request;
+use crate::utils::format::{parse_duration, parse_timestamp};
+use crate::utils::request::{request_reply, send_client_request};
 
 pub fn run_control(context: &mut AppState, args: &CommandArgs) -> AppResult {
     let request = if args.exit {
@@ -18,11 +19,65 @@ pub fn run_control(context: &mut AppState, args: &CommandArgs) -> AppResult {
         Some(ClientRequest::PlayerResume)
     } else if args.toggle_play {
         Some(ClientRequest::PlayerTogglePlay)
+    } else if let Some(seek) = args.seek.as_ref() {
+        Some(seek_request(seek)?)
+    } else if let Some(sleep) = args.sleep.as_ref() {
+        Some(sleep_request(sleep)?)
+    } else if let Some(path) = args.scan_loudness.as_ref() {
+        // the server doesn't share our working directory
+        let path = path.as_deref().map(std::fs::canonicalize).transpose()?;
+        Some(ClientRequest::LoudnessScan { path })
     } else {
         None
     };
-    if let Some(request) = request {
-        send_client_request(context, &request)?;
+    match request {
+        // the server is gone before it gets to reply
+        Some(ClientRequest::ServerQuit) => {
+            send_client_request(context, &ClientRequest::ServerQuit)?;
+        }
+        Some(request) => {
+            request_reply(context, &request)?;
+        }
+        None => {}
     }
     Ok(())
 }
+
+fn seek_request(s: &str) -> AppResult<ClientRequest> {
+    let invalid = || {
+        DiziError::new(
+            DiziErrorKind::InvalidParameters,
+            format!("Invalid seek position '{s}'"),
+        )
+    };
+    let request = if let Some(offset) = s.strip_prefix('+') {
+        let offset_ms = parse_timestamp(offset).ok_or_else(invalid)? as i64;
+        ClientRequest::PlayerSeekRelative { offset_ms }
+    } else if let Some(offset) = s.strip_prefix('-') {
+        let offset_ms = parse_timestamp(offset).ok_or_else(invalid)? as i64;
+        ClientRequest::PlayerSeekRelative {
+            offset_ms: -offset_ms,
+        }
+    } else {
+        let position_ms = parse_timestamp(s).ok_or_else(invalid)?;
+        ClientRequest::PlayerSeek { position_ms }
+    };
+    Ok(request)
+}
+
+fn sleep_request(s: &str) -> AppResult<ClientRequest> {
+    if s == "off" {
+        return Ok(ClientRequest::PlayerSleepCancel);
+    }
+    let secs = parse_duration(s)
+        .filter(|secs| *secs > 0.0)
+        .ok_or_else(|| {
+            DiziError::new(
+                DiziErrorKind::InvalidParameters,
+                format!("Invalid sleep duration '{s}'"),
+            )
+        })?;
+    Ok(ClientRequest::PlayerSleep {
+        minutes: (secs / 60.0) as f32,
+    })
+}
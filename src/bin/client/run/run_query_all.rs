@@ -1,71 +1,22 @@
-use std::io::{BufRead, BufReader};
-use std::thread;
-
 use dizi::error::AppResult;
 use dizi::request::client::ClientRequest;
 use dizi::response::server::ServerBroadcastEvent;
 
 use crate::context::AppState;
-use crate::event::AppEvent;
-use crate::utils::request::send_client_request;
+use crate::utils::request::request_reply;
 
 pub fn run_query_all(context: &mut AppState) -> AppResult {
-    // server listener
+    let request = ClientRequest::PlayerState;
+    if let Some(ServerBroadcastEvent::PlayerState { mut state }) = request_reply(context, &request)?
     {
-        let stream = context.clone_stream()?;
-        let event_tx = context.clone_event_tx();
-
-        let _ = thread::spawn(move || {
-            let cursor = BufReader::new(stream);
-            for line in cursor.lines().map_while(Result::ok) {
-                let _ = event_tx.send(AppEvent::Server(line));
-            }
-        });
-
-        // request for server state
-        let request = ClientRequest::ServerQueryAll;
-        send_client_request(context, &request)?;
-
-        // request for server state
-        let request = ClientRequest::PlayerState;
-        send_client_request(context, &request)?;
-    }
-
-    loop {
-        let event = match context.poll_event() {
-            Ok(event) => event,
-            Err(_) => return Ok(()), // TODO
-        };
-
-        if let AppEvent::Server(message) = event {
-            let server_broadcast_event: ServerBroadcastEvent = serde_json::from_str(&message)?;
-            match server_broadcast_event {
-                ServerBroadcastEvent::ServerQueryAll { mut query_items } => {
-                    let mut items_sorted: Vec<(String, String)> = query_items.drain().collect();
-                    items_sorted.sort();
-                    for (key, val) in items_sorted {
-                        println!("{} = {}", key, val);
-                    }
-                    break;
-                }
-                ServerBroadcastEvent::PlayerState { mut state } => {
-                    if !state.playlist.is_empty() {
-                        state.playlist.set_cursor_index(Some(0));
-                    }
-                    let mut query_items = state.query_all();
-                    let mut items_sorted: Vec<(String, String)> = query_items.drain().collect();
-                    items_sorted.sort();
-                    for (key, val) in items_sorted {
-                        println!("{} = {}", key, val);
-                    }
-                    break;
-                }
-                ServerBroadcastEvent::ServerError { msg } => {
-                    println!("{}", msg);
-                    break;
-                }
-                _ => {}
-            }
+        if !state.playlist.is_empty() {
+            state.playlist.set_cursor_index(Some(0));
+        }
+        let mut query_items = state.query_all();
+        let mut items_sorted: Vec<(String, String)> = query_items.drain().collect();
+        items_sorted.sort();
+        for (key, val) in items_sorted {
+            println!("{} = {}", key, val);
         }
     }
     Ok(())
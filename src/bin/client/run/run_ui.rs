@@ -1,13 +1,19 @@
-use std::io::{BufRead, BufReader};
+use std::io::BufRead;
+use std::sync::mpsc;
 use std::thread;
+use std::time::Duration;
 
 use ratatui::layout::{Constraint, Rect};
 use ratatui::termion::event::Event;
 
 use dizi::error::AppResult;
+use dizi::player::PlayerStatus;
+use dizi::protocol::Feature;
 use dizi::request::client::ClientRequest;
 
+use crate::LAYOUT_T;
 use crate::config::AppKeyMapping;
+use crate::config::option::WidgetType;
 use crate::context::{AppState, QuitType};
 use crate::event::AppEvent;
 use crate::key_command::{AppExecute, Command, CommandKeybind};
@@ -19,6 +25,9 @@ use crate::ui::views;
 use crate::ui::views::TuiView;
 use crate::utils::request::send_client_request;
 
+/// How often the player is redrawn while playing
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
 pub fn run_ui(
     backend: &mut AppBackend,
     context: &mut AppState,
@@ -28,11 +37,11 @@ pub fn run_ui(
 
     // server listener
     {
-        let stream = context.clone_stream()?;
+        // picks up where the hello left off
+        let cursor = context.take_reader()?;
         let event_tx = context.clone_event_tx();
 
         let _ = thread::spawn(move || {
-            let cursor = BufReader::new(stream);
             for line in cursor.lines().map_while(Result::ok) {
                 let _ = event_tx.send(AppEvent::Server(line));
             }
@@ -41,6 +50,16 @@ pub fn run_ui(
         // request for server state
         let request = ClientRequest::PlayerState;
         send_client_request(context, &request)?;
+
+        // spectrum updates are only sent to clients that ask for them
+        if context.server_state_ref().supports(Feature::Spectrum)
+            && LAYOUT_T
+                .layout
+                .contains(|widget| matches!(widget, WidgetType::Visualizer(_)))
+        {
+            let request = ClientRequest::PlayerSpectrumSubscribe;
+            send_client_request(context, &request)?;
+        }
     }
 
     while context.quit == QuitType::DoNot {
@@ -59,9 +78,18 @@ pub fn run_ui(
             backend.render(TuiView::new(context));
         }
 
-        let event = match context.poll_event() {
-            Ok(event) => event,
-            Err(_) => return Ok(()), // TODO
+        // keep the clock and progress bar moving in between progress updates
+        let event = if context.server_state_ref().player.status == PlayerStatus::Playing {
+            match context.poll_event_timeout(REDRAW_INTERVAL) {
+                Ok(event) => event,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        } else {
+            match context.poll_event() {
+                Ok(event) => event,
+                Err(_) => return Ok(()), // TODO
+            }
         };
 
         match event {
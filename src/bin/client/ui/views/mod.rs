@@ -1,9 +1,11 @@
 mod tui_command_menu;
+mod tui_device_menu;
 mod tui_folder_view;
 mod tui_textfield;
 mod tui_view;
 
 pub use self::tui_command_menu::*;
+pub use self::tui_device_menu::*;
 pub use self::tui_folder_view::*;
 pub use self::tui_textfield::*;
 pub use self::tui_view::*;
@@ -0,0 +1,76 @@
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::widgets::{Clear, Widget};
+
+use crate::context::AppState;
+use crate::ui::views::TuiView;
+use crate::ui::widgets::TuiMenu;
+
+const BORDER_HEIGHT: usize = 1;
+const BOTTOM_MARGIN: usize = 1;
+
+/// Lists the server's output devices, marking the one under the cursor
+pub struct TuiDeviceMenu<'a> {
+    context: &'a AppState,
+    cursor: usize,
+}
+
+impl<'a> TuiDeviceMenu<'a> {
+    pub fn new(context: &'a AppState, cursor: usize) -> Self {
+        Self { context, cursor }
+    }
+}
+
+impl<'a> Widget for TuiDeviceMenu<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        TuiView::new(self.context).render(area, buf);
+
+        let server_state = self.context.server_state_ref();
+        let playing_on = server_state.player.audio_device.as_deref();
+        let display_vec: Vec<String> = if server_state.audio_devices.is_empty() {
+            vec!["  Loading audio devices...".to_string()]
+        } else {
+            server_state
+                .audio_devices
+                .iter()
+                .enumerate()
+                .map(|(i, device)| {
+                    let cursor = if i == self.cursor { ">" } else { " " };
+                    let playing = if playing_on == Some(device.name.as_str()) {
+                        "*"
+                    } else {
+                        " "
+                    };
+                    format!(
+                        "{cursor}{playing} {}    ({})",
+                        device.description, device.name
+                    )
+                })
+                .collect()
+        };
+        let display_str: Vec<&str> = display_vec.iter().map(|v| v.as_str()).collect();
+        let display_str_len = display_str.len();
+
+        let y = if (area.height as usize) < display_str_len + BORDER_HEIGHT + BOTTOM_MARGIN {
+            0
+        } else {
+            area.height - (BORDER_HEIGHT + BOTTOM_MARGIN) as u16 - display_str_len as u16
+        };
+
+        let menu_height = if display_str_len + BORDER_HEIGHT > area.height as usize {
+            area.height
+        } else {
+            (display_str_len + BORDER_HEIGHT) as u16
+        };
+
+        let menu_rect = Rect {
+            x: 0,
+            y,
+            width: area.width,
+            height: menu_height,
+        };
+
+        Clear.render(menu_rect, buf);
+        TuiMenu::new(&display_str).render(menu_rect, buf);
+    }
+}
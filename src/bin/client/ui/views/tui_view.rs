@@ -6,7 +6,7 @@ use ratatui::widgets::{Block, Borders, Paragraph, Widget, Wrap};
 
 use crate::config::option::{LayoutComposition, WidgetType};
 use crate::context::AppState;
-use crate::ui::widgets::{TuiFooter, TuiPlayer, TuiPlaylist, TuiTopBar};
+use crate::ui::widgets::{TuiFooter, TuiPlayer, TuiPlaylist, TuiTopBar, TuiVisualizer};
 
 use crate::LAYOUT_T;
 
@@ -113,11 +113,16 @@ pub fn render_widget(context: &AppState, layout: &LayoutComposition, area: Rect,
             match widget {
                 WidgetType::FileBrowser => TuiFolderView::new(context, focused).render(rect, buf),
                 WidgetType::MusicPlayer => {
-                    TuiPlayer::new(&context.server_state_ref().player).render(rect, buf)
+                    let server_state = context.server_state_ref();
+                    TuiPlayer::new(&server_state.player, server_state.elapsed()).render(rect, buf)
                 }
                 WidgetType::Playlist => {
                     TuiPlaylist::new(&context.server_state_ref().player, focused).render(rect, buf)
                 }
+                WidgetType::Visualizer(style) => {
+                    TuiVisualizer::new(context.server_state_ref().spectrum.as_ref(), *style)
+                        .render(rect, buf)
+                }
             }
         }
         LayoutComposition::Composite {
@@ -6,6 +6,7 @@ mod tui_playlist;
 mod tui_prompt;
 mod tui_text;
 mod tui_topbar;
+mod tui_visualizer;
 
 pub use self::tui_dirlist_detailed::*;
 pub use self::tui_footer::*;
@@ -15,3 +16,4 @@ pub use self::tui_playlist::*;
 pub use self::tui_prompt::*;
 pub use self::tui_text::*;
 pub use self::tui_topbar::*;
+pub use self::tui_visualizer::*;
@@ -18,7 +18,7 @@ impl<'a> TuiFooter<'a> {
 
 impl<'a> Widget for TuiFooter<'a> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let text = vec![
+        let mut text = vec![
             Span::styled(
                 format!("Audio system: {}", self.player_state.audio_host),
                 Style::default().fg(Color::Green),
@@ -45,8 +45,39 @@ impl<'a> Widget for TuiFooter<'a> {
                     .map(|s| s.to_string())
                     .unwrap_or_else(|| "UNKNOWN".to_string())
             )),
+            Span::raw("  "),
+            Span::raw(format!(
+                "Output: {}",
+                self.player_state
+                    .output_format
+                    .as_ref()
+                    .map(|format| format!(
+                        "{} Hz, {} channels, {}",
+                        format.sample_rate, format.channels, format.sample_format
+                    ))
+                    .unwrap_or_else(|| "UNKNOWN".to_string())
+            )),
         ];
 
+        // only worth showing when there's a choice
+        let audio_metadata = self
+            .player_state
+            .song
+            .as_ref()
+            .map(|song| song.audio_metadata());
+        if let Some(metadata) = audio_metadata
+            && metadata.tracks.len() > 1
+            && let Some(track) = metadata.track()
+        {
+            text.push(Span::raw("  "));
+            text.push(Span::raw(format!(
+                "Audio track: {} ({}, {})",
+                track.id,
+                track.language.as_deref().unwrap_or("unknown"),
+                track.codec
+            )));
+        }
+
         Paragraph::new(Line::from(text)).render(area, buf);
     }
 }
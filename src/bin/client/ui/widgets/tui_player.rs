@@ -11,11 +11,13 @@ use dizi::player::{PlayerState, PlayerStatus};
 
 pub struct TuiPlayer<'a> {
     player: &'a PlayerState,
+    // how far into the song the player is by now, in between progress updates
+    elapsed: time::Duration,
 }
 
 impl<'a> TuiPlayer<'a> {
-    pub fn new(player: &'a PlayerState) -> Self {
-        Self { player }
+    pub fn new(player: &'a PlayerState, elapsed: time::Duration) -> Self {
+        Self { player, elapsed }
     }
 }
 
@@ -61,6 +63,11 @@ impl<'a> Widget for TuiPlayer<'a> {
                 PlaylistType::DirectoryListing => on_style,
             };
 
+            let equalizer_span = match self.player.equalizer.as_ref() {
+                Some(preset) => Span::styled(format!("[EQ: {preset}] "), on_style),
+                None => Span::styled("[EQ] ", off_style),
+            };
+
             let player_status_style = Style::default()
                 .fg(Color::Green)
                 .add_modifier(Modifier::BOLD);
@@ -69,8 +76,13 @@ impl<'a> Widget for TuiPlayer<'a> {
                     format!("Volume: {}%  ", self.player.volume,),
                     player_status_style,
                 ),
+                Span::styled(
+                    format!("Speed: {:.2}x  ", self.player.speed),
+                    player_status_style,
+                ),
                 Span::styled("[PLAYLIST] ", playlist_file_style),
                 Span::styled("[DIRECTORY] ", playlist_directory_style),
+                equalizer_span,
             ]);
 
             let rect = Rect {
@@ -81,7 +93,7 @@ impl<'a> Widget for TuiPlayer<'a> {
             Paragraph::new(text).render(rect, buf);
         }
 
-        let duration_elapsed = self.player.elapsed;
+        let duration_elapsed = self.elapsed;
         let duration_played_str = {
             let total_secs = duration_elapsed.as_secs();
             let minutes = total_secs / 60;
@@ -117,6 +129,11 @@ impl<'a> Widget for TuiPlayer<'a> {
                 false => off_style,
             };
 
+            let sleep_span = match self.player.sleep.as_ref() {
+                Some(sleep) => Span::styled(format!("[SLEEP: {sleep}] "), on_style),
+                None => Span::styled("[SLEEP] ", off_style),
+            };
+
             let player_status = match self.player.status {
                 PlayerStatus::Playing => "\u{25B6}  ",
                 PlayerStatus::Stopped => "\u{2588}\u{2588}",
@@ -131,6 +148,7 @@ impl<'a> Widget for TuiPlayer<'a> {
                 Span::styled("[NEXT] ", next_style),
                 Span::styled("[REPEAT] ", repeat_style),
                 Span::styled("[SHUFFLE] ", shuffle_style),
+                sleep_span,
             ]);
 
             let rect = Rect {
@@ -142,12 +160,11 @@ impl<'a> Widget for TuiPlayer<'a> {
         }
 
         {
-            let total_duration = total_duration.as_secs();
-            if total_duration > 0 {
-                let secs = duration_elapsed.as_secs();
+            let total_duration = total_duration.as_secs_f32();
+            if total_duration > 0.0 {
                 // draw a progress bar
                 let progress_bar_width =
-                    (secs as f32 / total_duration as f32 * area.width as f32) as usize;
+                    (duration_elapsed.as_secs_f32() / total_duration * area.width as f32) as usize;
 
                 let progress_bar_space = " ".repeat(progress_bar_width);
                 let style = Style::default().bg(Color::Blue);
@@ -158,6 +175,26 @@ impl<'a> Widget for TuiPlayer<'a> {
                     area.width as usize,
                     style,
                 );
+
+                // mark the ends of the A-B loop
+                let marker_style = Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD);
+                let markers = [("A", self.player.loop_a), ("B", self.player.loop_b)];
+                for (marker, position) in markers {
+                    let Some(position) = position else {
+                        continue;
+                    };
+                    let offset =
+                        (position.as_secs_f32() / total_duration * area.width as f32) as u16;
+                    buf.set_stringn(
+                        area.x + offset.min(area.width.saturating_sub(1)),
+                        area.y + area.height - 1,
+                        marker,
+                        1,
+                        marker_style,
+                    );
+                }
             }
         }
     }
@@ -0,0 +1,111 @@
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Style};
+use ratatui::widgets::Widget;
+
+use dizi::player::{SPECTRUM_FLOOR_DB, Spectrum};
+
+use crate::config::option::VisualizerStyle;
+
+const BAR_SYMBOLS: [&str; 8] = ["▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+
+pub struct TuiVisualizer<'a> {
+    spectrum: Option<&'a Spectrum>,
+    style: VisualizerStyle,
+}
+
+impl<'a> TuiVisualizer<'a> {
+    pub fn new(spectrum: Option<&'a Spectrum>, style: VisualizerStyle) -> Self {
+        Self { spectrum, style }
+    }
+}
+
+impl<'a> Widget for TuiVisualizer<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+        let Some(spectrum) = self.spectrum else {
+            return;
+        };
+        match self.style {
+            VisualizerStyle::Bars => render_bars(spectrum, area, buf),
+            VisualizerStyle::Vu => render_vu(spectrum, area, buf),
+        }
+    }
+}
+
+/// How far `db` is between the floor and full scale, from 0 to 1
+fn level(db: f32) -> f32 {
+    ((db - SPECTRUM_FLOOR_DB) / -SPECTRUM_FLOOR_DB).clamp(0.0, 1.0)
+}
+
+fn render_bars(spectrum: &Spectrum, area: Rect, buf: &mut Buffer) {
+    if spectrum.bands.is_empty() {
+        return;
+    }
+    let style = Style::default().fg(Color::Cyan);
+    let band_count = spectrum.bands.len();
+    for x in 0..area.width {
+        let band = x as usize * band_count / area.width as usize;
+        // bar height in eighths of a cell
+        let mut eighths = (level(spectrum.bands[band]) * area.height as f32 * 8.0).round() as u16;
+        for y in (0..area.height).rev() {
+            if eighths == 0 {
+                break;
+            }
+            let symbol = BAR_SYMBOLS[eighths.min(8) as usize - 1];
+            buf[(area.x + x, area.y + y)]
+                .set_symbol(symbol)
+                .set_style(style);
+            eighths = eighths.saturating_sub(8);
+        }
+    }
+}
+
+fn render_vu(spectrum: &Spectrum, area: Rect, buf: &mut Buffer) {
+    let channels = spectrum.rms.len().min(spectrum.peak.len());
+    let labels: Vec<String> = match channels {
+        2 => vec!["L".to_string(), "R".to_string()],
+        _ => (1..=channels).map(|channel| channel.to_string()).collect(),
+    };
+    let label_width = labels.iter().map(|label| label.len()).max().unwrap_or(0) as u16 + 1;
+    // leave room for the peak level, like "  -12.3 dB"
+    let meter_width = area.width.saturating_sub(label_width + 10);
+    if meter_width == 0 {
+        return;
+    }
+
+    for (channel, label) in labels.iter().enumerate().take(area.height as usize) {
+        let y = area.y + channel as u16;
+        let (rms, peak) = (spectrum.rms[channel], spectrum.peak[channel]);
+        buf.set_string(area.x, y, label, Style::default());
+
+        let meter_x = area.x + label_width;
+        let filled = (level(rms) * meter_width as f32).round() as u16;
+        for x in 0..filled {
+            // green until -12 dB, yellow until -3 dB, then red
+            let color = match SPECTRUM_FLOOR_DB * (1.0 - (x + 1) as f32 / meter_width as f32) {
+                db if db > -3.0 => Color::Red,
+                db if db > -12.0 => Color::Yellow,
+                _ => Color::Green,
+            };
+            buf[(meter_x + x, y)]
+                .set_symbol("█")
+                .set_style(Style::default().fg(color));
+        }
+        let peak_x = (level(peak) * meter_width as f32).round() as u16;
+        if peak_x > 0 {
+            buf[(meter_x + peak_x - 1, y)]
+                .set_symbol("▏")
+                .set_style(Style::default().fg(Color::White));
+        }
+
+        let peak_text = if peak <= SPECTRUM_FLOOR_DB {
+            "   -inf dB".to_string()
+        } else {
+            format!("{peak:>7.1} dB")
+        };
+        buf.set_string(meter_x + meter_width, y, peak_text, Style::default());
+    }
+}
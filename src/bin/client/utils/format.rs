@@ -21,3 +21,84 @@ pub fn file_size_to_string(file_size: u64) -> String {
 pub fn clear_screen() {
     println!("{}", ratatui::termion::clear::All);
 }
+
+/// Parses timestamps like `83.5`, `1:23.5` or `1:01:23` into milliseconds
+pub fn parse_timestamp(s: &str) -> Option<u64> {
+    let mut ms = 0.0;
+    for part in s.split(':') {
+        let value: f64 = part.parse().ok()?;
+        if !value.is_finite() || value < 0.0 {
+            return None;
+        }
+        ms = ms * 60.0 + value;
+    }
+    Some((ms * 1000.0).round() as u64)
+}
+
+/// Parses durations like `30m`, `1h30m` or `90s` into seconds,
+/// a plain number being minutes
+pub fn parse_duration(s: &str) -> Option<f64> {
+    if let Ok(minutes) = s.parse::<f64>() {
+        return (minutes.is_finite() && minutes >= 0.0).then_some(minutes * 60.0);
+    }
+    if s.is_empty() {
+        return None;
+    }
+    let mut secs = 0.0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let unit_start = rest.find(|c: char| c.is_ascii_alphabetic())?;
+        let value: f64 = rest[..unit_start].parse().ok()?;
+        if !value.is_finite() || value < 0.0 {
+            return None;
+        }
+        let unit_end = rest[unit_start..]
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .map_or(rest.len(), |i| unit_start + i);
+        secs += value
+            * match &rest[unit_start..unit_end] {
+                "h" => 3600.0,
+                "m" => 60.0,
+                "s" => 1.0,
+                _ => return None,
+            };
+        rest = &rest[unit_end..];
+    }
+    Some(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_duration, parse_timestamp};
+
+    #[test]
+    fn parses_seconds_minutes_and_hours() {
+        assert_eq!(parse_timestamp("83.5"), Some(83_500));
+        assert_eq!(parse_timestamp("1:23.5"), Some(83_500));
+        assert_eq!(parse_timestamp("1:01:23"), Some(3_683_000));
+    }
+
+    #[test]
+    fn rejects_invalid_timestamps() {
+        assert_eq!(parse_timestamp(""), None);
+        assert_eq!(parse_timestamp("1:-5"), None);
+        assert_eq!(parse_timestamp("abc"), None);
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30"), Some(1800.0));
+        assert_eq!(parse_duration("30m"), Some(1800.0));
+        assert_eq!(parse_duration("1h30m"), Some(5400.0));
+        assert_eq!(parse_duration("90s"), Some(90.0));
+        assert_eq!(parse_duration("1.5h"), Some(5400.0));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("30x"), None);
+        assert_eq!(parse_duration("m"), None);
+        assert_eq!(parse_duration("-5m"), None);
+    }
+}
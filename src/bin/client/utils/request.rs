@@ -1,10 +1,17 @@
-use std::io::Write;
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
 
-use dizi::error::AppResult;
-use dizi::request::client::ClientRequest;
+use dizi::error::{AppResult, DiziError, DiziErrorKind};
+use dizi::protocol;
+use dizi::request::client::{AuthToken, ClientRequest, ClientRequestMessage};
+use dizi::response::server::ServerBroadcastEvent;
 
 use crate::context::AppState;
 
+/// How long to wait for the server to answer our hello,
+/// servers that predate it never do
+const HELLO_TIMEOUT: Duration = Duration::from_secs(3);
+
 pub fn send_client_request(context: &mut AppState, request: &ClientRequest) -> AppResult {
     let json = serde_json::to_string(&request)?;
 
@@ -12,3 +19,178 @@ pub fn send_client_request(context: &mut AppState, request: &ClientRequest) -> A
     context.flush_stream()?;
     Ok(())
 }
+
+/// Sends `request` and waits for the server's reply to it,
+/// skipping over whatever else the server broadcasts in the meantime.
+///
+/// Returns what the server sent back, if the request asked for anything
+pub fn request_reply(
+    context: &mut AppState,
+    request: &ClientRequest,
+) -> AppResult<Option<ServerBroadcastEvent>> {
+    let id = context.next_request_id();
+    let message = ClientRequestMessage {
+        id: Some(id),
+        request: request.clone(),
+    };
+    let json = serde_json::to_string(&message)?;
+    context.stream.write_all(json.as_bytes())?;
+    context.flush_stream()?;
+
+    let reader = context.reader_mut()?;
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        let res = serde_json::from_str(&line);
+        line.clear();
+        let event = match res {
+            Ok(event) => event,
+            // newer events we don't know about
+            Err(_) => continue,
+        };
+        if let ServerBroadcastEvent::Reply {
+            id: Some(reply_id),
+            result,
+        } = event
+            && reply_id == id
+        {
+            return result
+                .map(|payload| payload.map(|payload| *payload))
+                .map_err(|msg| DiziError::new(DiziErrorKind::Server, msg));
+        }
+    }
+    Err(DiziError::new(
+        DiziErrorKind::Server,
+        "Server closed the connection before replying".to_string(),
+    ))
+}
+
+/// Gives the server our token, which it wants before anything else over TCP
+fn authenticate(context: &mut AppState) -> AppResult {
+    let token = context
+        .config_ref()
+        .client_ref()
+        .token
+        .clone()
+        .ok_or_else(|| {
+            DiziError::new(
+                DiziErrorKind::Unauthorized,
+                "Set a token in client.toml to connect over TCP".to_string(),
+            )
+        })?;
+    let token = AuthToken(token);
+    request_reply(context, &ClientRequest::ClientAuth { token }).map_err(|err| {
+        DiziError::new(DiziErrorKind::Unauthorized, format!("dizi-server: {err}"))
+    })?;
+    Ok(())
+}
+
+/// Tells the server which protocol version and features we have,
+/// failing if the two of us can't talk to each other.
+///
+/// Over TCP, authenticates first
+pub fn server_hello(context: &mut AppState) -> AppResult {
+    if context.stream.is_tcp() {
+        context.stream.set_read_timeout(Some(HELLO_TIMEOUT))?;
+        let res = authenticate(context);
+        context.stream.set_read_timeout(None)?;
+        res?;
+    }
+    let request = ClientRequest::ServerHello {
+        version: protocol::PROTOCOL_VERSION,
+        features: protocol::FEATURES.to_vec(),
+    };
+    context.stream.set_read_timeout(Some(HELLO_TIMEOUT))?;
+    let res = request_reply(context, &request);
+    context.stream.set_read_timeout(None)?;
+
+    let mismatch = |msg: String| DiziError::new(DiziErrorKind::IncompatibleProtocol, msg);
+    match res {
+        Ok(Some(ServerBroadcastEvent::ServerHello {
+            version, features, ..
+        })) => {
+            if !protocol::is_compatible(version) {
+                return Err(mismatch(format!(
+                    "dizi-server speaks protocol version {version}, dizi speaks {}. \
+                     Restart the server after updating it",
+                    protocol::supported_versions()
+                )));
+            }
+            context.server_state_mut().features = features;
+            Ok(())
+        }
+        Ok(_) => Err(mismatch("dizi-server didn't answer our hello".to_string())),
+        Err(err)
+            if matches!(
+                err.kind(),
+                DiziErrorKind::IoError(io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+            ) =>
+        {
+            Err(mismatch(format!(
+                "dizi-server didn't answer our hello, it likely predates protocol version {}. \
+                 Restart the server after updating it",
+                protocol::PROTOCOL_VERSION
+            )))
+        }
+        Err(err) if matches!(err.kind(), DiziErrorKind::Server) => {
+            Err(mismatch(format!("dizi-server turned us away: {err}")))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
+    use std::thread;
+
+    use dizi::player::PlayerState;
+
+    use super::*;
+    use crate::config::AppConfig;
+
+    #[test]
+    fn broadcasts_right_after_a_reply_are_kept() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let server = thread::spawn(move || {
+            let mut reader = BufReader::new(server.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let message: ClientRequestMessage = serde_json::from_str(&line).unwrap();
+            let hello = ServerBroadcastEvent::ServerHello {
+                version: protocol::PROTOCOL_VERSION,
+                apis: Vec::new(),
+                features: Vec::new(),
+            };
+            let reply = ServerBroadcastEvent::Reply {
+                id: message.id,
+                result: Ok(Some(Box::new(hello))),
+            };
+            let state = ServerBroadcastEvent::PlayerState {
+                state: PlayerState::new(),
+            };
+            // both in one write, so they come in together
+            let json = format!(
+                "{}\n{}\n",
+                serde_json::to_string(&reply).unwrap(),
+                serde_json::to_string(&state).unwrap()
+            );
+            (&server).write_all(json.as_bytes()).unwrap();
+        });
+
+        let mut context = AppState::new(AppConfig::default(), PathBuf::new(), client.into());
+        server_hello(&mut context).unwrap();
+        server.join().unwrap();
+
+        let mut line = String::new();
+        context.take_reader().unwrap().read_line(&mut line).unwrap();
+        assert!(
+            matches!(
+                serde_json::from_str(&line).unwrap(),
+                ServerBroadcastEvent::PlayerState { .. }
+            ),
+            "{line}"
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+/// One end of an A–B loop
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopPoint {
+    A,
+    B,
+}
+
+/// Section of the current song to repeat, playback jumps back to `a` on reaching `b`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AbLoop {
+    pub a: Option<Duration>,
+    pub b: Option<Duration>,
+}
+
+impl AbLoop {
+    /// Moves `point` to `position`, keeping A before B.
+    ///
+    /// Setting B without A loops from the start of the song,
+    /// and setting B before A swaps the two.
+    pub fn set(&mut self, point: LoopPoint, position: Duration) {
+        match point {
+            LoopPoint::A => {
+                self.a = Some(position);
+                if self.b.is_some_and(|b| b <= position) {
+                    self.b = None;
+                }
+            }
+            LoopPoint::B => {
+                let a = self.a.unwrap_or_default();
+                if position > a {
+                    self.a = Some(a);
+                    self.b = Some(position);
+                } else if position < a {
+                    self.a = Some(position);
+                    self.b = Some(a);
+                } else {
+                    self.b = None;
+                }
+            }
+        }
+    }
+
+    /// Start and end of the loop, once both are set
+    pub fn range(&self) -> Option<(Duration, Duration)> {
+        match (self.a, self.b) {
+            (Some(a), Some(b)) if a < b => Some((a, b)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn loops_once_both_points_are_set() {
+        let mut ab_loop = AbLoop::default();
+        ab_loop.set(LoopPoint::A, secs(10));
+        assert_eq!(ab_loop.range(), None);
+        ab_loop.set(LoopPoint::B, secs(20));
+        assert_eq!(ab_loop.range(), Some((secs(10), secs(20))));
+    }
+
+    #[test]
+    fn b_without_a_loops_from_the_start() {
+        let mut ab_loop = AbLoop::default();
+        ab_loop.set(LoopPoint::B, secs(20));
+        assert_eq!(ab_loop.range(), Some((secs(0), secs(20))));
+    }
+
+    #[test]
+    fn points_stay_in_order() {
+        let mut ab_loop = AbLoop::default();
+        ab_loop.set(LoopPoint::A, secs(10));
+        ab_loop.set(LoopPoint::B, secs(5));
+        assert_eq!(ab_loop.range(), Some((secs(5), secs(10))));
+
+        // moving A past B starts a new loop
+        ab_loop.set(LoopPoint::A, secs(30));
+        assert_eq!(ab_loop.a, Some(secs(30)));
+        assert_eq!(ab_loop.b, None);
+    }
+}
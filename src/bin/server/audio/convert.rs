@@ -0,0 +1,54 @@
+use crate::audio::remix::ChannelMixer;
+use crate::audio::resample::Resampler;
+use crate::audio::symphonia::decode::DecodedAudioSpec;
+
+/// Remixes and resamples decoded audio into the format the output device was opened with
+#[derive(Clone, Debug)]
+pub struct SampleConverter {
+    input_spec: DecodedAudioSpec,
+    mixer: Option<ChannelMixer>,
+    resampler: Option<Resampler>,
+}
+
+impl SampleConverter {
+    pub fn new(input_spec: DecodedAudioSpec, output_spec: DecodedAudioSpec) -> Self {
+        let mixer = (input_spec.channels != output_spec.channels)
+            .then(|| ChannelMixer::new(input_spec.channels, output_spec.channels));
+        // mix first, so there are fewer channels to resample when downmixing
+        let resampler = (input_spec.sample_rate != output_spec.sample_rate).then(|| {
+            Resampler::new(
+                output_spec.channels,
+                input_spec.sample_rate,
+                output_spec.sample_rate,
+            )
+        });
+        Self {
+            input_spec,
+            mixer,
+            resampler,
+        }
+    }
+
+    pub fn input_spec(&self) -> DecodedAudioSpec {
+        self.input_spec
+    }
+
+    pub fn process(&mut self, samples: Vec<f32>) -> Vec<f32> {
+        let samples = match self.mixer.as_ref() {
+            Some(mixer) => mixer.process(&samples),
+            None => samples,
+        };
+        match self.resampler.as_mut() {
+            Some(resampler) => resampler.process(&samples),
+            None => samples,
+        }
+    }
+
+    /// Returns whatever is still buffered once the input has ended
+    pub fn flush(&mut self) -> Vec<f32> {
+        match self.resampler.as_mut() {
+            Some(resampler) => resampler.flush(),
+            None => Vec::new(),
+        }
+    }
+}
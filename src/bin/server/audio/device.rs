@@ -1,3 +1,26 @@
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait};
+
+use dizi::error::{AppResult, DiziError, DiziErrorKind};
+use dizi::player::{AudioDevice, AudioDeviceConfig};
+
+use crate::audio::sink::{SharedSink, SinkKind};
+
+/// Where the server plays audio
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AudioSystem {
+    Host(cpal::HostId),
+    Sink(SinkKind),
+}
+
+/// Device the player stream plays on
+#[derive(Clone)]
+pub enum OutputDevice {
+    Cpal(cpal::Device),
+    Sink(SharedSink),
+}
+
 pub fn get_default_host(host_id: cpal::HostId) -> cpal::Host {
     tracing::debug!("Available audio systems:");
     for host in cpal::available_hosts() {
@@ -9,3 +32,186 @@ pub fn get_default_host(host_id: cpal::HostId) -> cpal::Host {
         .and_then(|id| cpal::host_from_id(id).ok())
         .unwrap_or_else(cpal::default_host)
 }
+
+/// Name used to select `device`, stable across restarts where the host allows it
+pub fn device_name(device: &cpal::Device) -> String {
+    device
+        .id()
+        .map(|id| id.id().to_string())
+        .unwrap_or_else(|_| device.to_string())
+}
+
+/// Every output device of `host` along with the formats it supports
+pub fn list_output_devices(host: &cpal::Host) -> AppResult<Vec<AudioDevice>> {
+    let default_name = host.default_output_device().map(|d| device_name(&d));
+    let devices = host
+        .output_devices()?
+        .map(|device| {
+            let name = device_name(&device);
+            let description = device
+                .description()
+                .map(|description| description.name().to_string())
+                .unwrap_or_else(|_| name.clone());
+            let configs = device
+                .supported_output_configs()
+                .map(|configs| {
+                    configs
+                        .map(|config| AudioDeviceConfig {
+                            channels: config.channels() as usize,
+                            min_sample_rate: config.min_sample_rate(),
+                            max_sample_rate: config.max_sample_rate(),
+                            sample_format: config.sample_format().to_string(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            AudioDevice {
+                default: default_name.as_ref() == Some(&name),
+                name,
+                description,
+                configs,
+            }
+        })
+        .collect();
+    Ok(devices)
+}
+
+/// The output device called `name`, or the default output device if `name` is `None`
+pub fn get_output_device(host: &cpal::Host, name: Option<&str>) -> AppResult<cpal::Device> {
+    match name {
+        Some(name) => host
+            .output_devices()?
+            .find(|device| device_name(device) == name || device.to_string() == name)
+            .ok_or_else(|| {
+                DiziError::new(
+                    DiziErrorKind::InvalidParameters,
+                    format!("Audio device '{name}' not found"),
+                )
+            }),
+        None => host.default_output_device().ok_or_else(|| {
+            let error_msg = "Failed to get default output device";
+            tracing::error!("{error_msg}");
+            DiziError::new(DiziErrorKind::Symphonia, error_msg.to_string())
+        }),
+    }
+}
+
+/// Playing this long without failing means the device has recovered
+const RECOVERED_AFTER: Duration = Duration::from_secs(10);
+/// Xruns are only glitches, but this many within a second means the device is in trouble
+const XRUN_STORM: usize = 20;
+
+/// How to get playback going again after the output device fails
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// times to try reopening the device before giving up
+    pub attempts: usize,
+    /// wait between attempts, the first one is made straight away
+    pub interval: Duration,
+}
+
+/// Failures of the output device in a row, for deciding when to give up on it
+#[derive(Debug, Default)]
+pub struct DeviceRecovery {
+    failures: usize,
+    last_failure: Option<Instant>,
+}
+
+impl DeviceRecovery {
+    /// Counts a failure at `now`, returning how long to wait before reopening the device,
+    /// or `None` once `policy` has run out of attempts
+    pub fn failed(&mut self, policy: &RetryPolicy, now: Instant) -> Option<Duration> {
+        let recovered = self
+            .last_failure
+            .is_some_and(|last| now.duration_since(last) > policy.interval + RECOVERED_AFTER);
+        if recovered {
+            self.failures = 0;
+        }
+        self.last_failure = Some(now);
+        self.failures += 1;
+        match self.failures {
+            n if n > policy.attempts => None,
+            1 => Some(Duration::ZERO),
+            _ => Some(policy.interval),
+        }
+    }
+
+    pub fn failures(&self) -> usize {
+        self.failures
+    }
+}
+
+/// Tells errors a stream can't carry on from apart from glitches
+#[derive(Debug)]
+pub struct StreamErrorFilter {
+    xruns: usize,
+    since: Instant,
+}
+
+impl StreamErrorFilter {
+    pub fn new() -> Self {
+        Self {
+            xruns: 0,
+            since: Instant::now(),
+        }
+    }
+
+    /// Whether an error of `kind` at `now` means the stream has to be rebuilt
+    pub fn is_fatal(&mut self, kind: cpal::ErrorKind, now: Instant) -> bool {
+        match kind {
+            cpal::ErrorKind::Xrun => {
+                if now.duration_since(self.since) > Duration::from_secs(1) {
+                    self.since = now;
+                    self.xruns = 0;
+                }
+                self.xruns += 1;
+                self.xruns >= XRUN_STORM
+            }
+            // the stream keeps playing, just not as well
+            cpal::ErrorKind::DeviceChanged | cpal::ErrorKind::RealtimeDenied => false,
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        attempts: 2,
+        interval: Duration::from_secs(2),
+    };
+
+    #[test]
+    fn retries_straight_away_then_waits_then_gives_up() {
+        let mut recovery = DeviceRecovery::default();
+        let now = Instant::now();
+        assert_eq!(recovery.failed(&POLICY, now), Some(Duration::ZERO));
+        assert_eq!(recovery.failed(&POLICY, now), Some(POLICY.interval));
+        assert_eq!(recovery.failed(&POLICY, now + POLICY.interval), None);
+    }
+
+    #[test]
+    fn failures_far_apart_start_over() {
+        let mut recovery = DeviceRecovery::default();
+        let now = Instant::now();
+        recovery.failed(&POLICY, now);
+        recovery.failed(&POLICY, now);
+        let later = now + Duration::from_secs(60);
+        assert_eq!(recovery.failed(&POLICY, later), Some(Duration::ZERO));
+        assert_eq!(recovery.failures(), 1);
+    }
+
+    #[test]
+    fn only_a_storm_of_xruns_is_fatal() {
+        let mut filter = StreamErrorFilter::new();
+        let now = Instant::now();
+        assert!(!filter.is_fatal(cpal::ErrorKind::DeviceChanged, now));
+        for _ in 1..XRUN_STORM {
+            assert!(!filter.is_fatal(cpal::ErrorKind::Xrun, now));
+        }
+        assert!(filter.is_fatal(cpal::ErrorKind::Xrun, now));
+        assert!(filter.is_fatal(cpal::ErrorKind::DeviceNotAvailable, now));
+    }
+}
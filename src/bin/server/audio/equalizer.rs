@@ -0,0 +1,164 @@
+use crate::audio::biquad::{Biquad, BiquadCoefficients};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EqFilter {
+    #[default]
+    Peaking,
+    LowShelf,
+    HighShelf,
+}
+
+/// A single filter of an equalizer preset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EqBand {
+    pub filter: EqFilter,
+    /// center frequency for peaking filters, corner frequency for shelves, in Hz
+    pub frequency: f32,
+    /// boost (or cut if negative) in dB
+    pub gain: f32,
+    pub q: f32,
+}
+
+impl EqBand {
+    /// Filter coefficients at `sample_rate`, `None` if the band can't be represented at that rate
+    fn coefficients(&self, sample_rate: u32) -> Option<BiquadCoefficients> {
+        let nyquist = sample_rate as f32 / 2.0;
+        if self.frequency <= 0.0 || self.frequency >= nyquist || self.q <= 0.0 {
+            return None;
+        }
+        let (frequency, q, gain, sample_rate) = (
+            self.frequency as f64,
+            self.q as f64,
+            self.gain as f64,
+            sample_rate as f64,
+        );
+        let coefficients = match self.filter {
+            EqFilter::Peaking => BiquadCoefficients::peaking(frequency, q, gain, sample_rate),
+            EqFilter::LowShelf => BiquadCoefficients::low_shelf(frequency, q, gain, sample_rate),
+            EqFilter::HighShelf => BiquadCoefficients::high_shelf(frequency, q, gain, sample_rate),
+        };
+        Some(coefficients)
+    }
+}
+
+/// A named set of bands, configured in `server.toml`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EqPreset {
+    pub name: String,
+    pub bands: Vec<EqBand>,
+}
+
+/// Runs interleaved samples through each band of a preset in turn
+#[derive(Clone, Debug)]
+pub struct Equalizer {
+    filters: Vec<Biquad>,
+}
+
+impl Equalizer {
+    /// Filter coefficients depend on the sample rate,
+    /// so a new equalizer must be made whenever the output sample rate changes
+    pub fn new(preset: &EqPreset, channels: usize, sample_rate: u32) -> Self {
+        let filters = preset
+            .bands
+            .iter()
+            .filter_map(|band| {
+                let coefficients = band.coefficients(sample_rate);
+                if coefficients.is_none() {
+                    tracing::warn!(?band, sample_rate, "Skipping equalizer band");
+                }
+                coefficients
+            })
+            .map(|coefficients| Biquad::new(coefficients, channels))
+            .collect();
+        Self { filters }
+    }
+
+    pub fn process_sample(&mut self, channel: usize, sample: f32) -> f32 {
+        self.filters
+            .iter_mut()
+            .fold(sample as f64, |x, filter| filter.process_sample(channel, x)) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    /// Peak amplitude of a sine at `frequency` after going through `preset`
+    fn response(preset: &EqPreset, frequency: f64) -> f64 {
+        let sample_rate = 48000;
+        let mut equalizer = Equalizer::new(preset, 1, sample_rate);
+        (0..sample_rate)
+            .map(|i| {
+                let x = (2.0 * PI * frequency * i as f64 / sample_rate as f64).sin();
+                equalizer.process_sample(0, x as f32).abs() as f64
+            })
+            // let the filters settle first
+            .skip(sample_rate as usize / 2)
+            .fold(0.0, f64::max)
+    }
+
+    fn preset(bands: &[EqBand]) -> EqPreset {
+        EqPreset {
+            name: "test".to_string(),
+            bands: bands.to_vec(),
+        }
+    }
+
+    #[test]
+    fn peaking_boosts_center_frequency() {
+        let boost = preset(&[EqBand {
+            filter: EqFilter::Peaking,
+            frequency: 1000.0,
+            gain: 6.0,
+            q: 1.0,
+        }]);
+        let gain_db = 20.0 * response(&boost, 1000.0).log10();
+        assert!((gain_db - 6.0).abs() < 0.1, "{gain_db}");
+        // far away frequencies are left alone
+        let gain_db = 20.0 * response(&boost, 50.0).log10();
+        assert!(gain_db.abs() < 0.1, "{gain_db}");
+    }
+
+    #[test]
+    fn shelves_cut_their_side() {
+        let cut = preset(&[
+            EqBand {
+                filter: EqFilter::LowShelf,
+                frequency: 200.0,
+                gain: -12.0,
+                q: 0.707,
+            },
+            EqBand {
+                filter: EqFilter::HighShelf,
+                frequency: 8000.0,
+                gain: -12.0,
+                q: 0.707,
+            },
+        ]);
+        let low = 20.0 * response(&cut, 30.0).log10();
+        let mid = 20.0 * response(&cut, 1500.0).log10();
+        let high = 20.0 * response(&cut, 20000.0).log10();
+        assert!((low + 12.0).abs() < 0.5, "{low}");
+        assert!(mid.abs() < 0.5, "{mid}");
+        assert!((high + 12.0).abs() < 0.5, "{high}");
+    }
+
+    #[test]
+    fn skips_bands_above_nyquist() {
+        let band = EqBand {
+            filter: EqFilter::Peaking,
+            frequency: 30000.0,
+            gain: 6.0,
+            q: 1.0,
+        };
+        assert!(
+            Equalizer::new(&preset(&[band]), 2, 44100)
+                .filters
+                .is_empty()
+        );
+        assert_eq!(Equalizer::new(&preset(&[band]), 2, 96000).filters.len(), 1);
+    }
+}
@@ -0,0 +1,161 @@
+use std::f32::consts::FRAC_PI_2;
+use std::time::Duration;
+
+/// Shape of the gain ramps used when fading between two songs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    /// keeps the combined loudness constant throughout the fade
+    EqualPower,
+}
+
+impl FadeCurve {
+    /// Gains for the outgoing and incoming song at `t`,
+    /// where `t` goes from 0.0 (start of fade) to 1.0 (end of fade)
+    pub fn gains(&self, t: f32) -> (f32, f32) {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => (1.0 - t, t),
+            Self::EqualPower => ((t * FRAC_PI_2).cos(), (t * FRAC_PI_2).sin()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Crossfade {
+    pub duration: Duration,
+    pub curve: FadeCurve,
+}
+
+/// A slow fade to silence that keeps playing, like the sleep timer's
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FadeOut {
+    /// how far along the fade should be by now
+    pub gain: f32,
+    /// time left until it's silent
+    pub remaining: Duration,
+}
+
+impl FadeOut {
+    /// Ramp carrying on with the fade at `sample_rate`
+    pub fn ramp(&self, sample_rate: u32) -> Ramp {
+        let frames = self.remaining.as_secs_f64() * sample_rate as f64;
+        let gain = self.gain.clamp(0.0, 1.0);
+        // the ramp's step is set by how long it'd take from full volume
+        let full_frames = if gain > 0.0 {
+            (frames / gain as f64) as usize
+        } else {
+            0
+        };
+        let mut ramp = Ramp::new(full_frames, gain);
+        ramp.fade_to(0.0);
+        ramp
+    }
+}
+
+/// Gain moving in a straight line towards a target, one frame at a time,
+/// so pausing, resuming and jumping around the song don't click
+#[derive(Clone, Copy, Debug)]
+pub struct Ramp {
+    gain: f32,
+    target: f32,
+    step: f32,
+}
+
+impl Ramp {
+    /// A ramp starting at `gain` that takes `frames` frames
+    /// to go from silence to full volume
+    pub fn new(frames: usize, gain: f32) -> Self {
+        Self {
+            gain,
+            target: gain,
+            step: 1.0 / frames.max(1) as f32,
+        }
+    }
+
+    pub fn fade_to(&mut self, target: f32) {
+        self.target = target;
+    }
+
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.gain == self.target
+    }
+
+    /// Frames left until the ramp is silent, `None` if it isn't fading out
+    pub fn frames_to_silence(&self) -> Option<usize> {
+        if self.target != 0.0 {
+            return None;
+        }
+        Some((self.gain / self.step).ceil() as usize)
+    }
+
+    /// Moves on to the next frame
+    pub fn advance(&mut self) {
+        if self.gain < self.target {
+            self.gain = (self.gain + self.step).min(self.target);
+        } else if self.gain > self.target {
+            self.gain = (self.gain - self.step).max(self.target);
+        }
+    }
+
+    /// Applies the ramp to interleaved `samples`, moving it along as it goes
+    pub fn apply(&mut self, samples: &mut [f32], channels: usize) {
+        for frame in samples.chunks_mut(channels) {
+            frame.iter_mut().for_each(|sample| *sample *= self.gain);
+            self.advance();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramp_reaches_silence_after_its_frames() {
+        let mut ramp = Ramp::new(4, 1.0);
+        ramp.fade_to(0.0);
+        assert_eq!(ramp.frames_to_silence(), Some(4));
+
+        let mut samples = vec![1.0; 12];
+        ramp.apply(&mut samples, 2);
+        assert_eq!(
+            samples,
+            [
+                1.0, 1.0, 0.75, 0.75, 0.5, 0.5, 0.25, 0.25, 0.0, 0.0, 0.0, 0.0
+            ]
+        );
+        assert!(ramp.is_done());
+        assert_eq!(ramp.frames_to_silence(), Some(0));
+    }
+
+    #[test]
+    fn fade_out_is_silent_when_its_time_is_up() {
+        let fade_out = FadeOut {
+            gain: 0.5,
+            remaining: Duration::from_secs(2),
+        };
+        let ramp = fade_out.ramp(100);
+        assert_eq!(ramp.gain(), 0.5);
+        assert_eq!(ramp.frames_to_silence(), Some(200));
+    }
+
+    #[test]
+    fn ramp_turns_around_midway() {
+        let mut ramp = Ramp::new(4, 1.0);
+        ramp.fade_to(0.0);
+        ramp.advance();
+        ramp.advance();
+        ramp.fade_to(1.0);
+        assert_eq!(ramp.frames_to_silence(), None);
+        ramp.advance();
+        assert_eq!(ramp.gain(), 0.75);
+        ramp.advance();
+        assert!(ramp.is_done());
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use dizi::error::AppResult;
+
+use super::Loudness;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct LoudnessCacheEntry {
+    /// modification time of the file when it was scanned
+    pub modified: SystemTime,
+    pub loudness: Loudness,
+}
+
+/// Loudness scan results, saved to disk so files only need to be scanned once.
+///
+/// Entries are keyed by path and are ignored once the file has been modified.
+#[derive(Clone, Debug, Default)]
+pub struct LoudnessCache {
+    path: PathBuf,
+    entries: HashMap<PathBuf, LoudnessCacheEntry>,
+}
+
+impl LoudnessCache {
+    /// Loads the cache at `path`, starting from an empty cache if it can't be read
+    pub fn load(path: &Path) -> Self {
+        let entries = fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| serde_json::from_str(&contents).map_err(|err| err.to_string()))
+            .unwrap_or_else(|err| {
+                tracing::debug!(?path, err, "Failed to load loudness cache");
+                HashMap::new()
+            });
+        Self {
+            path: path.to_path_buf(),
+            entries,
+        }
+    }
+
+    pub fn save(&self) -> AppResult {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = serde_json::to_string(&self.entries)?;
+        fs::write(&self.path, contents)?;
+        Ok(())
+    }
+
+    /// Loudness of `file`, if it was scanned since it was last modified
+    pub fn get(&self, file: &Path) -> Option<Loudness> {
+        let entry = self.entries.get(file)?;
+        let modified = fs::metadata(file).and_then(|m| m.modified()).ok()?;
+        (entry.modified == modified).then_some(entry.loudness)
+    }
+
+    pub fn contains(&self, file: &Path) -> bool {
+        self.get(file).is_some()
+    }
+
+    pub fn insert(&mut self, file: PathBuf, entry: LoudnessCacheEntry) {
+        self.entries.insert(file, entry);
+    }
+}
@@ -0,0 +1,241 @@
+use std::f64::consts::PI;
+
+use crate::audio::biquad::{Biquad, BiquadCoefficients};
+
+/// Gating blocks are 400ms long and start every 100ms
+const BLOCK_STEPS: usize = 4;
+const STEPS_PER_SEC: u32 = 10;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Oversampling factor used to find the true peak
+const OVERSAMPLING: usize = 4;
+/// Input samples used for each interpolated sample
+const PEAK_TAPS: usize = 12;
+
+/// Measures integrated loudness and true peak as specified by ITU-R BS.1770 / EBU R128
+#[derive(Clone, Debug)]
+pub struct LoudnessMeter {
+    channels: usize,
+    weights: Vec<f64>,
+    shelf: Biquad,
+    high_pass: Biquad,
+
+    step_frames: usize,
+    // frames and per channel sum of squares of the current 100ms step
+    step_position: usize,
+    step_energy: Vec<f64>,
+    // weighted sum of squares of the last `BLOCK_STEPS` steps
+    recent_steps: Vec<f64>,
+    // mean square of every gating block so far
+    blocks: Vec<f64>,
+
+    peak_filter: Vec<[f64; PEAK_TAPS]>,
+    // last `PEAK_TAPS` input samples of each channel
+    peak_history: Vec<[f64; PEAK_TAPS]>,
+    true_peak: f64,
+}
+
+impl LoudnessMeter {
+    pub fn new(channels: usize, sample_rate: u32) -> Self {
+        let weights = (0..channels)
+            .map(|channel| match (channels, channel) {
+                // 5.1: LFE is ignored and surrounds are boosted
+                (6, 3) => 0.0,
+                (6, 4) | (6, 5) => 1.41,
+                _ => 1.0,
+            })
+            .collect();
+        let (shelf, high_pass) = k_weighting(sample_rate as f64);
+        Self {
+            channels,
+            weights,
+            shelf: Biquad::new(shelf, channels),
+            high_pass: Biquad::new(high_pass, channels),
+            step_frames: (sample_rate / STEPS_PER_SEC).max(1) as usize,
+            step_position: 0,
+            step_energy: vec![0.0; channels],
+            recent_steps: Vec::with_capacity(BLOCK_STEPS),
+            blocks: Vec::new(),
+            peak_filter: peak_filter(),
+            peak_history: vec![[0.0; PEAK_TAPS]; channels],
+            true_peak: 0.0,
+        }
+    }
+
+    /// Adds interleaved samples to the measurement
+    pub fn process(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                let x = *sample as f64;
+                self.track_peak(channel, x);
+
+                let y = self.shelf.process_sample(channel, x);
+                let y = self.high_pass.process_sample(channel, y);
+                self.step_energy[channel] += y * y;
+            }
+            self.step_position += 1;
+            if self.step_position == self.step_frames {
+                self.finish_step();
+            }
+        }
+    }
+
+    fn finish_step(&mut self) {
+        let energy = self
+            .step_energy
+            .iter()
+            .zip(&self.weights)
+            .map(|(energy, weight)| energy * weight)
+            .sum();
+        self.step_energy.iter_mut().for_each(|e| *e = 0.0);
+        self.step_position = 0;
+
+        if self.recent_steps.len() == BLOCK_STEPS {
+            self.recent_steps.remove(0);
+        }
+        self.recent_steps.push(energy);
+        if self.recent_steps.len() == BLOCK_STEPS {
+            let total: f64 = self.recent_steps.iter().sum();
+            self.blocks
+                .push(total / (BLOCK_STEPS * self.step_frames) as f64);
+        }
+    }
+
+    fn track_peak(&mut self, channel: usize, x: f64) {
+        let history = &mut self.peak_history[channel];
+        history.rotate_left(1);
+        history[PEAK_TAPS - 1] = x;
+        for phase in &self.peak_filter {
+            let y: f64 = phase.iter().zip(history.iter()).map(|(h, x)| h * x).sum();
+            self.true_peak = self.true_peak.max(y.abs());
+        }
+    }
+
+    /// Integrated loudness in LUFS, `None` if everything measured so far is below the absolute gate
+    pub fn integrated(&self) -> Option<f64> {
+        let absolute_threshold = energy(ABSOLUTE_GATE);
+        let gated = gated_mean(&self.blocks, absolute_threshold)?;
+        let relative_threshold = energy(loudness(gated) + RELATIVE_GATE);
+        let gated = gated_mean(&self.blocks, absolute_threshold.max(relative_threshold))?;
+        Some(loudness(gated))
+    }
+
+    /// Highest sample value after oversampling, as a linear value where 1.0 is full scale
+    pub fn true_peak(&self) -> f64 {
+        self.true_peak
+    }
+}
+
+fn loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn energy(loudness: f64) -> f64 {
+    10.0_f64.powf((loudness + 0.691) / 10.0)
+}
+
+fn gated_mean(blocks: &[f64], threshold: f64) -> Option<f64> {
+    let (sum, count) = blocks
+        .iter()
+        .filter(|block| **block > threshold)
+        .fold((0.0, 0), |(sum, count), block| (sum + block, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// The two K-weighting stages (head shelf and high pass) for `sample_rate`
+fn k_weighting(sample_rate: f64) -> (BiquadCoefficients, BiquadCoefficients) {
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / sample_rate).tan();
+    let vh = 10.0_f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = BiquadCoefficients {
+        b0: (vh + vb * k / q + k * k) / a0,
+        b1: 2.0 * (k * k - vh) / a0,
+        b2: (vh - vb * k / q + k * k) / a0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0,
+    };
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / sample_rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = BiquadCoefficients {
+        b0: 1.0,
+        b1: -2.0,
+        b2: 1.0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0,
+    };
+    (shelf, high_pass)
+}
+
+/// Interpolation filters for each oversampled position between two input samples
+fn peak_filter() -> Vec<[f64; PEAK_TAPS]> {
+    let center = (PEAK_TAPS / 2 - 1) as f64;
+    (0..OVERSAMPLING)
+        .map(|phase| {
+            let offset = phase as f64 / OVERSAMPLING as f64;
+            let mut taps = [0.0; PEAK_TAPS];
+            for (k, tap) in taps.iter_mut().enumerate() {
+                let x = k as f64 - center - offset;
+                let window = 0.5 + 0.5 * (PI * x / (PEAK_TAPS / 2) as f64).cos();
+                let sinc = if x.abs() < 1e-9 {
+                    1.0
+                } else {
+                    (PI * x).sin() / (PI * x)
+                };
+                *tap = sinc * window;
+            }
+            let total: f64 = taps.iter().sum();
+            taps.iter_mut().for_each(|tap| *tap /= total);
+            taps
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stereo_sine(amplitude: f64, sample_rate: u32, seconds: u32) -> Vec<f32> {
+        (0..sample_rate * seconds)
+            .flat_map(|i| {
+                let s = amplitude * (2.0 * PI * 1000.0 * i as f64 / sample_rate as f64).sin();
+                [s as f32, s as f32]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sine_at_minus_23_dbfs_is_minus_23_lufs() {
+        for sample_rate in [44100, 48000] {
+            let mut meter = LoudnessMeter::new(2, sample_rate);
+            meter.process(&stereo_sine(10.0_f64.powf(-23.0 / 20.0), sample_rate, 5));
+            let integrated = meter.integrated().unwrap();
+            assert!((integrated + 23.0).abs() < 0.1, "{integrated}");
+        }
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        let mut meter = LoudnessMeter::new(2, 48000);
+        meter.process(&vec![0.0; 48000 * 2]);
+        assert_eq!(meter.integrated(), None);
+    }
+
+    #[test]
+    fn true_peak_of_sine() {
+        let mut meter = LoudnessMeter::new(2, 48000);
+        meter.process(&stereo_sine(0.5, 48000, 1));
+        assert!(
+            (meter.true_peak() - 0.5).abs() < 0.01,
+            "{}",
+            meter.true_peak()
+        );
+    }
+}
@@ -0,0 +1,14 @@
+pub mod cache;
+pub mod meter;
+pub mod scan;
+
+use serde::{Deserialize, Serialize};
+
+/// Loudness of a whole file, as measured by `meter::LoudnessMeter`
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Loudness {
+    /// integrated loudness in LUFS
+    pub integrated: f32,
+    /// true peak, where 1.0 is full scale
+    pub true_peak: f32,
+}
@@ -0,0 +1,98 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+use dizi::error::{AppResult, DiziError, DiziErrorKind};
+use dizi::song::{DiziAudioFile, DiziFile};
+
+use crate::audio::symphonia::decode::DecodeSource;
+use crate::events::{ServerEvent, ServerEventSender};
+use crate::util::mimetype::is_playable;
+
+use super::Loudness;
+use super::cache::{LoudnessCache, LoudnessCacheEntry};
+use super::meter::LoudnessMeter;
+
+/// Decodes all of `path` and measures its loudness
+pub fn scan_file(path: &Path) -> AppResult<LoudnessCacheEntry> {
+    let modified = fs::metadata(path)?.modified()?;
+    let audio_file = DiziAudioFile::try_from(DiziFile::new(path))?;
+    let mut source = DecodeSource::new(&audio_file, 1.0)?;
+
+    let mut meter: Option<LoudnessMeter> = None;
+    for packet in source.reader.by_ref() {
+        let (samples, spec) = source.decoder.decode::<f32>(packet)?;
+        if let Some(spec) = spec {
+            let meter =
+                meter.get_or_insert_with(|| LoudnessMeter::new(spec.channels, spec.sample_rate));
+            meter.process(&samples);
+        }
+    }
+
+    let loudness = meter.as_ref().and_then(|meter| {
+        Some(Loudness {
+            integrated: meter.integrated()? as f32,
+            true_peak: meter.true_peak() as f32,
+        })
+    });
+    match loudness {
+        Some(loudness) => Ok(LoudnessCacheEntry { modified, loudness }),
+        None => {
+            let error_msg = "File is too short or silent to measure loudness";
+            Err(DiziError::new(
+                DiziErrorKind::InvalidParameters,
+                error_msg.to_string(),
+            ))
+        }
+    }
+}
+
+/// Scans every playable file in `paths` that isn't in `cache` yet, in the background.
+///
+/// Directories are searched recursively. A `ServerEvent::LoudnessScanned`
+/// is sent after each file.
+pub fn spawn_scan(
+    paths: Vec<PathBuf>,
+    cache: LoudnessCache,
+    event_tx: ServerEventSender,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut files = Vec::new();
+        for path in paths.iter() {
+            find_files(&mut files, path);
+        }
+        // CUE tracks of one image all point at the same file, scan it once
+        let mut seen = HashSet::new();
+        files.retain(|file| seen.insert(file.clone()) && !cache.contains(file));
+
+        let total = files.len();
+        for (i, path) in files.into_iter().enumerate() {
+            tracing::debug!(?path, "Scanning loudness");
+            let result = scan_file(&path);
+            let event = ServerEvent::LoudnessScanned {
+                path,
+                result,
+                scanned: i + 1,
+                total,
+            };
+            if event_tx.send(event).is_err() {
+                return;
+            }
+        }
+    })
+}
+
+fn find_files(files: &mut Vec<PathBuf>, path: &Path) {
+    if path.is_dir() {
+        if let Ok(readdir) = fs::read_dir(path) {
+            let mut paths: Vec<PathBuf> = readdir.flatten().map(|entry| entry.path()).collect();
+            paths.sort();
+            for entry_path in paths.iter() {
+                find_files(files, entry_path);
+            }
+        }
+    } else if let Ok(true) = is_playable(path) {
+        files.push(path.to_path_buf());
+    }
+}
@@ -1,3 +1,17 @@
+pub mod ab_loop;
+pub mod biquad;
+pub mod convert;
 pub mod device;
+pub mod equalizer;
+pub mod fade;
+pub mod loudness;
+pub mod remix;
+pub mod replaygain;
 pub mod request;
+pub mod resample;
+pub mod sink;
+pub mod sleep;
+pub mod spectrum;
+pub mod speed;
 pub mod symphonia;
+pub mod volume;
@@ -0,0 +1,86 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+
+/// Converts interleaved samples from one channel count to another
+#[derive(Clone, Debug)]
+pub struct ChannelMixer {
+    in_channels: usize,
+    out_channels: usize,
+    // gain of each input channel for each output channel, `out_channels` rows of `in_channels`
+    matrix: Vec<f32>,
+}
+
+impl ChannelMixer {
+    pub fn new(in_channels: usize, out_channels: usize) -> Self {
+        Self {
+            in_channels,
+            out_channels,
+            matrix: mix_matrix(in_channels, out_channels),
+        }
+    }
+
+    pub fn process(&self, samples: &[f32]) -> Vec<f32> {
+        let frames = samples.len() / self.in_channels;
+        let mut output = Vec::with_capacity(frames * self.out_channels);
+        for frame in samples.chunks_exact(self.in_channels) {
+            for row in self.matrix.chunks_exact(self.in_channels) {
+                let sample = row.iter().zip(frame).map(|(gain, s)| gain * s).sum();
+                output.push(sample);
+            }
+        }
+        output
+    }
+}
+
+fn mix_matrix(in_channels: usize, out_channels: usize) -> Vec<f32> {
+    let mut matrix = vec![0.0; in_channels * out_channels];
+    let mut set = |out_channel: usize, in_channel: usize, gain: f32| {
+        matrix[out_channel * in_channels + in_channel] = gain;
+    };
+
+    match (in_channels, out_channels) {
+        // mono to stereo (and up): same signal on the front left and right
+        (1, _) => {
+            for out_channel in 0..out_channels.min(2) {
+                set(out_channel, 0, 1.0);
+            }
+        }
+        // everything down to mono
+        (_, 1) => {
+            for in_channel in 0..in_channels {
+                set(0, in_channel, 1.0 / in_channels as f32);
+            }
+        }
+        // 5.1 (FL, FR, FC, LFE, SL, SR) to stereo with ITU-R BS.775 coefficients,
+        // scaled down so a full scale signal on every channel doesn't clip.
+        // LFE is dropped.
+        (6, 2) => {
+            let scale = 1.0 / (1.0 + 2.0 * FRAC_1_SQRT_2);
+            set(0, 0, scale);
+            set(1, 1, scale);
+            set(0, 2, FRAC_1_SQRT_2 * scale);
+            set(1, 2, FRAC_1_SQRT_2 * scale);
+            set(0, 4, FRAC_1_SQRT_2 * scale);
+            set(1, 5, FRAC_1_SQRT_2 * scale);
+        }
+        // otherwise keep the channels both sides have and fold the rest
+        // into the output channels round robin
+        _ => {
+            for in_channel in 0..in_channels {
+                let out_channel = in_channel % out_channels;
+                let gain = if in_channel < out_channels {
+                    1.0
+                } else {
+                    FRAC_1_SQRT_2
+                };
+                set(out_channel, in_channel, gain);
+            }
+            for row in matrix.chunks_exact_mut(in_channels) {
+                let total: f32 = row.iter().sum();
+                if total > 1.0 {
+                    row.iter_mut().for_each(|gain| *gain /= total);
+                }
+            }
+        }
+    }
+    matrix
+}
@@ -0,0 +1,147 @@
+use dizi::song::MusicMetadata;
+
+use crate::audio::loudness::Loudness;
+
+/// Loudness that ReplayGain normalizes to, in LUFS
+const REFERENCE_LOUDNESS: f32 = -18.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+}
+
+/// Normalizes loudness between songs using their ReplayGain tags
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReplayGain {
+    pub mode: ReplayGainMode,
+    /// extra gain in dB applied on top of the tagged gain
+    pub preamp: f32,
+    /// lower the gain if the song would clip according to its tagged peak
+    pub prevent_clipping: bool,
+}
+
+impl ReplayGain {
+    /// Gain in dB to apply to a song, if it has the tags for it.
+    /// Falls back to the other mode's tags when the preferred ones are missing.
+    pub fn gain_db(&self, metadata: &MusicMetadata) -> Option<f32> {
+        let tags = &metadata.standard_tags;
+        let track = (
+            tags.get("ReplayGainTrackGain"),
+            tags.get("ReplayGainTrackPeak"),
+        );
+        let album = (
+            tags.get("ReplayGainAlbumGain"),
+            tags.get("ReplayGainAlbumPeak"),
+        );
+        let (gain, peak) = match self.mode {
+            ReplayGainMode::Off => return None,
+            ReplayGainMode::Track if track.0.is_some() => track,
+            ReplayGainMode::Track => album,
+            ReplayGainMode::Album if album.0.is_some() => album,
+            ReplayGainMode::Album => track,
+        };
+        let gain = parse_gain(gain?)?;
+        let peak = peak.and_then(|peak| peak.trim().parse::<f32>().ok());
+        Some(self.apply(gain, peak))
+    }
+
+    /// Gain in dB to apply to a song with a measured `loudness`, for songs without tags
+    pub fn loudness_gain_db(&self, loudness: Loudness) -> Option<f32> {
+        match self.mode {
+            ReplayGainMode::Off => None,
+            _ => Some(self.apply(
+                REFERENCE_LOUDNESS - loudness.integrated,
+                Some(loudness.true_peak),
+            )),
+        }
+    }
+
+    /// Adds the preamp to `gain` and caps it so `peak` stays at or below full scale
+    pub fn apply(&self, gain: f32, peak: Option<f32>) -> f32 {
+        let gain = gain + self.preamp;
+        match peak {
+            Some(peak) if self.prevent_clipping && peak > 0.0 => gain.min(-20.0 * peak.log10()),
+            _ => gain,
+        }
+    }
+}
+
+pub fn db_to_linear(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+/// Parses gain tags such as `-6.54 dB`
+fn parse_gain(s: &str) -> Option<f32> {
+    let s = s.trim();
+    let s = s
+        .strip_suffix("dB")
+        .or_else(|| s.strip_suffix("db"))
+        .or_else(|| s.strip_suffix("DB"))
+        .unwrap_or(s);
+    s.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(tags: &[(&str, &str)]) -> MusicMetadata {
+        MusicMetadata {
+            standard_tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_gain_tags() {
+        assert_eq!(parse_gain("-6.54 dB"), Some(-6.54));
+        assert_eq!(parse_gain("+2.10 dB"), Some(2.1));
+        assert_eq!(parse_gain("3"), Some(3.0));
+        assert_eq!(parse_gain("loud"), None);
+    }
+
+    #[test]
+    fn falls_back_to_other_mode() {
+        let replaygain = ReplayGain {
+            mode: ReplayGainMode::Album,
+            ..Default::default()
+        };
+        let song = metadata(&[("ReplayGainTrackGain", "-3.00 dB")]);
+        assert_eq!(replaygain.gain_db(&song), Some(-3.0));
+    }
+
+    #[test]
+    fn prevents_clipping() {
+        let replaygain = ReplayGain {
+            mode: ReplayGainMode::Track,
+            preamp: 6.0,
+            prevent_clipping: true,
+        };
+        // a peak of 0.5 leaves about 6.02 dB of headroom
+        let song = metadata(&[
+            ("ReplayGainTrackGain", "+3.00 dB"),
+            ("ReplayGainTrackPeak", "0.5"),
+        ]);
+        let gain = replaygain.gain_db(&song).unwrap();
+        assert!((gain - 6.0206).abs() < 1e-3, "{gain}");
+    }
+
+    #[test]
+    fn gain_from_measured_loudness() {
+        let replaygain = ReplayGain {
+            mode: ReplayGainMode::Track,
+            ..Default::default()
+        };
+        let loudness = Loudness {
+            integrated: -9.5,
+            true_peak: 0.9,
+        };
+        assert_eq!(replaygain.loudness_gain_db(loudness), Some(-8.5));
+    }
+}
@@ -2,12 +2,30 @@ use std::time::Duration;
 
 use dizi::song::DiziAudioFile;
 
+use crate::audio::ab_loop::{AbLoop, LoopPoint};
+use crate::audio::equalizer::EqPreset;
+use crate::audio::fade::FadeOut;
+use crate::audio::speed::Speed;
+
 /// User requests to the player
 #[derive(Clone, Debug)]
 pub enum PlayerRequest {
     Play {
         song: Box<DiziAudioFile>,
         volume: f32,
+        // linear gain applied to the song on top of the volume
+        gain: f32,
+        // where in the song to start
+        position: Duration,
+        // open the stream without starting playback
+        paused: bool,
+        ab_loop: AbLoop,
+    },
+    /// Song to play once the current one ends
+    Enqueue {
+        song: Box<DiziAudioFile>,
+        crossfade: bool,
+        gain: f32,
     },
     Pause,
     Resume,
@@ -15,10 +33,38 @@ pub enum PlayerRequest {
     SetVolume {
         volume: f32,
     },
-    FastForward {
-        offset: Duration,
+    /// Slowly fades towards silence without pausing, for the sleep timer
+    FadeOut(FadeOut),
+    /// Undoes `FadeOut`
+    FadeIn,
+    /// `None` turns the equalizer off
+    SetEqualizer {
+        preset: Option<EqPreset>,
+    },
+    Seek {
+        position: Duration,
+    },
+    /// negative offsets seek backward
+    SeekBy {
+        offset_ms: i64,
+    },
+    SetSpeed {
+        speed: Speed,
+    },
+    /// Moves a loop point to `position`, or to where playback is if `None`
+    SetLoopPoint {
+        point: LoopPoint,
+        position: Option<Duration>,
+    },
+    ClearLoop,
+    /// Continue with another audio track of the song being played
+    SwitchTrack {
+        song: Box<DiziAudioFile>,
+        gain: f32,
     },
-    Rewind {
-        offset: Duration,
+    /// Play on `device` from now on, stopping the current stream
+    SetDevice {
+        device: Box<cpal::Device>,
+        stream_config: cpal::SupportedStreamConfig,
     },
 }
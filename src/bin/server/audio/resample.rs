@@ -0,0 +1,198 @@
+use std::f64::consts::PI;
+
+/// Number of input frames used on each side of an output frame
+const HALF_TAPS: usize = 32;
+/// Number of precomputed filter phases between two input frames,
+/// in-between phases are linearly interpolated
+const PHASES: usize = 256;
+/// Low-pass cutoff relative to the lower of the two Nyquist frequencies,
+/// leaves room for the filter's transition band
+const CUTOFF: f64 = 0.95;
+
+/// Windowed sinc sample rate converter for interleaved samples.
+///
+/// Output frame `n` lines up with input time `n * in_rate / out_rate`,
+/// so the filter adds no delay to the stream.
+#[derive(Clone, Debug)]
+pub struct Resampler {
+    channels: usize,
+    in_rate: u32,
+    out_rate: u32,
+    // input frames per output frame
+    step: f64,
+    // `PHASES + 1` rows of `2 * HALF_TAPS` coefficients
+    table: Vec<f32>,
+    // input frames still needed by upcoming output frames
+    history: Vec<f32>,
+    // position of the next output frame, in frames from the start of `history`
+    position: f64,
+    frames_in: u64,
+    frames_out: u64,
+}
+
+impl Resampler {
+    pub fn new(channels: usize, in_rate: u32, out_rate: u32) -> Self {
+        let mut resampler = Self {
+            channels,
+            in_rate,
+            out_rate,
+            step: in_rate as f64 / out_rate as f64,
+            table: filter_table(in_rate, out_rate),
+            history: Vec::new(),
+            position: 0.0,
+            frames_in: 0,
+            frames_out: 0,
+        };
+        resampler.reset();
+        resampler
+    }
+
+    /// Forgets all previous input, must be called when the input is discontinuous
+    pub fn reset(&mut self) {
+        // pad the front with silence so the first output frame has a full window
+        self.history = vec![0.0; (HALF_TAPS - 1) * self.channels];
+        self.position = (HALF_TAPS - 1) as f64;
+        self.frames_in = 0;
+        self.frames_out = 0;
+    }
+
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.history.extend_from_slice(samples);
+        self.frames_in += (samples.len() / self.channels) as u64;
+
+        let mut output = Vec::with_capacity(
+            ((samples.len() / self.channels) as f64 / self.step) as usize * self.channels
+                + self.channels,
+        );
+        let history_frames = self.history.len() / self.channels;
+        while (self.position as usize) + HALF_TAPS < history_frames {
+            self.push_frame(&mut output);
+        }
+
+        // drop input frames no upcoming output frame needs
+        let consumed = (self.position as usize).saturating_sub(HALF_TAPS - 1);
+        self.history.drain(..consumed * self.channels);
+        self.position -= consumed as f64;
+        output
+    }
+
+    /// Returns the output for the remaining input, as if it were followed by silence
+    pub fn flush(&mut self) -> Vec<f32> {
+        let expected =
+            (self.frames_in as u128 * self.out_rate as u128).div_ceil(self.in_rate as u128) as u64;
+        let remaining = expected.saturating_sub(self.frames_out) as usize;
+
+        let frames_in = self.frames_in;
+        let mut output = self.process(&vec![0.0; HALF_TAPS * self.channels]);
+        output.truncate(remaining * self.channels);
+        self.frames_in = frames_in;
+        self.frames_out = expected;
+        output
+    }
+
+    fn push_frame(&mut self, output: &mut Vec<f32>) {
+        let index = self.position as usize;
+        let phase = (self.position - index as f64) * PHASES as f64;
+        let phase_index = (phase as usize).min(PHASES - 1);
+        let t = (phase - phase_index as f64) as f32;
+
+        let taps = 2 * HALF_TAPS;
+        let row0 = &self.table[phase_index * taps..(phase_index + 1) * taps];
+        let row1 = &self.table[(phase_index + 1) * taps..(phase_index + 2) * taps];
+
+        let first = (index + 1 - HALF_TAPS) * self.channels;
+        for channel in 0..self.channels {
+            let mut sample = 0.0;
+            for (k, (c0, c1)) in row0.iter().zip(row1).enumerate() {
+                let coefficient = c0 + (c1 - c0) * t;
+                sample += coefficient * self.history[first + k * self.channels + channel];
+            }
+            output.push(sample);
+        }
+        self.position += self.step;
+        self.frames_out += 1;
+    }
+}
+
+fn filter_table(in_rate: u32, out_rate: u32) -> Vec<f32> {
+    // when downsampling, cut off below the output's Nyquist frequency to avoid aliasing
+    let cutoff = CUTOFF * (out_rate as f64 / in_rate as f64).min(1.0);
+    let taps = 2 * HALF_TAPS;
+
+    let mut table = Vec::with_capacity((PHASES + 1) * taps);
+    for phase in 0..=PHASES {
+        let frac = phase as f64 / PHASES as f64;
+        let row: Vec<f64> = (0..taps)
+            .map(|k| {
+                // distance of this tap from the output frame, in input frames
+                let x = k as f64 - (HALF_TAPS - 1) as f64 - frac;
+                cutoff * sinc(cutoff * x) * blackman(x / HALF_TAPS as f64)
+            })
+            .collect();
+        // normalize so a constant signal passes through unchanged
+        let total: f64 = row.iter().sum();
+        table.extend(row.iter().map(|c| (c / total) as f32));
+    }
+    table
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Blackman window over -1.0..=1.0
+fn blackman(x: f64) -> f64 {
+    if x.abs() > 1.0 {
+        return 0.0;
+    }
+    0.42 + 0.5 * (PI * x).cos() + 0.08 * (2.0 * PI * x).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_length_matches_rate_ratio() {
+        let mut resampler = Resampler::new(2, 44100, 48000);
+        let mut frames = 0;
+        for _ in 0..10 {
+            frames += resampler.process(&vec![0.0; 4410 * 2]).len() / 2;
+        }
+        frames += resampler.flush().len() / 2;
+        assert_eq!(frames, 48000);
+    }
+
+    #[test]
+    fn constant_signal_is_preserved() {
+        let mut resampler = Resampler::new(1, 48000, 44100);
+        let output = resampler.process(&vec![0.5; 48000]);
+        // skip the frames affected by the silence the resampler starts with
+        for sample in &output[HALF_TAPS * 2..] {
+            assert!((sample - 0.5).abs() < 1e-4, "{sample}");
+        }
+    }
+
+    #[test]
+    fn sine_keeps_its_frequency_and_amplitude() {
+        let (in_rate, out_rate) = (44100, 48000);
+        let freq = 1000.0;
+        let input: Vec<f32> = (0..in_rate)
+            .map(|i| (2.0 * PI * freq * i as f64 / in_rate as f64).sin() as f32)
+            .collect();
+
+        let mut resampler = Resampler::new(1, in_rate, out_rate);
+        let output = resampler.process(&input);
+        for (i, sample) in output.iter().enumerate().skip(HALF_TAPS * 2) {
+            let expected = (2.0 * PI * freq * i as f64 / out_rate as f64).sin() as f32;
+            assert!(
+                (sample - expected).abs() < 1e-2,
+                "{i}: {sample} != {expected}"
+            );
+        }
+    }
+}
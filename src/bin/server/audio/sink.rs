@@ -0,0 +1,218 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use nix::fcntl::OFlag;
+use nix::sys::stat::Mode;
+
+use dizi::error::{AppResult, DiziError, DiziErrorKind};
+
+/// Channel count audio is converted to for sinks
+pub const SINK_CHANNELS: u16 = 2;
+/// Sample rate audio is converted to for sinks, snapcast's default
+pub const SINK_SAMPLE_RATE: u32 = 48000;
+
+/// Outputs that don't go through an audio host, for running without a sound card
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SinkKind {
+    /// records everything played to a 16-bit WAV file
+    Wav(PathBuf),
+    /// writes raw interleaved 16-bit little endian PCM to a named pipe
+    Fifo(PathBuf),
+    /// discards audio
+    Null,
+}
+
+impl SinkKind {
+    /// Format every sink is written in
+    pub fn stream_config(&self) -> cpal::SupportedStreamConfig {
+        cpal::SupportedStreamConfig::new(
+            SINK_CHANNELS,
+            SINK_SAMPLE_RATE,
+            cpal::SupportedBufferSize::Unknown,
+            cpal::SampleFormat::I16,
+        )
+    }
+
+    pub fn open(&self) -> AppResult<Sink> {
+        let sink = match self {
+            Self::Wav(path) => Sink::Wav(WavWriter::create(path)?),
+            Self::Fifo(path) => Sink::Fifo(FifoWriter::new(path)?),
+            Self::Null => Sink::Null,
+        };
+        Ok(sink)
+    }
+}
+
+impl std::fmt::Display for SinkKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Wav(path) => write!(f, "wav:{}", path.display()),
+            Self::Fifo(path) => write!(f, "fifo:{}", path.display()),
+            Self::Null => f.write_str("null"),
+        }
+    }
+}
+
+/// An opened sink, shared by every stream the player opens
+pub type SharedSink = Arc<Mutex<Sink>>;
+
+pub enum Sink {
+    Wav(WavWriter),
+    Fifo(FifoWriter),
+    Null,
+}
+
+impl Sink {
+    pub fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        match self {
+            Self::Wav(writer) => writer.write(samples),
+            Self::Fifo(writer) => writer.write(samples),
+            Self::Null => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Debug for Sink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Wav(writer) => write!(f, "Wav({:?})", writer.path),
+            Self::Fifo(writer) => write!(f, "Fifo({:?})", writer.path),
+            Self::Null => f.write_str("Null"),
+        }
+    }
+}
+
+const WAV_HEADER_LEN: u32 = 44;
+
+/// Appends samples to a WAV file, keeping the header up to date
+/// so the file stays playable if the server is killed
+pub struct WavWriter {
+    path: PathBuf,
+    file: File,
+    data_len: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path) -> AppResult<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(path)?;
+        file.write_all(&wav_header(0))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            data_len: 0,
+        })
+    }
+
+    fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        self.file.write_all(&to_le_bytes(samples))?;
+        self.data_len = self.data_len.saturating_add(samples.len() as u32 * 2);
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&wav_header(self.data_len))?;
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
+
+/// Header of a 16-bit PCM WAV file with `data_len` bytes of samples
+fn wav_header(data_len: u32) -> Vec<u8> {
+    let block_align = SINK_CHANNELS * 2;
+    let mut header = Vec::with_capacity(WAV_HEADER_LEN as usize);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(WAV_HEADER_LEN - 8).saturating_add(data_len).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    // PCM
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&SINK_CHANNELS.to_le_bytes());
+    header.extend_from_slice(&SINK_SAMPLE_RATE.to_le_bytes());
+    header.extend_from_slice(&(SINK_SAMPLE_RATE * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+    header
+}
+
+/// Writes to a named pipe whenever something is reading from it.
+///
+/// Audio is dropped while there's no reader or the reader falls behind,
+/// so a missing reader never holds up playback.
+pub struct FifoWriter {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl FifoWriter {
+    pub fn new(path: &Path) -> AppResult<Self> {
+        match std::fs::metadata(path) {
+            Ok(metadata) if metadata.file_type().is_fifo() => {}
+            Ok(_) => {
+                return Err(DiziError::new(
+                    DiziErrorKind::InvalidParameters,
+                    format!("'{}' is not a fifo", path.display()),
+                ));
+            }
+            Err(_) => {
+                nix::unistd::mkfifo(path, Mode::from_bits_truncate(0o644)).map_err(|err| {
+                    DiziError::new(
+                        DiziErrorKind::IoError(io::ErrorKind::Other),
+                        format!("Failed to create fifo '{}': {err}", path.display()),
+                    )
+                })?;
+            }
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: None,
+        })
+    }
+
+    fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        if self.file.is_none() {
+            // fails until a reader opens the other end
+            self.file = OpenOptions::new()
+                .write(true)
+                .custom_flags(OFlag::O_NONBLOCK.bits())
+                .open(&self.path)
+                .ok();
+        }
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        match file.write_all(&to_le_bytes(samples)) {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {
+                // reader went away, wait for the next one
+                self.file = None;
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn to_le_bytes(samples: &[i16]) -> Vec<u8> {
+    samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wav_header_sizes() {
+        let header = wav_header(1000);
+        assert_eq!(header.len(), WAV_HEADER_LEN as usize);
+        assert_eq!(&header[4..8], &1036u32.to_le_bytes());
+        assert_eq!(&header[28..32], &(48000u32 * 4).to_le_bytes());
+        assert_eq!(&header[40..44], &1000u32.to_le_bytes());
+    }
+}
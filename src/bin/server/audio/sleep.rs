@@ -0,0 +1,101 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use dizi::player::SleepTimer;
+
+use crate::events::{ServerEvent, ServerEventSender};
+
+/// How often a running sleep timer reports the time left
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When the player is going to stop by itself
+#[derive(Debug)]
+pub enum Sleep {
+    /// stop at `deadline`, fading out on the way
+    Timer {
+        deadline: Instant,
+        // the ticker thread stops once this is dropped
+        _ticker: mpsc::Sender<()>,
+    },
+    /// stop once this many more songs have finished, counting the current one
+    Tracks { remaining: usize },
+}
+
+impl Sleep {
+    /// A timer going off after `duration`,
+    /// sending a `ServerEvent::SleepTick` every second until then
+    pub fn timer(duration: Duration, event_tx: ServerEventSender) -> Self {
+        let deadline = Instant::now() + duration;
+        let (ticker, stop_rx) = mpsc::channel();
+        thread::spawn(move || {
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                match stop_rx.recv_timeout(remaining.min(TICK_INTERVAL)) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    // replaced or cancelled
+                    _ => return,
+                }
+                if event_tx.send(ServerEvent::SleepTick).is_err() || Instant::now() >= deadline {
+                    return;
+                }
+            }
+        });
+        Self::Timer {
+            deadline,
+            _ticker: ticker,
+        }
+    }
+
+    /// Time left on the timer, `None` when counting songs
+    pub fn remaining_time(&self) -> Option<Duration> {
+        match self {
+            Self::Timer { deadline, .. } => {
+                Some(deadline.saturating_duration_since(Instant::now()))
+            }
+            Self::Tracks { .. } => None,
+        }
+    }
+
+    pub fn state(&self) -> SleepTimer {
+        match self {
+            Self::Timer { .. } => SleepTimer::Timer {
+                remaining: self.remaining_time().unwrap_or_default(),
+            },
+            Self::Tracks { remaining } => SleepTimer::Tracks {
+                remaining: *remaining,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timer_ticks_until_it_goes_off() {
+        let (event_tx, event_rx) = mpsc::channel();
+        let sleep = Sleep::timer(Duration::from_millis(50), event_tx);
+        assert!(matches!(
+            event_rx.recv_timeout(Duration::from_secs(1)),
+            Ok(ServerEvent::SleepTick)
+        ));
+        assert_eq!(sleep.remaining_time(), Some(Duration::ZERO));
+        // the ticker is done once the time is up
+        assert!(matches!(
+            event_rx.recv_timeout(Duration::from_secs(2)),
+            Err(mpsc::RecvTimeoutError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn dropping_the_timer_stops_the_ticks() {
+        let (event_tx, event_rx) = mpsc::channel();
+        drop(Sleep::timer(Duration::from_secs(60), event_tx));
+        assert!(matches!(
+            event_rx.recv_timeout(Duration::from_secs(2)),
+            Err(mpsc::RecvTimeoutError::Disconnected)
+        ));
+    }
+}
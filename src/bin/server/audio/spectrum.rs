@@ -0,0 +1,343 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use symphonia::core::dsp::complex::Complex;
+use symphonia::core::dsp::fft::Fft;
+
+use dizi::player::{SPECTRUM_FLOOR_DB, Spectrum};
+
+use crate::events::{ServerEvent, ServerEventSender};
+
+/// Frames analyzed for each spectrum, about 40ms at 48kHz
+pub const FFT_SIZE: usize = 2048;
+/// Range the bands are spread over
+const MIN_FREQ: f32 = 40.0;
+const MAX_FREQ: f32 = 16000.0;
+
+/// What was played since the last time it was read
+struct TapBuffer {
+    channels: usize,
+    sample_rate: u32,
+    // the last `FFT_SIZE` frames, interleaved
+    window: VecDeque<f32>,
+    new_frames: usize,
+    peak: Vec<f32>,
+    sum_squares: Vec<f64>,
+}
+
+/// The latest played samples and their levels
+pub struct TapSnapshot {
+    pub channels: usize,
+    pub sample_rate: u32,
+    pub window: Vec<f32>,
+    pub peak: Vec<f32>,
+    pub rms: Vec<f32>,
+}
+
+/// Samples going to the output device, written by the playback callback
+/// and read by the spectrum thread.
+///
+/// The callback never waits on the lock, samples are dropped instead,
+/// and nothing is written unless a client wants the spectrum.
+#[derive(Clone)]
+pub struct SpectrumTap {
+    buffer: Arc<Mutex<TapBuffer>>,
+    enabled: Arc<AtomicBool>,
+}
+
+impl SpectrumTap {
+    pub fn new() -> Self {
+        Self {
+            buffer: Arc::new(Mutex::new(TapBuffer {
+                channels: 1,
+                sample_rate: 0,
+                window: VecDeque::new(),
+                new_frames: 0,
+                peak: Vec::new(),
+                sum_squares: Vec::new(),
+            })),
+            enabled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Starts over for a stream of a different format
+    pub fn set_format(&self, channels: usize, sample_rate: u32) {
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.channels = channels.max(1);
+            buffer.sample_rate = sample_rate;
+            buffer.window = VecDeque::with_capacity(FFT_SIZE * buffer.channels);
+            buffer.new_frames = 0;
+            buffer.peak = vec![0.0; channels];
+            buffer.sum_squares = vec![0.0; channels];
+        }
+    }
+
+    /// Adds interleaved samples that were just played
+    pub fn write(&self, samples: &[f32]) {
+        let Ok(mut buffer) = self.buffer.try_lock() else {
+            return;
+        };
+        let channels = buffer.channels;
+        for frame in samples.chunks_exact(channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                buffer.peak[channel] = buffer.peak[channel].max(sample.abs());
+                buffer.sum_squares[channel] += (*sample as f64).powi(2);
+            }
+        }
+        // make room first, so the window never grows past what it was made with
+        let window_len = FFT_SIZE * channels;
+        let kept = &samples[samples.len().saturating_sub(window_len)..];
+        let excess = (buffer.window.len() + kept.len()).saturating_sub(window_len);
+        buffer.window.drain(..excess);
+        buffer.window.extend(kept);
+        buffer.new_frames += samples.len() / channels;
+    }
+
+    /// Takes what was played since the last call, `None` if nothing was
+    pub fn take(&self) -> Option<TapSnapshot> {
+        let mut buffer = self.buffer.lock().ok()?;
+        if buffer.new_frames == 0 {
+            return None;
+        }
+        let frames = buffer.new_frames as f64;
+        let rms = buffer
+            .sum_squares
+            .iter()
+            .map(|sum| (sum / frames).sqrt() as f32)
+            .collect();
+        let snapshot = TapSnapshot {
+            channels: buffer.channels,
+            sample_rate: buffer.sample_rate,
+            window: buffer.window.iter().copied().collect(),
+            peak: std::mem::take(&mut buffer.peak),
+            rms,
+        };
+        buffer.new_frames = 0;
+        buffer.peak = vec![0.0; snapshot.channels];
+        buffer.sum_squares.iter_mut().for_each(|sum| *sum = 0.0);
+        Some(snapshot)
+    }
+}
+
+impl std::fmt::Debug for SpectrumTap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpectrumTap")
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
+
+/// Turns played samples into a `Spectrum`
+pub struct SpectrumAnalyzer {
+    fft: Fft,
+    window: Vec<f32>,
+    bands: usize,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(bands: usize) -> Self {
+        // Hann window, to keep loud bands from leaking into their neighbours
+        let window = (0..FFT_SIZE)
+            .map(|i| {
+                let x = i as f32 / FFT_SIZE as f32;
+                0.5 - 0.5 * (2.0 * std::f32::consts::PI * x).cos()
+            })
+            .collect();
+        Self {
+            fft: Fft::new(FFT_SIZE),
+            window,
+            bands: bands.max(1),
+        }
+    }
+
+    pub fn analyze(&mut self, snapshot: &TapSnapshot) -> Spectrum {
+        Spectrum {
+            bands: self.bands(snapshot),
+            peak: snapshot.peak.iter().map(|level| to_db(*level)).collect(),
+            rms: snapshot.rms.iter().map(|level| to_db(*level)).collect(),
+        }
+    }
+
+    /// Spectrum of everything being silent
+    pub fn silence(&self, channels: usize) -> Spectrum {
+        Spectrum {
+            bands: vec![SPECTRUM_FLOOR_DB; self.bands],
+            peak: vec![SPECTRUM_FLOOR_DB; channels],
+            rms: vec![SPECTRUM_FLOOR_DB; channels],
+        }
+    }
+
+    fn bands(&mut self, snapshot: &TapSnapshot) -> Vec<f32> {
+        let channels = snapshot.channels;
+        // mix down to mono, padding with silence right after a stream starts
+        let mut input = vec![Complex::default(); FFT_SIZE];
+        let start = FFT_SIZE - (snapshot.window.len() / channels).min(FFT_SIZE);
+        for (i, frame) in snapshot.window.chunks_exact(channels).enumerate() {
+            let Some(slot) = input.get_mut(start + i) else {
+                break;
+            };
+            let mono = frame.iter().sum::<f32>() / channels as f32;
+            slot.re = mono * self.window[start + i];
+        }
+        let mut output = vec![Complex::default(); FFT_SIZE];
+        self.fft.fft(&input, &mut output);
+
+        // a full scale sine comes out at FFT_SIZE / 4 with the Hann window
+        let scale = 4.0 / FFT_SIZE as f32;
+        let magnitudes: Vec<f32> = output[..FFT_SIZE / 2]
+            .iter()
+            .map(|bin| (bin.re * bin.re + bin.im * bin.im).sqrt() * scale)
+            .collect();
+
+        let bin_width = snapshot.sample_rate as f32 / FFT_SIZE as f32;
+        let max_freq = MAX_FREQ.min(snapshot.sample_rate as f32 / 2.0);
+        let ratio = (max_freq / MIN_FREQ).powf(1.0 / self.bands as f32);
+        (0..self.bands)
+            .map(|band| {
+                let low = MIN_FREQ * ratio.powi(band as i32);
+                let high = low * ratio;
+                let low_bin = (low / bin_width).round() as usize;
+                // low bands can be narrower than a bin, so take at least one
+                let high_bin = ((high / bin_width).round() as usize).max(low_bin + 1);
+                let level = magnitudes
+                    .get(low_bin..high_bin.min(magnitudes.len()))
+                    .and_then(|bins| bins.iter().copied().reduce(f32::max))
+                    .unwrap_or_default();
+                to_db(level)
+            })
+            .collect()
+    }
+}
+
+fn to_db(level: f32) -> f32 {
+    if level <= 0.0 {
+        return SPECTRUM_FLOOR_DB;
+    }
+    (20.0 * level.log10()).max(SPECTRUM_FLOOR_DB)
+}
+
+/// Analyzes what `tap` picked up `rate` times a second while it's enabled,
+/// sending a `ServerEvent::PlayerSpectrum` each time
+pub fn spawn_spectrum_thread(
+    tap: SpectrumTap,
+    rate: u32,
+    bands: usize,
+    event_tx: ServerEventSender,
+) -> JoinHandle<()> {
+    let interval = Duration::from_secs_f64(1.0 / rate.max(1) as f64);
+    thread::spawn(move || {
+        let mut analyzer = SpectrumAnalyzer::new(bands);
+        // channel count of the last spectrum, if it wasn't silent
+        let mut sounding: Option<usize> = None;
+        loop {
+            thread::sleep(interval);
+            if !tap.is_enabled() {
+                continue;
+            }
+            let spectrum = match tap.take() {
+                Some(snapshot) => {
+                    sounding = Some(snapshot.channels);
+                    analyzer.analyze(&snapshot)
+                }
+                // paused or stopped, let visualizers fall back to nothing once
+                None => match sounding.take() {
+                    Some(channels) => analyzer.silence(channels),
+                    None => continue,
+                },
+            };
+            if event_tx
+                .send(ServerEvent::PlayerSpectrum(spectrum))
+                .is_err()
+            {
+                return;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    fn sine_snapshot(freq: f32, amplitude: f32) -> TapSnapshot {
+        let tap = SpectrumTap::new();
+        tap.set_format(2, 48000);
+        let samples: Vec<f32> = (0..FFT_SIZE * 2)
+            .flat_map(|i| {
+                let sample = amplitude * (2.0 * PI * freq * i as f32 / 48000.0).sin();
+                [sample, sample]
+            })
+            .collect();
+        tap.write(&samples);
+        tap.take().unwrap()
+    }
+
+    #[test]
+    fn window_keeps_the_latest_frames_without_growing() {
+        let tap = SpectrumTap::new();
+        tap.set_format(2, 48000);
+        let capacity = tap.buffer.lock().unwrap().window.capacity();
+
+        let samples: Vec<f32> = (0..FFT_SIZE * 6).map(|i| i as f32).collect();
+        tap.write(&samples[..10]);
+        tap.write(&samples);
+        tap.write(&samples[..10]);
+
+        let buffer = tap.buffer.lock().unwrap();
+        assert_eq!(buffer.window.capacity(), capacity);
+        assert_eq!(buffer.window.len(), FFT_SIZE * 2);
+        assert_eq!(buffer.window.back(), Some(&9.0));
+        assert_eq!(buffer.window[0], (FFT_SIZE * 4 + 10) as f32);
+    }
+
+    #[test]
+    fn loudest_band_holds_the_tone() {
+        let mut analyzer = SpectrumAnalyzer::new(16);
+        let spectrum = analyzer.analyze(&sine_snapshot(1000.0, 1.0));
+        let (loudest, level) = spectrum
+            .bands
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+
+        let ratio = (MAX_FREQ / MIN_FREQ).powf(1.0 / 16.0);
+        let low = MIN_FREQ * ratio.powi(loudest as i32);
+        assert!(low <= 1000.0 && 1000.0 < low * ratio, "{low}");
+        assert!(*level > -1.0 && *level < 0.5, "{level}");
+        assert!(spectrum.bands[0] < -60.0, "{}", spectrum.bands[0]);
+    }
+
+    #[test]
+    fn levels_of_a_sine() {
+        let mut analyzer = SpectrumAnalyzer::new(16);
+        let spectrum = analyzer.analyze(&sine_snapshot(1000.0, 0.5));
+        for (peak, rms) in spectrum.peak.iter().zip(spectrum.rms.iter()) {
+            assert!((peak + 6.02).abs() < 0.1, "{peak}");
+            assert!((rms + 9.03).abs() < 0.1, "{rms}");
+        }
+    }
+
+    #[test]
+    fn nothing_to_take_until_more_is_played() {
+        let tap = SpectrumTap::new();
+        tap.set_format(2, 48000);
+        assert!(tap.take().is_none());
+        tap.write(&[0.5; 64]);
+        assert!(tap.take().is_some());
+        assert!(tap.take().is_none());
+    }
+}
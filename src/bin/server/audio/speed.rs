@@ -0,0 +1,279 @@
+use crate::audio::resample::Resampler;
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+
+/// Length of each segment that gets overlapped, in seconds
+const SEGMENT_SECS: f64 = 0.04;
+/// How far from its nominal position a segment may be taken from, in seconds
+const SEARCH_SECS: f64 = 0.012;
+/// Every `SEARCH_STRIDE`th offset and sample is compared in the coarse search
+const SEARCH_STRIDE: usize = 4;
+
+/// How playback speed is changed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpeedMode {
+    /// keeps the pitch the same
+    #[default]
+    Stretch,
+    /// plays faster or slower like a tape, changing the pitch along with it
+    Resample,
+}
+
+/// Playback speed and how to get there
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Speed {
+    pub value: f32,
+    pub mode: SpeedMode,
+}
+
+impl Speed {
+    /// Changes samples of the output format to this speed, `None` at normal speed
+    pub fn changer(&self, channels: usize, sample_rate: u32) -> Option<SpeedChanger> {
+        if self.value == 1.0 {
+            return None;
+        }
+        let changer = match self.mode {
+            SpeedMode::Stretch => {
+                SpeedChanger::Stretch(TimeStretch::new(channels, sample_rate, self.value))
+            }
+            SpeedMode::Resample => {
+                // rates only matter as a ratio
+                let percent = (self.value * 100.0).round() as u32;
+                SpeedChanger::Resample(Resampler::new(channels, percent, 100))
+            }
+        };
+        Some(changer)
+    }
+}
+
+impl std::default::Default for Speed {
+    fn default() -> Self {
+        Self {
+            value: 1.0,
+            mode: SpeedMode::default(),
+        }
+    }
+}
+
+pub enum SpeedChanger {
+    Stretch(TimeStretch),
+    Resample(Resampler),
+}
+
+impl SpeedChanger {
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        match self {
+            Self::Stretch(stretch) => stretch.process(samples),
+            Self::Resample(resampler) => resampler.process(samples),
+        }
+    }
+
+    /// Returns whatever is still buffered once the input has ended
+    pub fn flush(&mut self) -> Vec<f32> {
+        match self {
+            Self::Stretch(stretch) => stretch.flush(),
+            Self::Resample(resampler) => resampler.flush(),
+        }
+    }
+}
+
+/// Changes tempo without changing pitch using WSOLA (waveform similarity overlap-add).
+///
+/// Output is built from Hann windowed segments overlapping by half.
+/// Segments are read from the input `speed` times further apart than they are written,
+/// each shifted by up to `search` frames to line up best with the previous one.
+pub struct TimeStretch {
+    channels: usize,
+    speed: f64,
+    segment: usize,
+    hop: usize,
+    search: usize,
+    window: Vec<f32>,
+    // interleaved input not yet consumed, and its frames summed to mono for comparing
+    input: Vec<f32>,
+    mono: Vec<f32>,
+    // nominal start of the next segment, in frames from the start of `input`
+    position: f64,
+    // where the previous segment's second half continues in the input,
+    // in frames from the start of `input`
+    continuation: Option<usize>,
+    // second half of the previous windowed segment, added to the next one
+    overlap: Vec<f32>,
+    frames_in: u64,
+    frames_out: u64,
+}
+
+impl TimeStretch {
+    pub fn new(channels: usize, sample_rate: u32, speed: f32) -> Self {
+        let hop = ((SEGMENT_SECS * sample_rate as f64) as usize / 2).max(1);
+        let segment = hop * 2;
+        let window = (0..segment)
+            .map(|i| {
+                let x = i as f64 / segment as f64;
+                (0.5 - 0.5 * (2.0 * std::f64::consts::PI * x).cos()) as f32
+            })
+            .collect();
+        Self {
+            channels,
+            speed: speed as f64,
+            segment,
+            hop,
+            search: (SEARCH_SECS * sample_rate as f64) as usize,
+            window,
+            input: Vec::new(),
+            mono: Vec::new(),
+            position: 0.0,
+            continuation: None,
+            overlap: vec![0.0; hop * channels],
+            frames_in: 0,
+            frames_out: 0,
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.input.extend_from_slice(samples);
+        self.mono.extend(
+            samples
+                .chunks_exact(self.channels)
+                .map(|f| f.iter().sum::<f32>()),
+        );
+        self.frames_in += (samples.len() / self.channels) as u64;
+
+        let mut output = Vec::new();
+        loop {
+            let nominal = self.position.round() as usize;
+            if nominal + self.search + self.segment > self.mono.len() {
+                break;
+            }
+            let start = self.best_start(nominal);
+            self.push_segment(start, &mut output);
+            self.continuation = Some(start + self.hop);
+            self.position += self.hop as f64 * self.speed;
+        }
+
+        // drop input frames no upcoming segment needs
+        let needed = (self.position as usize).saturating_sub(self.search);
+        let consumed = match self.continuation {
+            Some(continuation) => needed.min(continuation),
+            None => needed,
+        };
+        self.input.drain(..consumed * self.channels);
+        self.mono.drain(..consumed);
+        self.position -= consumed as f64;
+        self.continuation = self
+            .continuation
+            .map(|continuation| continuation - consumed);
+        output
+    }
+
+    /// Returns the output for the remaining input, as if it were followed by silence
+    pub fn flush(&mut self) -> Vec<f32> {
+        let expected = (self.frames_in as f64 / self.speed).round() as u64;
+        let remaining = expected.saturating_sub(self.frames_out) as usize;
+
+        let frames_in = self.frames_in;
+        let padding = (self.segment + self.search) * 2 * (self.speed.ceil() as usize);
+        let mut output = self.process(&vec![0.0; padding * self.channels]);
+        output.truncate(remaining * self.channels);
+        self.frames_in = frames_in;
+        self.frames_out = expected;
+        output
+    }
+
+    /// Start of the segment around `nominal` that best continues the previous segment
+    fn best_start(&self, nominal: usize) -> usize {
+        let Some(continuation) = self.continuation else {
+            return nominal;
+        };
+        // what would have followed the previous segment without skipping any input
+        let target = &self.mono[continuation..continuation + self.hop];
+        let low = nominal.saturating_sub(self.search);
+        let high = nominal + self.search;
+
+        let best = |starts: &mut dyn Iterator<Item = usize>, stride: usize| {
+            starts
+                .map(|start| (start, similarity(target, &self.mono[start..], stride)))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(start, _)| start)
+        };
+        let coarse =
+            best(&mut (low..=high).step_by(SEARCH_STRIDE), SEARCH_STRIDE).unwrap_or(nominal);
+        let fine_low = coarse.saturating_sub(SEARCH_STRIDE).max(low);
+        let fine_high = (coarse + SEARCH_STRIDE).min(high);
+        best(&mut (fine_low..=fine_high), 1).unwrap_or(coarse)
+    }
+
+    fn push_segment(&mut self, start: usize, output: &mut Vec<f32>) {
+        let channels = self.channels;
+        let segment = &self.input[start * channels..(start + self.segment) * channels];
+        for (i, frame) in segment.chunks_exact(channels).enumerate() {
+            let gain = self.window[i];
+            for (channel, sample) in frame.iter().enumerate() {
+                let windowed = sample * gain;
+                if i < self.hop {
+                    output.push(self.overlap[i * channels + channel] + windowed);
+                } else {
+                    self.overlap[(i - self.hop) * channels + channel] = windowed;
+                }
+            }
+        }
+        self.frames_out += self.hop as u64;
+    }
+}
+
+/// Normalized cross-correlation of `target` with the start of `candidate`
+fn similarity(target: &[f32], candidate: &[f32], stride: usize) -> f32 {
+    let (mut dot, mut energy) = (0.0, 0.0);
+    for (t, c) in target.iter().zip(candidate).step_by(stride) {
+        dot += t * c;
+        energy += c * c;
+    }
+    dot / (energy + 1e-9).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    fn sine(freq: f64, sample_rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| (2.0 * PI * freq * i as f64 / sample_rate as f64).sin() as f32)
+            .collect()
+    }
+
+    fn rising_zero_crossings(samples: &[f32]) -> usize {
+        samples
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count()
+    }
+
+    #[test]
+    fn output_length_follows_speed() {
+        for speed in [0.5, 1.5, 3.0] {
+            let mut stretch = TimeStretch::new(2, 48000, speed);
+            let mut frames = 0;
+            for _ in 0..10 {
+                frames += stretch.process(&vec![0.1; 4800 * 2]).len() / 2;
+            }
+            frames += stretch.flush().len() / 2;
+            assert_eq!(frames, (48000.0 / speed as f64).round() as usize, "{speed}");
+        }
+    }
+
+    #[test]
+    fn stretching_keeps_the_pitch() {
+        let sample_rate = 48000;
+        let mut stretch = TimeStretch::new(1, sample_rate, 2.0);
+        let input = sine(440.0, sample_rate, sample_rate as usize * 2);
+        let mut output = stretch.process(&input);
+        output.extend(stretch.flush());
+        // one second of output, from two seconds of input, still has 440 cycles
+        assert_eq!(output.len(), sample_rate as usize);
+        let cycles = rising_zero_crossings(&output);
+        assert!((cycles as i64 - 440).abs() <= 5, "{cycles}");
+    }
+}
@@ -1,12 +1,32 @@
+use std::collections::VecDeque;
 use std::iter::Iterator;
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::time::Duration;
 
 use symphonia::core::audio::conv::ConvertibleSample;
-use symphonia::core::codecs::audio::AudioDecoder;
+use symphonia::core::codecs::CodecParameters;
+use symphonia::core::codecs::audio::{AudioDecoder, AudioDecoderOptions};
 use symphonia::core::errors::Error as SymphoniaError;
-use symphonia::core::formats::FormatReader;
+use symphonia::core::formats::{FormatReader, SeekMode, SeekTo};
 use symphonia::core::packet::Packet;
+use symphonia::core::units::Time;
 
-use dizi::error::{AppResult, DiziError};
+use dizi::error::{AppResult, DiziError, DiziErrorKind};
+use dizi::song::DiziAudioFile;
+
+use crate::audio::ab_loop::{AbLoop, LoopPoint};
+use crate::audio::convert::SampleConverter;
+use crate::audio::fade::{Crossfade, FadeCurve, Ramp};
+use crate::audio::speed::{Speed, SpeedChanger};
+use crate::audio::symphonia::stream::{SampleBuffer, StreamEvent, StreamOptions};
+
+/// How long to wait for the output stream to drain the buffer
+/// before checking for new requests again
+const BUFFER_FULL_WAIT: Duration = Duration::from_millis(50);
+
+/// How many held back frames to release at a time when there's nothing to crossfade into
+const TAIL_FLUSH_FRAMES: usize = 4096;
 
 pub struct PacketReader {
     format: Box<dyn FormatReader>,
@@ -17,6 +37,33 @@ impl PacketReader {
     pub fn new(format: Box<dyn FormatReader>, track_id: u32) -> Self {
         Self { format, track_id }
     }
+
+    /// Seeks to `time` and returns the position actually seeked to,
+    /// which is at or before `time`
+    pub fn seek(&mut self, time: Duration) -> AppResult<Duration> {
+        let time = Time::try_new(time.as_secs() as i64, time.subsec_nanos()).ok_or_else(|| {
+            let error_msg = "Invalid seek time";
+            DiziError::new(DiziErrorKind::InvalidParameters, error_msg.to_string())
+        })?;
+        let seeked_to = self.format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time,
+                track_id: Some(self.track_id),
+            },
+        )?;
+
+        let actual_time = self
+            .format
+            .tracks()
+            .iter()
+            .find(|track| track.id == self.track_id)
+            .and_then(|track| track.time_base)
+            .and_then(|time_base| time_base.calc_time(seeked_to.actual_ts))
+            .map(|time| Duration::from_secs_f64(time.as_secs_f64().max(0.0)))
+            .unwrap_or_default();
+        Ok(actual_time)
+    }
 }
 
 impl Iterator for PacketReader {
@@ -38,7 +85,12 @@ impl Iterator for PacketReader {
 /// The channel count and sample rate that symphonia actually produced when
 /// decoding, as opposed to what was reported by the (possibly incomplete)
 /// pre-decode probe metadata.
-#[derive(Clone, Copy, Debug)]
+///
+/// Some codecs/containers don't populate channel/sample-rate in their probed
+/// codec parameters until a packet is decoded. Converting with stale values
+/// misreads the interleaved samples (e.g. a mono file read as stereo plays
+/// back at 2x speed), so conversion is always set up from this.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct DecodedAudioSpec {
     pub channels: usize,
     pub sample_rate: u32,
@@ -53,6 +105,11 @@ impl PacketDecoder {
         Self { decoder }
     }
 
+    /// Must be called after seeking, as the next packet is discontinuous
+    pub fn reset(&mut self) {
+        self.decoder.reset();
+    }
+
     pub fn decode<T>(&mut self, packet: Packet) -> AppResult<(Vec<T>, Option<DecodedAudioSpec>)>
     where
         T: ConvertibleSample + cpal::Sample + Send + 'static,
@@ -82,30 +139,874 @@ impl PacketDecoder {
     }
 }
 
-/// Decodes every packet from `reader`, concatenating the resulting samples.
+/// A file that is ready to be decoded
+pub struct DecodeSource {
+    pub reader: PacketReader,
+    pub decoder: PacketDecoder,
+    pub total_duration: Option<Duration>,
+    /// where the song starts and ends in the file, for tracks of a CUE sheet
+    pub start: Duration,
+    pub end: Option<Duration>,
+    /// linear gain to normalize loudness with
+    pub gain: f32,
+}
+
+impl DecodeSource {
+    pub fn new(audio_file: &DiziAudioFile, gain: f32) -> AppResult<Self> {
+        let track_id = audio_file.audio_metadata.track_id;
+        let cue = audio_file.file.cue.as_ref();
+
+        let format_reader = audio_file.file.get_probe_result()?;
+
+        let codec_params = format_reader
+            .tracks()
+            .iter()
+            .find(|track| track.id == track_id)
+            .ok_or_else(|| {
+                let error_msg = "Audio track not found";
+                tracing::error!(?audio_file.file, track_id, "{error_msg}");
+                DiziError::new(DiziErrorKind::Symphonia, error_msg.to_string())
+            })?
+            .codec_params
+            .as_ref()
+            .ok_or_else(|| {
+                let error_msg = "Failed to get audio track codec_params";
+                tracing::error!("{error_msg}");
+                DiziError::new(DiziErrorKind::Symphonia, error_msg.to_string())
+            })?;
+
+        let audio_codec_params = match codec_params {
+            CodecParameters::Audio(params) => params,
+            _ => {
+                let error_msg = "Codec not audio";
+                tracing::error!("{error_msg}");
+                let err = DiziError::new(DiziErrorKind::ParseError, error_msg.to_string());
+                return Err(err);
+            }
+        };
+
+        // Use the default options for the decoder.
+        let dec_opts: AudioDecoderOptions = Default::default();
+
+        // Create a decoder for the track.
+        let decoder =
+            symphonia::default::get_codecs().make_audio_decoder(audio_codec_params, &dec_opts)?;
+
+        Ok(Self {
+            reader: PacketReader::new(format_reader, track_id),
+            decoder: PacketDecoder::new(decoder),
+            total_duration: audio_file.audio_metadata.total_duration,
+            start: cue.map(|cue| cue.start).unwrap_or_default(),
+            end: cue.and_then(|cue| cue.end),
+            gain,
+        })
+    }
+
+    /// Moves back to the start of the file
+    fn rewind(&mut self) -> AppResult {
+        self.reader.seek(Duration::ZERO)?;
+        self.decoder.reset();
+        Ok(())
+    }
+}
+
+/// Requests handled by the decoder thread
+pub enum DecoderRequest {
+    /// Seek to a position in the current song
+    Seek(Duration),
+    /// Seek forward by a number of milliseconds, or backward if negative
+    SeekBy(i64),
+    /// Continue with `source` once the current one runs out,
+    /// must have the same spec as the current source
+    Enqueue {
+        source: DecodeSource,
+        crossfade: bool,
+    },
+    /// Play at a different speed, starting with the next sample heard
+    SetSpeed(Speed),
+    /// Moves one end of the A–B loop to a position, or to the sample being played if `None`
+    SetLoopPoint(LoopPoint, Option<Duration>),
+    /// Replaces the A–B loop
+    SetLoop(AbLoop),
+    /// Continues the current song from another of its audio tracks, at the same position
+    SwitchTrack(DecodeSource),
+}
+
+/// What cutting decoded samples down to the part of the song being played did
+enum Cut {
+    Continue,
+    /// reached B and went back to A
+    Looped,
+    /// reached the end of the song, before the end of the file for CUE tracks
+    Ended,
+}
+
+/// A source waiting to be played after the current one
+struct QueuedSource {
+    source: DecodeSource,
+    // whether to crossfade into it
+    crossfade: bool,
+}
+
+/// Mixes the end of the previous song into the start of the current one
+struct Fade {
+    // samples of the previous song still to be mixed in
+    tail: VecDeque<f32>,
+    len: usize,
+    curve: FadeCurve,
+}
+
+impl Fade {
+    fn mix(&mut self, samples: &mut [f32], channels: usize) {
+        for frame in samples.chunks_mut(channels) {
+            let t = 1.0 - self.tail.len() as f32 / self.len as f32;
+            let (gain_out, gain_in) = self.curve.gains(t);
+            for sample in frame.iter_mut() {
+                let previous = self.tail.pop_front().unwrap_or_default();
+                *sample = previous * gain_out + *sample * gain_in;
+            }
+        }
+    }
+
+    /// Fades out whatever is left of the previous song,
+    /// for when the current song is shorter than the fade
+    fn finish(mut self, channels: usize) -> Vec<f32> {
+        let mut samples = vec![0.0; self.tail.len()];
+        self.mix(&mut samples, channels);
+        samples
+    }
+}
+
+/// Decodes packets on its own thread and feeds them into a `SampleBuffer`
+/// that the output stream drains, so only a small window of the file
+/// is ever held in memory. Samples are converted to `spec`, the format
+/// the output stream was opened with, on the way.
+///
+/// When a source is queued up, its samples are appended right after the
+/// current source's, so the output stream plays through without a gap.
+/// With crossfade enabled, the last few seconds of each source are held
+/// back and mixed with the start of the next one.
 ///
-/// Also returns the channel count and sample rate symphonia actually decoded
-/// the audio at, taken from the first non-empty decoded packet. This must be
-/// used (rather than pre-decode probe metadata) to configure playback,
-/// since some codecs/containers don't populate channel/sample-rate in their
-/// probed codec parameters until a packet is decoded; using stale or
-/// device-default values there causes the interleaved sample buffer to be
-/// misread (e.g. a mono file misread as stereo plays back at 2x speed).
-pub fn decode_all<T>(
-    reader: PacketReader,
-    mut decoder: PacketDecoder,
-) -> AppResult<(Vec<T>, Option<DecodedAudioSpec>)>
-where
-    T: ConvertibleSample + cpal::Sample + Send + 'static,
-{
-    let mut samples = Vec::new();
-    let mut spec = None;
-    for packet in reader {
-        let (packet_samples, packet_spec) = decoder.decode::<T>(packet)?;
-        if spec.is_none() {
-            spec = packet_spec;
-        }
-        samples.extend(packet_samples);
-    }
-    Ok((samples, spec))
+/// Away from normal speed, everything going into the buffer is sped up
+/// or slowed down last, so crossfades take the same share of each song.
+///
+/// An A–B loop is cut out of the converted samples, so playback jumps
+/// from B back to A without a gap at the output sample rate.
+/// Tracks of a CUE sheet are cut out of their file the same way,
+/// and positions are counted from the start of the track.
+///
+/// Seeking fades out the start of what was buffered and fades in
+/// from the new position, so jumps don't click.
+pub struct StreamDecoder {
+    source: DecodeSource,
+    spec: DecodedAudioSpec,
+    // set up once the first packet of `source` is decoded
+    converter: Option<SampleConverter>,
+    buffer: Arc<SampleBuffer<f32>>,
+    request_rx: mpsc::Receiver<DecoderRequest>,
+    stream_tx: mpsc::Sender<StreamEvent>,
+    crossfade: Option<Crossfade>,
+    // source to continue with once `source` runs out
+    next: Option<QueuedSource>,
+    // source whose tail is still in the buffer after moving on to the next one,
+    // kept around in case the user seeks before playback reaches the next source
+    previous: Option<DecodeSource>,
+    // whether `source` was crossfaded into
+    crossfaded: bool,
+    // most recently decoded samples, held back to be mixed with the next source
+    tail: VecDeque<f32>,
+    tail_len: usize,
+    fade: Option<Fade>,
+    // samples decoded but not yet accepted by the buffer
+    pending: Option<Vec<f32>>,
+    source_done: bool,
+    end_of_stream: bool,
+    speed: Speed,
+    speed_changer: Option<SpeedChanger>,
+    ab_loop: AbLoop,
+    // output frame the next converted sample of `source` belongs to
+    decoded_frames: usize,
+    // frames to drop after seeking, as seeks land at or before the requested position
+    skip_frames: usize,
+    // frames to fade over when jumping to another position
+    seek_fade: usize,
+    // fades in what follows a jump
+    fade_in: Option<Ramp>,
+}
+
+impl StreamDecoder {
+    pub fn new(
+        source: DecodeSource,
+        spec: DecodedAudioSpec,
+        buffer: Arc<SampleBuffer<f32>>,
+        request_rx: mpsc::Receiver<DecoderRequest>,
+        stream_tx: mpsc::Sender<StreamEvent>,
+        options: &StreamOptions,
+    ) -> Self {
+        let (crossfade, speed) = (options.crossfade, options.speed);
+        let tail_len = crossfade
+            .map(|c| (c.duration.as_secs_f64() * spec.sample_rate as f64) as usize * spec.channels)
+            .unwrap_or_default();
+        let mut decoder = Self {
+            source,
+            spec,
+            converter: None,
+            buffer,
+            request_rx,
+            stream_tx,
+            crossfade,
+            next: None,
+            previous: None,
+            crossfaded: false,
+            tail: VecDeque::with_capacity(tail_len),
+            tail_len,
+            fade: None,
+            pending: None,
+            source_done: false,
+            end_of_stream: false,
+            speed,
+            speed_changer: speed.changer(spec.channels, spec.sample_rate),
+            ab_loop: AbLoop::default(),
+            decoded_frames: 0,
+            skip_frames: 0,
+            seek_fade: (options.fade.as_secs_f64() * spec.sample_rate as f64) as usize,
+            fade_in: None,
+        };
+        decoder.start_source();
+        decoder
+    }
+
+    /// Keeps decoding until the stream that owns the request sender is dropped
+    pub fn run(mut self) {
+        loop {
+            if self.previous.is_some() && !self.buffer.has_pending_track_start() {
+                // playback has moved on to the current source
+                self.previous = None;
+            }
+            if self.end_of_stream {
+                // nothing left to decode, sleep until someone seeks or the stream is dropped
+                match self.request_rx.recv() {
+                    Ok(req) => self.process_request(req),
+                    Err(_) => return,
+                }
+                continue;
+            }
+
+            loop {
+                match self.request_rx.try_recv() {
+                    Ok(req) => self.process_request(req),
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => return,
+                }
+            }
+
+            match self.pending.take() {
+                Some(samples) => {
+                    if !self.buffer.try_push(&samples) {
+                        self.buffer.wait_for_space(samples.len(), BUFFER_FULL_WAIT);
+                        self.pending = Some(samples);
+                    }
+                }
+                None => {
+                    self.pending = self.next_samples().filter(|s| !s.is_empty());
+                    if let (Some(samples), Some(fade_in)) =
+                        (self.pending.as_mut(), self.fade_in.as_mut())
+                    {
+                        fade_in.apply(samples, self.spec.channels);
+                        if fade_in.is_done() {
+                            self.fade_in = None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the next samples to go into the buffer, if any
+    fn next_samples(&mut self) -> Option<Vec<f32>> {
+        let samples = self.next_media_samples();
+        let Some(changer) = self.speed_changer.as_mut() else {
+            if self.end_of_stream {
+                self.buffer.set_end_of_stream();
+            }
+            return samples;
+        };
+        if !self.end_of_stream {
+            return samples.map(|samples| changer.process(&samples));
+        }
+        let samples = changer.flush();
+        // start over clean in case another source gets queued up
+        self.speed_changer = self
+            .speed
+            .changer(self.spec.channels, self.spec.sample_rate);
+        if samples.is_empty() {
+            self.buffer.set_end_of_stream();
+            return None;
+        }
+        // play out what the changer still had before ending
+        self.end_of_stream = false;
+        Some(samples)
+    }
+
+    /// Returns the next samples at normal speed, if any
+    fn next_media_samples(&mut self) -> Option<Vec<f32>> {
+        if !self.source_done {
+            let packet = self.source.reader.next();
+            let (mut samples, file_ended) =
+                match packet.map(|packet| self.source.decoder.decode::<f32>(packet)) {
+                    Some(Ok((mut samples, Some(packet_spec)))) => {
+                        if self.source.gain != 1.0 {
+                            samples.iter_mut().for_each(|s| *s *= self.source.gain);
+                        }
+                        (self.convert(samples, packet_spec), false)
+                    }
+                    Some(Ok((_, None))) => return None,
+                    // end of the song, or unrecoverable and we play what we have
+                    Some(Err(_)) | None => {
+                        let samples = self
+                            .converter
+                            .take()
+                            .map(|mut converter| converter.flush())
+                            .unwrap_or_default();
+                        (samples, true)
+                    }
+                };
+            let ended = match self.cut(&mut samples) {
+                Cut::Looped => return Some(self.mix_in(&mut samples)),
+                Cut::Ended => true,
+                Cut::Continue => file_ended,
+            };
+            if !ended {
+                return Some(self.mix_in(&mut samples));
+            }
+            if let Some((a, b)) = self.loop_frames()
+                && self.decoded_frames < b
+            {
+                // B is past the end of the song, so loop at the end instead
+                let channels = self.spec.channels;
+                self.buffer
+                    .set_loop(Some((a * channels, self.decoded_frames * channels)));
+                self.loop_back(a);
+                return Some(self.mix_in(&mut samples));
+            }
+            let samples = self.mix_in(&mut samples);
+            self.source_finished();
+            return Some(samples);
+        }
+
+        match self.next.take() {
+            Some(next) if next.crossfade && !self.tail.is_empty() => {
+                let fade = self.crossfade.map(|crossfade| Fade {
+                    len: self.tail.len(),
+                    tail: std::mem::take(&mut self.tail),
+                    curve: crossfade.curve,
+                });
+                self.start_next_source(next, fade);
+                None
+            }
+            Some(next) if self.tail.is_empty() => {
+                self.start_next_source(next, None);
+                None
+            }
+            next => {
+                self.next = next;
+                if self.tail.is_empty() {
+                    self.end_of_stream = true;
+                    return None;
+                }
+                // play out what was held back while waiting for the next source
+                let count = self.tail.len().min(TAIL_FLUSH_FRAMES * self.spec.channels);
+                Some(self.tail.drain(..count).collect())
+            }
+        }
+    }
+
+    /// Converts samples decoded as `packet_spec` to the output spec
+    fn convert(&mut self, samples: Vec<f32>, packet_spec: DecodedAudioSpec) -> Vec<f32> {
+        let mut flushed = Vec::new();
+        let converter = match self.converter.take() {
+            Some(converter) if converter.input_spec() == packet_spec => converter,
+            Some(mut converter) => {
+                // spec changed mid stream
+                flushed = converter.flush();
+                SampleConverter::new(packet_spec, self.spec)
+            }
+            None => SampleConverter::new(packet_spec, self.spec),
+        };
+        let converter = self.converter.insert(converter);
+        flushed.extend(converter.process(samples));
+        flushed
+    }
+
+    /// Mixes in the previous source if fading and holds back the end for the next one
+    fn mix_in(&mut self, samples: &mut Vec<f32>) -> Vec<f32> {
+        if let Some(fade) = self.fade.as_mut() {
+            fade.mix(samples, self.spec.channels);
+            if fade.tail.is_empty() {
+                self.fade = None;
+            }
+        }
+        self.hold_back(std::mem::take(samples))
+    }
+
+    /// Holds back the last `tail_len` samples and returns the rest
+    fn hold_back(&mut self, samples: Vec<f32>) -> Vec<f32> {
+        if self.tail_len == 0 {
+            return samples;
+        }
+        self.tail.extend(samples);
+        let excess = self.tail.len().saturating_sub(self.tail_len);
+        self.tail.drain(..excess).collect()
+    }
+
+    fn source_finished(&mut self) {
+        self.source_done = true;
+        if let Some(fade) = self.fade.take() {
+            let samples = fade.finish(self.spec.channels);
+            self.tail.extend(samples);
+        }
+        if self.next.is_none() {
+            // give the server a chance to queue up the next song
+            // while the rest of the buffer plays out
+            let _ = self.stream_tx.send(StreamEvent::DecodeFinished);
+        }
+    }
+
+    fn start_next_source(&mut self, next: QueuedSource, fade: Option<Fade>) {
+        self.previous = Some(std::mem::replace(&mut self.source, next.source));
+        self.converter = None;
+        self.crossfaded = fade.is_some();
+        self.fade = fade;
+        self.source_done = false;
+        self.end_of_stream = false;
+        self.start_source();
+        if self.ab_loop != AbLoop::default() {
+            // loops belong to the song they were set in
+            self.ab_loop = AbLoop::default();
+            let _ = self.stream_tx.send(StreamEvent::LoopChanged(self.ab_loop));
+        }
+        self.buffer.mark_track_start();
+    }
+
+    /// Moves to the start of `source`, which is past the start of the file for CUE tracks
+    fn start_source(&mut self) {
+        self.decoded_frames = 0;
+        self.skip_frames = 0;
+        if self.source.start.is_zero() {
+            return;
+        }
+        match self.seek_source(0) {
+            Ok(skip_frames) => self.skip_frames = skip_frames,
+            Err(err) => tracing::error!(?err, "Failed to seek to the start of the track"),
+        }
+    }
+
+    fn process_request(&mut self, req: DecoderRequest) {
+        let target = match req {
+            DecoderRequest::Enqueue { source, crossfade } => {
+                self.next = Some(QueuedSource { source, crossfade });
+                // wake up and start on it
+                self.end_of_stream = false;
+                return;
+            }
+            DecoderRequest::Seek(position) => {
+                self.restore_previous();
+                position
+            }
+            DecoderRequest::SeekBy(offset_ms) => {
+                self.restore_previous();
+                let samples_per_sec = self.spec.sample_rate as usize * self.spec.channels;
+                if samples_per_sec == 0 {
+                    return;
+                }
+                let current_ms = self.buffer.position() as i64 * 1000 / samples_per_sec as i64;
+                Duration::from_millis((current_ms + offset_ms).max(0) as u64)
+            }
+            DecoderRequest::SetSpeed(speed) => {
+                self.speed = speed;
+                // throw away what was buffered at the old speed by seeking to where playback is
+                self.restore_previous();
+                let position = self.playback_position();
+                self.seek(position);
+                return;
+            }
+            DecoderRequest::SetLoopPoint(point, position) => {
+                let mut ab_loop = self.ab_loop;
+                ab_loop.set(point, position.unwrap_or_else(|| self.playback_position()));
+                self.set_loop(ab_loop);
+                return;
+            }
+            DecoderRequest::SetLoop(ab_loop) => {
+                self.set_loop(ab_loop);
+                return;
+            }
+            DecoderRequest::SwitchTrack(source) => {
+                self.restore_previous();
+                let position = self.playback_position();
+                self.source = source;
+                self.seek(position);
+                return;
+            }
+        };
+
+        let target = match self.source.total_duration {
+            // stop 1 second short of the end so the song doesn't immediately finish
+            Some(total) if target + Duration::from_secs(1) >= total => {
+                target.min(total.saturating_sub(Duration::from_secs(1)))
+            }
+            _ => target,
+        };
+        self.seek(target);
+    }
+
+    /// Goes back to the previous source if playback hasn't reached the current one yet,
+    /// so seeks apply to the song that's actually playing
+    fn restore_previous(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            let mut current = std::mem::replace(&mut self.source, previous);
+            match current.rewind() {
+                Ok(_) => {
+                    self.next = Some(QueuedSource {
+                        source: current,
+                        crossfade: self.crossfaded,
+                    })
+                }
+                Err(err) => tracing::error!(?err, "Failed to rewind queued song"),
+            }
+        }
+    }
+
+    /// Seeks `source` to `frame` of the song and returns how many frames to drop
+    /// to land on it exactly, as seeks land at or before the requested position
+    fn seek_source(&mut self, frame: usize) -> AppResult<usize> {
+        let frame = self.frame_at(self.source.start) + frame;
+        let target = Duration::from_secs_f64(frame as f64 / self.spec.sample_rate as f64);
+        let actual = self.source.reader.seek(target)?;
+        self.source.decoder.reset();
+        self.converter = None;
+        Ok(frame.saturating_sub(self.frame_at(actual)))
+    }
+
+    fn seek(&mut self, target: Duration) {
+        let frame = self.frame_at(target);
+        match self.seek_source(frame) {
+            Ok(skip_frames) => {
+                self.pending = None;
+                self.tail.clear();
+                self.fade = None;
+                self.source_done = false;
+                self.end_of_stream = false;
+
+                self.speed_changer = self
+                    .speed
+                    .changer(self.spec.channels, self.spec.sample_rate);
+
+                self.decoded_frames = frame;
+                self.skip_frames = skip_frames;
+                let channels = self.spec.channels;
+                self.buffer
+                    .reset_faded(frame * channels, self.seek_fade, channels);
+                if self.seek_fade > 0 {
+                    let mut fade_in = Ramp::new(self.seek_fade, 0.0);
+                    fade_in.fade_to(1.0);
+                    self.fade_in = Some(fade_in);
+                }
+                self.buffer.set_speed(self.speed.value as f64);
+                self.buffer.set_loop(
+                    self.loop_frames()
+                        .map(|(a, b)| (a * self.spec.channels, b * self.spec.channels)),
+                );
+            }
+            Err(err) => {
+                tracing::error!(?err, ?target, "Failed to seek");
+            }
+        }
+    }
+
+    fn frame_at(&self, time: Duration) -> usize {
+        (time.as_secs_f64() * self.spec.sample_rate as f64) as usize
+    }
+
+    /// Position of the sample being played
+    fn playback_position(&self) -> Duration {
+        let frame = self.buffer.position() / self.spec.channels.max(1);
+        Duration::from_secs_f64(frame as f64 / self.spec.sample_rate as f64)
+    }
+
+    /// Frame the song ends at, before the end of the file for CUE tracks
+    fn end_frame(&self) -> Option<usize> {
+        // counted the way seeks are, so the next track picks up on the following frame
+        self.source.end.map(|end| {
+            self.frame_at(end)
+                .saturating_sub(self.frame_at(self.source.start))
+        })
+    }
+
+    fn loop_frames(&self) -> Option<(usize, usize)> {
+        self.ab_loop
+            .range()
+            .map(|(a, b)| (self.frame_at(a), self.frame_at(b)))
+    }
+
+    fn set_loop(&mut self, ab_loop: AbLoop) {
+        let was_looping = self.ab_loop.range().is_some();
+        self.ab_loop = ab_loop;
+        let _ = self.stream_tx.send(StreamEvent::LoopChanged(ab_loop));
+        if !was_looping && self.previous.is_none() {
+            match self.loop_frames() {
+                None => return,
+                // nothing past B has been decoded yet, so the loop can start without a seek
+                Some((a, b)) if self.decoded_frames < b => {
+                    let channels = self.spec.channels;
+                    self.buffer.set_loop(Some((a * channels, b * channels)));
+                    return;
+                }
+                Some(_) => {}
+            }
+        }
+
+        // what's buffered may go past the new B or loop back to the old A,
+        // or belong to the next song, so decode again from where playback is
+        self.restore_previous();
+        let position = self.playback_position();
+        let target = match ab_loop.range() {
+            Some((a, b)) if position >= b => a,
+            _ => position,
+        };
+        self.seek(target);
+    }
+
+    /// Drops converted samples that come before a seek target and cuts them off
+    /// at B, going back to A, or at the end of the song
+    fn cut(&mut self, samples: &mut Vec<f32>) -> Cut {
+        let channels = self.spec.channels;
+        let skip = self.skip_frames.min(samples.len() / channels);
+        samples.drain(..skip * channels);
+        self.skip_frames -= skip;
+
+        let start = self.decoded_frames;
+        self.decoded_frames += samples.len() / channels;
+        let end = self.end_frame();
+        match self.loop_frames() {
+            Some((a, b))
+                if start < b && self.decoded_frames >= b && end.is_none_or(|end| b <= end) =>
+            {
+                samples.truncate((b - start) * channels);
+                self.loop_back(a);
+                Cut::Looped
+            }
+            _ => match end {
+                Some(end) if self.decoded_frames >= end => {
+                    samples.truncate(end.saturating_sub(start) * channels);
+                    self.decoded_frames = end;
+                    Cut::Ended
+                }
+                _ => Cut::Continue,
+            },
+        }
+    }
+
+    /// Continues decoding from frame `a` without touching what's buffered
+    fn loop_back(&mut self, a: usize) {
+        match self.seek_source(a) {
+            Ok(skip_frames) => {
+                self.decoded_frames = a;
+                self.skip_frames = skip_frames;
+            }
+            Err(err) => {
+                tracing::error!(?err, a, "Failed to loop back");
+                self.ab_loop = AbLoop::default();
+                self.buffer.set_loop(None);
+                let _ = self.stream_tx.send(StreamEvent::LoopChanged(self.ab_loop));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::OnceLock;
+    use std::thread;
+
+    use dizi::song::{DiziAudioFile, DiziFile};
+
+    use crate::audio::spectrum::SpectrumTap;
+
+    use super::*;
+
+    const SPEC: DecodedAudioSpec = DecodedAudioSpec {
+        channels: 2,
+        sample_rate: 48000,
+    };
+    /// frames in the test song, 2 seconds
+    const SONG_FRAMES: usize = SPEC.sample_rate as usize * 2;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    /// A 16 bit stereo WAV file whose every frame holds its own number,
+    /// so it can be told where it's from
+    fn numbered_song() -> DiziAudioFile {
+        // written once, tests run at the same time
+        static PATH: OnceLock<PathBuf> = OnceLock::new();
+        let path = PATH.get_or_init(|| {
+            let data: Vec<u8> = (0..SONG_FRAMES)
+                .flat_map(|frame| [(frame % 32768) as i16, (frame / 32768) as i16])
+                .flat_map(i16::to_le_bytes)
+                .collect();
+            let mut wav = Vec::new();
+            wav.extend(b"RIFF");
+            wav.extend((36 + data.len() as u32).to_le_bytes());
+            wav.extend(b"WAVEfmt ");
+            wav.extend(16u32.to_le_bytes());
+            // PCM, channels, sample rate, byte rate, block align, bits per sample
+            wav.extend(1u16.to_le_bytes());
+            wav.extend(2u16.to_le_bytes());
+            wav.extend(SPEC.sample_rate.to_le_bytes());
+            wav.extend((SPEC.sample_rate * 4).to_le_bytes());
+            wav.extend(4u16.to_le_bytes());
+            wav.extend(16u16.to_le_bytes());
+            wav.extend(b"data");
+            wav.extend((data.len() as u32).to_le_bytes());
+            wav.extend(data);
+
+            let dir = std::env::temp_dir().join(format!("dizi-test-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("numbered.wav");
+            std::fs::write(&path, wav).unwrap();
+            path
+        });
+        DiziAudioFile::try_from(DiziFile::new(path)).unwrap()
+    }
+
+    fn frame_number(frame: &[f32]) -> usize {
+        let left = (frame[0] * 32768.0).round() as usize;
+        let right = (frame[1] * 32768.0).round() as usize;
+        right * 32768 + left
+    }
+
+    /// Plays `source` the way the output stream does, with the decoder on its own thread,
+    /// after sending it `requests`.
+    /// Returns the numbers of the frames played until the end, or until there are `max` of them
+    fn play(source: DecodeSource, requests: Vec<DecoderRequest>, max: usize) -> Vec<usize> {
+        let buffer = Arc::new(SampleBuffer::new(
+            SPEC.sample_rate as usize / 10 * SPEC.channels,
+        ));
+        let (request_tx, request_rx) = mpsc::channel();
+        for request in requests {
+            request_tx.send(request).unwrap();
+        }
+        let (stream_tx, _) = mpsc::channel();
+        let options = StreamOptions {
+            volume: 1.0,
+            crossfade: None,
+            equalizer: None,
+            dither: false,
+            speed: Speed::default(),
+            fade: Duration::ZERO,
+            fade_out: None,
+            spectrum: SpectrumTap::new(),
+            progress_interval: Duration::from_secs(1),
+        };
+        let decoder = StreamDecoder::new(
+            source,
+            SPEC,
+            buffer.clone(),
+            request_rx,
+            stream_tx,
+            &options,
+        );
+        thread::spawn(move || decoder.run());
+
+        let mut frames = Vec::new();
+        let mut data = [0.0; 960];
+        while frames.len() < max {
+            let Some(played) = buffer.pop_into(&mut data, |s| s) else {
+                continue;
+            };
+            frames.extend(data[..played.written].chunks_exact(2).map(frame_number));
+            if played.finished {
+                break;
+            }
+            if played.written == 0 {
+                thread::sleep(ms(1));
+            }
+        }
+        frames.truncate(max);
+        frames
+    }
+
+    #[test]
+    fn plays_every_frame() {
+        let source = DecodeSource::new(&numbered_song(), 1.0).unwrap();
+        let frames = play(source, Vec::new(), usize::MAX);
+        assert!(frames.iter().copied().eq(0..SONG_FRAMES));
+    }
+
+    #[test]
+    fn cue_tracks_are_cut_out_of_their_file() {
+        let mut source = DecodeSource::new(&numbered_song(), 1.0).unwrap();
+        source.start = ms(500);
+        source.end = Some(ms(1500));
+        let frames = play(source, Vec::new(), usize::MAX);
+        assert!(frames.iter().copied().eq(24000..72000));
+    }
+
+    #[test]
+    fn seeks_land_on_the_exact_frame() {
+        let request = DecoderRequest::Seek(ms(250));
+        let source = DecodeSource::new(&numbered_song(), 1.0).unwrap();
+        let frames = play(source, vec![request], 100);
+        assert!(frames.iter().copied().eq(12000..12100));
+    }
+
+    #[test]
+    fn seeks_in_a_cue_track_count_from_its_start() {
+        let mut source = DecodeSource::new(&numbered_song(), 1.0).unwrap();
+        source.start = ms(500);
+        source.end = Some(ms(1500));
+        let request = DecoderRequest::Seek(ms(250));
+        let frames = play(source, vec![request], 100);
+        assert!(frames.iter().copied().eq(36000..36100));
+    }
+
+    #[test]
+    fn seeking_back_past_the_start_plays_from_the_start() {
+        let request = DecoderRequest::SeekBy(-500);
+        let source = DecodeSource::new(&numbered_song(), 1.0).unwrap();
+        let frames = play(source, vec![request], 100);
+        assert!(frames.iter().copied().eq(0..100));
+    }
+
+    #[test]
+    fn ab_loop_goes_back_to_a_at_b() {
+        let request = DecoderRequest::SetLoop(AbLoop {
+            a: Some(ms(100)),
+            b: Some(ms(200)),
+        });
+        let source = DecodeSource::new(&numbered_song(), 1.0).unwrap();
+        let frames = play(source, vec![request], 9600 + 4800 * 2);
+        assert!(frames[..9600].iter().copied().eq(0..9600));
+        assert!(frames[9600..14400].iter().copied().eq(4800..9600));
+        assert!(frames[14400..].iter().copied().eq(4800..9600));
+    }
+
+    #[test]
+    fn queued_song_follows_without_a_gap() {
+        let song = numbered_song();
+        let request = DecoderRequest::Enqueue {
+            source: DecodeSource::new(&song, 1.0).unwrap(),
+            crossfade: false,
+        };
+        let frames = play(
+            DecodeSource::new(&song, 1.0).unwrap(),
+            vec![request],
+            usize::MAX,
+        );
+        assert_eq!(frames.len(), SONG_FRAMES * 2);
+        assert!(frames[..SONG_FRAMES].iter().copied().eq(0..SONG_FRAMES));
+        assert!(frames[SONG_FRAMES..].iter().copied().eq(0..SONG_FRAMES));
+    }
 }
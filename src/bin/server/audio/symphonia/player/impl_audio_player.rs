@@ -2,18 +2,26 @@ use std::path;
 use std::time;
 
 use dizi::error::{AppResult, DiziError, DiziErrorKind};
-use dizi::player::{PlayerState, PlayerStatus};
+use dizi::player::{AudioDevice, AudioDeviceConfig, OutputFormat, PlayerState, PlayerStatus};
 use dizi::playlist::PlaylistType;
 use dizi::song::DiziAudioFile;
 use dizi::song::DiziSongEntry;
 
+use crate::audio::ab_loop::{AbLoop, LoopPoint};
+use crate::audio::device::{
+    AudioSystem, device_name, get_default_host, get_output_device, list_output_devices,
+};
+use crate::audio::fade::FadeOut;
+use crate::audio::replaygain::db_to_linear;
 use crate::audio::request::PlayerRequest;
+use crate::audio::sleep::Sleep;
+use crate::audio::speed::{MAX_SPEED, MIN_SPEED, Speed};
 use crate::context::PlaylistContext;
-use crate::playlist::DiziPlaylist;
+use crate::playlist::{DiziPlaylist, cue};
 use crate::traits::{AudioPlayer, DiziPlaylistEntry, DiziPlaylistTrait};
 use crate::util::mimetype::{get_mimetype, is_mimetype_audio, is_mimetype_video};
 
-use super::SymphoniaPlayer;
+use super::{SymphoniaPlayer, output_config, output_format};
 
 impl SymphoniaPlayer {
     /// Plays `entry` if it is loaded. `entry` is taken by value (rather than
@@ -27,6 +35,62 @@ impl SymphoniaPlayer {
         }
         Ok(())
     }
+
+    fn preload_entry(&mut self, entry: Option<DiziPlaylistEntry>) -> AppResult {
+        if let Some(mut entry) = entry {
+            let audio_file = entry.entry.clone().load_metadata()?;
+            tracing::debug!(song = ?audio_file, "Preloading song");
+
+            // keep albums meant to be heard without breaks intact
+            let same_album = self.current_song_ref().is_some_and(|song| {
+                song.music_metadata.album().is_some()
+                    && song.music_metadata.album() == audio_file.music_metadata.album()
+            });
+            let crossfade = !(self.album_gapless && same_album);
+
+            let replaygain = self.replaygain_db(&audio_file);
+            self.player_stream_req().send(PlayerRequest::Enqueue {
+                song: Box::new(audio_file.clone()),
+                crossfade,
+                gain: db_to_linear(replaygain.unwrap_or_default()),
+            })?;
+            self.player_stream_res().recv()??;
+
+            entry.entry = DiziSongEntry::Loaded(audio_file);
+            self.preloaded = Some(entry);
+        }
+        Ok(())
+    }
+}
+
+impl SymphoniaPlayer {
+    /// Plays on `device` from now on, stopping the current stream
+    fn switch_device(&mut self, device: cpal::Device) -> AppResult<OutputFormat> {
+        let name = device_name(&device);
+        let stream_config = output_config(&device)?;
+        let output_format = output_format(&stream_config);
+
+        self.player_stream_req().send(PlayerRequest::SetDevice {
+            device: Box::new(device),
+            stream_config,
+        })?;
+        self.player_stream_res().recv()??;
+
+        self.state.audio_device = Some(name);
+        self.state.output_format = Some(output_format.clone());
+        Ok(output_format)
+    }
+
+    /// Opens a new stream for the current song, carrying on from where it was
+    fn replay(&mut self) -> AppResult {
+        let status = self.state.status;
+        if let Some(song) = self.state.song.clone()
+            && status != PlayerStatus::Stopped
+        {
+            self.play_from(&song, self.state.elapsed, status == PlayerStatus::Paused)?;
+        }
+        Ok(())
+    }
 }
 
 impl AudioPlayer for SymphoniaPlayer {
@@ -34,29 +98,41 @@ impl AudioPlayer for SymphoniaPlayer {
         let mut state = self.state.clone();
         state.playlist = self.playlist_context.file_playlist.to_file_playlist();
         state.playlist_status = self.playlist_context.current_playlist_type;
+        state.sleep = self.sleep.as_ref().map(Sleep::state);
         state
     }
 
     fn play_directory(&mut self, path: &path::Path) -> AppResult {
-        let mimetype = get_mimetype(path)?;
-        if !is_mimetype_audio(&mimetype) && !is_mimetype_video(&mimetype) {
-            return Err(DiziError::new(
-                DiziErrorKind::NotAudioFile,
-                format!("File mimetype is not of type audio: '{}'", mimetype),
-            ));
+        if !cue::is_cue_sheet(path) {
+            let mimetype = get_mimetype(path)?;
+            if !is_mimetype_audio(&mimetype) && !is_mimetype_video(&mimetype) {
+                return Err(DiziError::new(
+                    DiziErrorKind::NotAudioFile,
+                    format!("File mimetype is not of type audio: '{}'", mimetype),
+                ));
+            }
         }
 
         let shuffle_enabled = self.shuffle_enabled();
         if let Some(parent) = path.parent() {
             let mut playlist = DiziPlaylist::from_dir(parent)?;
             // find the song we're playing in the playlist and set playing index
-            // equal to the playing song
+            // equal to the playing song, or the first track of a CUE sheet
             let index = playlist
                 .contents
                 .iter()
                 .enumerate()
-                .find(|(_, p)| p.file_path() == path)
+                .find(|(_, p)| {
+                    p.file_path() == path
+                        || p.file().cue.as_ref().is_some_and(|cue| cue.sheet == path)
+                })
                 .map(|(i, _)| i);
+            if index.is_none() && cue::is_cue_sheet(path) {
+                return Err(DiziError::new(
+                    DiziErrorKind::NotAudioFile,
+                    format!("No playable tracks in CUE sheet '{}'", path.display()),
+                ));
+            }
 
             if shuffle_enabled {
                 playlist.unshuffle();
@@ -150,6 +226,45 @@ impl AudioPlayer for SymphoniaPlayer {
         self.play_entry(entry)
     }
 
+    fn preload_next(&mut self) -> AppResult {
+        let entry = self.playlist_context.next_song_peak();
+        self.preload_entry(entry)
+    }
+
+    fn preload_again(&mut self) -> AppResult {
+        let entry = self.playlist_context.current_song();
+        self.preload_entry(entry)
+    }
+
+    fn play_preloaded(&mut self) -> AppResult {
+        let entry = self.preloaded.take().ok_or_else(|| {
+            DiziError::new(DiziErrorKind::Server, "No song preloaded".to_string())
+        })?;
+        if let DiziSongEntry::Loaded(audio_file) = entry.entry {
+            // the playlist may have been reordered since the song was preloaded,
+            // so look it up again by its index
+            let playlist = self.playlist_context.current_playlist_mut();
+            let same_song = playlist
+                .contents
+                .get(entry.entry_index)
+                .is_some_and(|e| e.file_path() == audio_file.file.file_path);
+            if same_song {
+                playlist.contents[entry.entry_index] = DiziSongEntry::Loaded(audio_file.clone());
+                playlist.order_index = playlist
+                    .order
+                    .iter()
+                    .position(|index| *index == entry.entry_index);
+            }
+
+            self.state.replaygain = self.replaygain_db(&audio_file);
+            self.state.status = PlayerStatus::Playing;
+            self.state.loop_a = None;
+            self.state.loop_b = None;
+            self.state.song = Some(audio_file);
+        }
+        Ok(())
+    }
+
     fn pause(&mut self) -> AppResult {
         self.player_stream_req().send(PlayerRequest::Pause)?;
 
@@ -170,6 +285,7 @@ impl AudioPlayer for SymphoniaPlayer {
         self.player_stream_req().send(PlayerRequest::Stop)?;
 
         self.player_stream_res().recv()??;
+        self.preloaded = None;
         self.state.status = PlayerStatus::Stopped;
         Ok(())
     }
@@ -187,14 +303,46 @@ impl AudioPlayer for SymphoniaPlayer {
             s => Ok(s),
         }
     }
-    fn fast_forward(&mut self, offset: time::Duration) -> AppResult {
+    fn seek(&mut self, position: time::Duration) -> AppResult {
+        self.player_stream_req()
+            .send(PlayerRequest::Seek { position })?;
+        Ok(())
+    }
+    fn seek_by(&mut self, offset_ms: i64) -> AppResult {
         self.player_stream_req()
-            .send(PlayerRequest::FastForward { offset })?;
+            .send(PlayerRequest::SeekBy { offset_ms })?;
+        Ok(())
+    }
+
+    fn set_loop_point(&mut self, point: LoopPoint, position: Option<time::Duration>) -> AppResult {
+        self.player_stream_req()
+            .send(PlayerRequest::SetLoopPoint { point, position })?;
+
+        self.player_stream_res().recv()??;
+        Ok(())
+    }
+    fn clear_loop(&mut self) -> AppResult {
+        self.player_stream_req().send(PlayerRequest::ClearLoop)?;
+
+        self.player_stream_res().recv()??;
         Ok(())
     }
-    fn rewind(&mut self, offset: time::Duration) -> AppResult {
+    fn set_ab_loop(&mut self, ab_loop: AbLoop) {
+        self.state.loop_a = ab_loop.a;
+        self.state.loop_b = ab_loop.b;
+    }
+
+    fn fade_out(&mut self, fade_out: FadeOut) -> AppResult {
         self.player_stream_req()
-            .send(PlayerRequest::Rewind { offset })?;
+            .send(PlayerRequest::FadeOut(fade_out))?;
+
+        self.player_stream_res().recv()??;
+        Ok(())
+    }
+    fn fade_in(&mut self) -> AppResult {
+        self.player_stream_req().send(PlayerRequest::FadeIn)?;
+
+        self.player_stream_res().recv()??;
         Ok(())
     }
 
@@ -203,13 +351,140 @@ impl AudioPlayer for SymphoniaPlayer {
     }
     fn set_volume(&mut self, volume: usize) -> AppResult {
         self.player_stream_req().send(PlayerRequest::SetVolume {
-            volume: volume as f32 / 100.0,
+            volume: self.volume_curve.gain(volume),
         })?;
 
         self.player_stream_res().recv()??;
         self.state.volume = volume;
         Ok(())
     }
+    fn select_track(&mut self, track_id: u32) -> AppResult {
+        let mut song = self.state.song.clone().ok_or_else(|| {
+            DiziError::new(
+                DiziErrorKind::InvalidParameters,
+                "No song is playing".to_string(),
+            )
+        })?;
+        song.audio_metadata.select_track(track_id)?;
+
+        let replaygain = self.replaygain_db(&song);
+        self.player_stream_req().send(PlayerRequest::SwitchTrack {
+            song: Box::new(song.clone()),
+            gain: db_to_linear(replaygain.unwrap_or_default()),
+        })?;
+
+        self.player_stream_res().recv()??;
+        self.state.song = Some(song);
+        Ok(())
+    }
+    fn get_speed(&self) -> f32 {
+        self.state.speed
+    }
+    fn set_speed(&mut self, speed: f32) -> AppResult {
+        if !speed.is_finite() {
+            return Err(DiziError::new(
+                DiziErrorKind::InvalidParameters,
+                format!("Invalid speed '{speed}'"),
+            ));
+        }
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.player_stream_req().send(PlayerRequest::SetSpeed {
+            speed: Speed {
+                value: speed,
+                mode: self.speed_mode,
+            },
+        })?;
+
+        self.player_stream_res().recv()??;
+        self.state.speed = speed;
+        Ok(())
+    }
+    fn get_equalizer(&self) -> Option<&str> {
+        self.state.equalizer.as_deref()
+    }
+    fn set_equalizer(&mut self, preset: Option<&str>) -> AppResult {
+        let preset = preset
+            .map(|name| {
+                self.equalizer_presets
+                    .iter()
+                    .find(|preset| preset.name == name)
+                    .cloned()
+                    .ok_or_else(|| {
+                        DiziError::new(
+                            DiziErrorKind::InvalidParameters,
+                            format!("Equalizer preset '{name}' not found"),
+                        )
+                    })
+            })
+            .transpose()?;
+        let name = preset.as_ref().map(|preset| preset.name.clone());
+
+        self.player_stream_req()
+            .send(PlayerRequest::SetEqualizer { preset })?;
+
+        self.player_stream_res().recv()??;
+        self.state.equalizer = name;
+        Ok(())
+    }
+    fn equalizer_presets(&self) -> Vec<String> {
+        self.equalizer_presets
+            .iter()
+            .map(|preset| preset.name.clone())
+            .collect()
+    }
+    fn audio_devices(&self) -> AppResult<Vec<AudioDevice>> {
+        match &self.audio_system {
+            AudioSystem::Host(host_id) => list_output_devices(&get_default_host(*host_id)),
+            AudioSystem::Sink(sink) => {
+                let stream_config = sink.stream_config();
+                Ok(vec![AudioDevice {
+                    name: sink.to_string(),
+                    description: sink.to_string(),
+                    default: true,
+                    configs: vec![AudioDeviceConfig {
+                        channels: stream_config.channels() as usize,
+                        min_sample_rate: stream_config.sample_rate(),
+                        max_sample_rate: stream_config.sample_rate(),
+                        sample_format: stream_config.sample_format().to_string(),
+                    }],
+                }])
+            }
+        }
+    }
+    fn set_audio_device(&mut self, name: &str) -> AppResult<OutputFormat> {
+        let host_id = match &self.audio_system {
+            AudioSystem::Host(host_id) => *host_id,
+            AudioSystem::Sink(sink) => {
+                return Err(DiziError::new(
+                    DiziErrorKind::InvalidParameters,
+                    format!("Can't switch devices when playing to {sink}"),
+                ));
+            }
+        };
+        let audio_host = get_default_host(host_id);
+        let device = get_output_device(&audio_host, Some(name))?;
+        let output_format = self.switch_device(device)?;
+        self.replay()?;
+        Ok(output_format)
+    }
+    fn reopen_audio_device(&mut self) -> AppResult<OutputFormat> {
+        if let AudioSystem::Host(host_id) = &self.audio_system {
+            let audio_host = get_default_host(*host_id);
+            // the same device if it's back, otherwise the host's default
+            let device = match self.state.audio_device.as_deref() {
+                Some(name) => get_output_device(&audio_host, Some(name))
+                    .or_else(|_| get_output_device(&audio_host, None))?,
+                None => get_output_device(&audio_host, None)?,
+            };
+            self.switch_device(device)?;
+        }
+        self.replay()?;
+        self.state
+            .output_format
+            .clone()
+            .ok_or_else(|| DiziError::new(DiziErrorKind::Server, "No output format".to_string()))
+    }
+
     fn next_enabled(&self) -> bool {
         self.state.next
     }
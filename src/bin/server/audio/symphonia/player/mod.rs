@@ -1,29 +1,57 @@
 mod impl_audio_player;
 
 use std::path::PathBuf;
-use std::sync::mpsc;
+use std::sync::{Arc, Mutex, mpsc};
 use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
-use cpal::traits::HostTrait;
+use cpal::traits::DeviceTrait;
 
 use dizi::error::{AppResult, DiziError, DiziErrorKind};
-use dizi::player::{PlayerState, PlayerStatus};
+use dizi::player::{OutputFormat, PlayerState, PlayerStatus};
 use dizi::playlist::PlaylistType;
 use dizi::song::DiziAudioFile;
 
-use crate::audio::device::get_default_host;
+use crate::audio::ab_loop::AbLoop;
+use crate::audio::device::{
+    AudioSystem, DeviceRecovery, OutputDevice, device_name, get_default_host, get_output_device,
+};
+use crate::audio::equalizer::EqPreset;
+use crate::audio::fade::Crossfade;
+use crate::audio::loudness::cache::LoudnessCache;
+use crate::audio::replaygain::{ReplayGain, db_to_linear};
 use crate::audio::request::PlayerRequest;
-use crate::audio::symphonia::stream::PlayerStream;
+use crate::audio::sleep::Sleep;
+use crate::audio::spectrum::{SpectrumTap, spawn_spectrum_thread};
+use crate::audio::speed::SpeedMode;
+use crate::audio::symphonia::stream::{PlayerStream, StreamOptions};
+use crate::audio::volume::VolumeCurve;
 use crate::config;
 use crate::context::PlaylistContext;
 use crate::events::ServerEventSender;
 use crate::playlist::DiziPlaylist;
-use crate::traits::AudioPlayer;
+use crate::traits::{AudioPlayer, DiziPlaylistEntry};
 
 #[derive(Debug)]
 pub struct SymphoniaPlayer {
     pub state: PlayerState,
     pub playlist_context: PlaylistContext,
+    // song queued to play after the current one
+    pub preloaded: Option<DiziPlaylistEntry>,
+    // don't crossfade between songs of the same album
+    pub album_gapless: bool,
+    pub replaygain: ReplayGain,
+    // measured loudness, for songs without ReplayGain tags
+    pub loudness_cache: LoudnessCache,
+    pub equalizer_presets: Vec<EqPreset>,
+    pub volume_curve: VolumeCurve,
+    pub speed_mode: SpeedMode,
+    pub audio_system: AudioSystem,
+    // played samples for the spectrum, only gathered while a client is subscribed
+    pub spectrum_tap: SpectrumTap,
+    pub sleep: Option<Sleep>,
+    // failures of the output device in a row
+    pub device_recovery: DeviceRecovery,
 
     pub player_req_tx: mpsc::Sender<PlayerRequest>,
     pub player_res_rx: mpsc::Receiver<AppResult>,
@@ -33,26 +61,83 @@ pub struct SymphoniaPlayer {
 
 impl SymphoniaPlayer {
     pub fn new(config_t: &config::AppConfig, event_tx: ServerEventSender) -> AppResult<Self> {
-        let audio_host = get_default_host(config_t.server_ref().audio_system);
-        let audio_device = audio_host.default_output_device().ok_or_else(|| {
-            let error_msg = "Failed to get default output device";
-            tracing::error!("{error_msg}");
-            DiziError::new(DiziErrorKind::Symphonia, error_msg.to_string())
-        })?;
+        let server_config = config_t.server_ref();
+
+        let (audio_host, audio_device, audio_device_name, stream_config) = match &server_config
+            .audio_system
+        {
+            AudioSystem::Host(host_id) => {
+                let audio_host = get_default_host(*host_id);
+                let device = match get_output_device(&audio_host, server_config.device.as_deref()) {
+                    Ok(device) => device,
+                    Err(err) => {
+                        tracing::warn!(?err, "Falling back to the default output device");
+                        get_output_device(&audio_host, None)?
+                    }
+                };
+                let name = device_name(&device);
+                let stream_config = output_config(&device)?;
+                let host_name = audio_host.id().name().to_lowercase();
+                (host_name, OutputDevice::Cpal(device), name, stream_config)
+            }
+            AudioSystem::Sink(sink) => {
+                let device = OutputDevice::Sink(Arc::new(Mutex::new(sink.open()?)));
+                let name = sink.to_string();
+                (name.clone(), device, name, sink.stream_config())
+            }
+        };
+        let output_format = output_format(&stream_config);
+
+        let player_config = server_config.player_ref();
+
+        let crossfade = (!player_config.crossfade.is_zero()).then_some(Crossfade {
+            duration: player_config.crossfade,
+            curve: player_config.crossfade_curve,
+        });
+        let equalizer_presets = player_config.equalizer_presets.clone();
+        let equalizer = player_config.equalizer.as_ref().and_then(|name| {
+            let preset = equalizer_presets.iter().find(|preset| &preset.name == name);
+            if preset.is_none() {
+                tracing::warn!(name, "Equalizer preset not found");
+            }
+            preset.cloned()
+        });
+        let equalizer_name = equalizer.as_ref().map(|preset| preset.name.clone());
+        let spectrum_tap = SpectrumTap::new();
+        let _ = spawn_spectrum_thread(
+            spectrum_tap.clone(),
+            player_config.spectrum_rate,
+            player_config.spectrum_bands,
+            event_tx.clone(),
+        );
+        let options = StreamOptions {
+            volume: player_config.volume_curve.gain(player_config.volume),
+            crossfade,
+            equalizer,
+            dither: player_config.dither,
+            speed: player_config.speed,
+            fade: player_config.fade,
+            fade_out: None,
+            spectrum: spectrum_tap.clone(),
+            progress_interval: player_config.progress_interval,
+        };
 
         let (player_req_tx, player_req_rx) = mpsc::channel();
         let (player_res_tx, player_res_rx) = mpsc::channel();
 
         let stream_handle: JoinHandle<AppResult> = thread::spawn(move || {
-            let mut stream =
-                PlayerStream::new(event_tx, player_res_tx, player_req_rx, audio_device)?;
+            let mut stream = PlayerStream::new(
+                event_tx,
+                player_res_tx,
+                player_req_rx,
+                audio_device,
+                stream_config,
+                options,
+            )?;
             stream.listen_for_events()?;
             Ok(())
         });
 
-        let server_config = config_t.server_ref();
-        let player_config = server_config.player_ref();
-
         let playlist_context = PlaylistContext {
             file_playlist: DiziPlaylist::from_file(
                 &PathBuf::from("/"),
@@ -66,13 +151,28 @@ impl SymphoniaPlayer {
             repeat: player_config.repeat,
             shuffle: player_config.shuffle,
             volume: config_t.server_ref().player_ref().volume,
-            audio_host: audio_host.id().name().to_lowercase(),
+            speed: player_config.speed.value,
+            audio_host,
+            audio_device: Some(audio_device_name),
+            output_format: Some(output_format),
+            equalizer: equalizer_name,
             ..PlayerState::default()
         };
 
         Ok(Self {
             state,
             playlist_context,
+            preloaded: None,
+            album_gapless: player_config.album_gapless,
+            replaygain: player_config.replaygain,
+            loudness_cache: LoudnessCache::load(server_config.loudness_cache_ref()),
+            equalizer_presets,
+            volume_curve: player_config.volume_curve,
+            speed_mode: player_config.speed.mode,
+            audio_system: server_config.audio_system.clone(),
+            spectrum_tap,
+            sleep: None,
+            device_recovery: DeviceRecovery::default(),
             player_req_tx,
             player_res_rx,
             _stream_handle: stream_handle,
@@ -86,17 +186,46 @@ impl SymphoniaPlayer {
         &self.player_res_rx
     }
 
+    /// ReplayGain in dB to apply to `song`, using its measured loudness if it has no tags
+    fn replaygain_db(&self, song: &DiziAudioFile) -> Option<f32> {
+        self.replaygain.gain_db(&song.music_metadata).or_else(|| {
+            let loudness = self.loudness_cache.get(song.file_path())?;
+            self.replaygain.loudness_gain_db(loudness)
+        })
+    }
+
     fn play(&mut self, song: &DiziAudioFile) -> AppResult {
-        tracing::debug!(?song, "Playing song");
+        self.state.loop_a = None;
+        self.state.loop_b = None;
+        self.play_from(song, Duration::ZERO, false)
+    }
+
+    /// Plays `song` starting at `position`, leaving it paused if `paused` is set
+    fn play_from(&mut self, song: &DiziAudioFile, position: Duration, paused: bool) -> AppResult {
+        tracing::debug!(?song, ?position, "Playing song");
 
+        let replaygain = self.replaygain_db(song);
         self.player_stream_req().send(PlayerRequest::Play {
             song: Box::new(song.clone()),
-            volume: self.get_volume() as f32 / 100.0,
+            volume: self.volume_curve.gain(self.get_volume()),
+            gain: db_to_linear(replaygain.unwrap_or_default()),
+            position,
+            paused,
+            ab_loop: AbLoop {
+                a: self.state.loop_a,
+                b: self.state.loop_b,
+            },
         })?;
 
         self.player_stream_res().recv()??;
 
-        self.state.status = PlayerStatus::Playing;
+        self.preloaded = None;
+        self.state.replaygain = replaygain;
+        self.state.status = if paused {
+            PlayerStatus::Paused
+        } else {
+            PlayerStatus::Playing
+        };
         self.state.song = Some(song.clone());
         Ok(())
     }
@@ -106,3 +235,21 @@ impl SymphoniaPlayer {
         self.state.playlist_status = playlist_type;
     }
 }
+
+/// Stream config to open `device` with, every song is converted to this format
+/// so one stream config fits all
+fn output_config(device: &cpal::Device) -> AppResult<cpal::SupportedStreamConfig> {
+    device.default_output_config().map_err(|err| {
+        let error_msg = "Failed to get default output config";
+        tracing::error!(?err, "{error_msg}");
+        DiziError::new(DiziErrorKind::Symphonia, error_msg.to_string())
+    })
+}
+
+fn output_format(stream_config: &cpal::SupportedStreamConfig) -> OutputFormat {
+    OutputFormat {
+        channels: stream_config.channels() as usize,
+        sample_rate: stream_config.sample_rate(),
+        sample_format: stream_config.sample_format().to_string(),
+    }
+}
@@ -1,16 +1,32 @@
+mod output_stream;
 mod player_stream;
 mod player_stream_state;
+mod sample_buffer;
 mod stream_listener;
 
+pub use output_stream::*;
 pub use player_stream::*;
 pub use player_stream_state::*;
+pub use sample_buffer::*;
 pub use stream_listener::*;
 
 use std::time::Duration;
 
+use crate::audio::ab_loop::AbLoop;
+
 /// Events returned from stream
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum StreamEvent {
     Progress(Duration),
+    /// decoder reached the end of the song, but the buffered samples are still playing
+    DecodeFinished,
+    /// playback moved on to the queued song without stopping
+    TrackChanged,
+    /// A–B loop was changed, with points set to the current position filled in
+    LoopChanged(AbLoop),
+    /// faded out after a pause or stop request, the stream is silent from here on
+    FadedOut,
     StreamEnded,
+    /// the output device failed, the stream has to be rebuilt to carry on
+    DeviceError(String),
 }
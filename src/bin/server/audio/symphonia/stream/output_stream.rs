@@ -0,0 +1,118 @@
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use cpal::traits::StreamTrait;
+
+use dizi::error::AppResult;
+
+use crate::audio::sink::SharedSink;
+
+/// Frames handed to the sink at a time
+const SINK_PERIOD_FRAMES: usize = 1024;
+
+/// A running output stream, either on an audio host or feeding a sink
+pub enum OutputStream {
+    Cpal(cpal::Stream),
+    Sink(SinkStream),
+}
+
+impl OutputStream {
+    pub fn play(&self) -> AppResult {
+        match self {
+            Self::Cpal(stream) => stream.play()?,
+            Self::Sink(stream) => stream.playing.store(true, Ordering::Relaxed),
+        }
+        Ok(())
+    }
+
+    pub fn pause(&self) -> AppResult {
+        match self {
+            Self::Cpal(stream) => stream.pause()?,
+            Self::Sink(stream) => stream.playing.store(false, Ordering::Relaxed),
+        }
+        Ok(())
+    }
+}
+
+/// Pulls samples from the playback callback on its own thread at real-time pace
+/// and writes them to a sink, the way an audio device would
+pub struct SinkStream {
+    playing: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl SinkStream {
+    /// Plays what `callback` fills in, calling `err_fn` whenever the sink can't be written to
+    pub fn new<T, F, E>(
+        sink: SharedSink,
+        config: &cpal::StreamConfig,
+        mut callback: F,
+        mut err_fn: E,
+    ) -> Self
+    where
+        T: cpal::Sample + Send + 'static,
+        i16: cpal::FromSample<T>,
+        F: FnMut(&mut [T]) + Send + 'static,
+        E: FnMut(io::Error) + Send + 'static,
+    {
+        let playing = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let channels = config.channels as usize;
+        let sample_rate = config.sample_rate as f64;
+
+        let handle = {
+            let playing = playing.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                let mut data = vec![T::EQUILIBRIUM; SINK_PERIOD_FRAMES * channels];
+                let mut samples = Vec::with_capacity(data.len());
+                // when the current run of playback started and how many frames it has written
+                let mut started: Option<(Instant, usize)> = None;
+
+                while !stopped.load(Ordering::Relaxed) {
+                    if !playing.load(Ordering::Relaxed) {
+                        started = None;
+                        thread::sleep(Duration::from_millis(10));
+                        continue;
+                    }
+                    let (start, frames) = started.get_or_insert_with(|| (Instant::now(), 0));
+
+                    callback(&mut data);
+                    samples.clear();
+                    samples.extend(data.iter().map(|s| s.to_sample::<i16>()));
+                    if let Ok(mut sink) = sink.lock()
+                        && let Err(err) = sink.write(&samples)
+                    {
+                        tracing::error!(?err, "Failed to write to sink");
+                        err_fn(err);
+                    }
+
+                    *frames += SINK_PERIOD_FRAMES;
+                    let due = *start + Duration::from_secs_f64(*frames as f64 / sample_rate);
+                    if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                        thread::sleep(wait);
+                    }
+                }
+            })
+        };
+        Self {
+            playing,
+            stopped,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for SinkStream {
+    fn drop(&mut self) {
+        // wait for the thread, so streams replacing this one don't write at the same time
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -1,28 +1,51 @@
 use std::sync::mpsc;
 use std::time::Duration;
 
-use cpal::traits::{DeviceTrait, StreamTrait};
-use symphonia::core::codecs::CodecParameters;
-use symphonia::core::codecs::audio::AudioDecoderOptions;
-
-use dizi::error::{AppResult, DiziError, DiziErrorKind};
+use dizi::error::AppResult;
 use dizi::song::DiziAudioFile;
 
+use crate::audio::ab_loop::{AbLoop, LoopPoint};
+use crate::audio::device::OutputDevice;
+use crate::audio::equalizer::EqPreset;
+use crate::audio::fade::FadeOut;
 use crate::audio::request::PlayerRequest;
+use crate::audio::speed::Speed;
 use crate::audio::symphonia::stream::{
-    PlayerStreamEvent, PlayerStreamEventListener, PlayerStreamState, StreamEvent,
+    PlayerStreamEvent, PlayerStreamEventListener, PlayerStreamState, StreamEvent, StreamOptions,
 };
 use crate::events::{ServerEvent, ServerEventSender};
 
-use super::super::decode::{PacketDecoder, PacketReader, decode_all};
+use super::super::decode::{DecodeSource, DecoderRequest};
+
+/// What to do once the stream has faded out
+enum AfterFade {
+    Pause,
+    Stop,
+    Play(Box<PendingPlay>),
+}
+
+/// Song to start once the one playing has faded out
+struct PendingPlay {
+    source: DecodeSource,
+    position: Duration,
+    paused: bool,
+    ab_loop: AbLoop,
+    // song queued up to play after this one
+    next: Option<(DecodeSource, bool)>,
+}
 
 /// Stream
 pub struct PlayerStream {
     event_tx: ServerEventSender,
     event_poller: PlayerStreamEventListener,
-    device: cpal::Device,
+    device: OutputDevice,
     stream_config: cpal::SupportedStreamConfig,
     state: Option<PlayerStreamState>,
+    options: StreamOptions,
+    // whether the output stream is paused
+    paused: bool,
+    // waiting for the stream to fade out before doing this
+    after_fade: Option<AfterFade>,
 }
 
 impl PlayerStream {
@@ -30,16 +53,12 @@ impl PlayerStream {
         event_tx: ServerEventSender,
         player_res_tx: mpsc::Sender<AppResult>,
         player_req_rx: mpsc::Receiver<PlayerRequest>,
-        device: cpal::Device,
+        device: OutputDevice,
+        stream_config: cpal::SupportedStreamConfig,
+        options: StreamOptions,
     ) -> AppResult<Self> {
         let event_poller = PlayerStreamEventListener::new(player_res_tx, player_req_rx);
 
-        let stream_config = device.default_output_config().map_err(|err| {
-            let error_msg = "Failed to get default output config";
-            tracing::error!(?err, "{error_msg}");
-            DiziError::new(DiziErrorKind::Symphonia, error_msg.to_string())
-        })?;
-
         tracing::debug!(?stream_config, "stream config");
 
         Ok(Self {
@@ -48,43 +67,84 @@ impl PlayerStream {
             device,
             stream_config,
             state: None,
+            options,
+            paused: false,
+            after_fade: None,
         })
     }
 
+    pub fn play(
+        &mut self,
+        song: &DiziAudioFile,
+        gain: f32,
+        position: Duration,
+        paused: bool,
+        ab_loop: AbLoop,
+    ) -> AppResult {
+        let source = DecodeSource::new(song, gain)?;
+        let pending = PendingPlay {
+            source,
+            position,
+            paused,
+            ab_loop,
+            next: None,
+        };
+        // skipping to another song fades out the one playing first
+        self.fade_out(AfterFade::Play(Box::new(pending)))
+    }
     pub fn pause(&mut self) -> AppResult {
-        if let Some(state) = self.state.as_ref() {
-            state.stream.pause()?;
-        }
-        Ok(())
+        self.fade_out(AfterFade::Pause)
     }
     pub fn resume(&mut self) -> AppResult {
+        match self.after_fade {
+            // still fading out, fade back in instead
+            Some(AfterFade::Pause) => self.after_fade = None,
+            // stopping or moving on to another song, too late to resume this one
+            Some(_) => return Ok(()),
+            None => {}
+        }
         if let Some(state) = self.state.as_ref() {
-            state.stream.play()?;
+            state.playback_loop_tx.send(PlayerRequest::Resume)?;
+            if self.paused {
+                state.stream.play()?;
+                self.paused = false;
+            }
         }
         Ok(())
     }
+    /// Fades out and stops
+    pub fn fade_stop(&mut self) -> AppResult {
+        self.fade_out(AfterFade::Stop)
+    }
     pub fn stop(&mut self) -> AppResult {
         self.state.take();
+        self.paused = false;
+        self.after_fade = None;
         Ok(())
     }
-    pub fn fast_forward(&mut self, offset: Duration) -> AppResult {
-        if let Some(state) = self.state.as_ref() {
-            state
-                .playback_loop_tx
-                .send(PlayerRequest::FastForward { offset })?;
+    pub fn seek(&mut self, position: Duration) -> AppResult {
+        if let Some(pending) = self.pending_play() {
+            pending.position = position;
+        } else if let Some(state) = self.state.as_ref() {
+            state.decoder_tx.send(DecoderRequest::Seek(position))?;
         }
         Ok(())
     }
-    pub fn rewind(&mut self, offset: Duration) -> AppResult {
-        if let Some(state) = self.state.as_ref() {
-            state
-                .playback_loop_tx
-                .send(PlayerRequest::Rewind { offset })?;
+    pub fn seek_by(&mut self, offset_ms: i64) -> AppResult {
+        if let Some(pending) = self.pending_play() {
+            let offset = Duration::from_millis(offset_ms.unsigned_abs());
+            pending.position = match offset_ms < 0 {
+                true => pending.position.saturating_sub(offset),
+                false => pending.position + offset,
+            };
+        } else if let Some(state) = self.state.as_ref() {
+            state.decoder_tx.send(DecoderRequest::SeekBy(offset_ms))?;
         }
         Ok(())
     }
 
     pub fn set_volume(&mut self, volume: f32) {
+        self.options.volume = volume;
         if let Some(state) = self.state.as_ref() {
             let _ = state
                 .playback_loop_tx
@@ -92,6 +152,82 @@ impl PlayerStream {
         }
     }
 
+    pub fn set_fade_out(&mut self, fade_out: Option<FadeOut>) {
+        self.options.fade_out = fade_out;
+        // a stream on its way out keeps its fade, so it doesn't come back up
+        if matches!(self.after_fade, Some(AfterFade::Stop | AfterFade::Play(_))) {
+            return;
+        }
+        if let Some(state) = self.state.as_ref() {
+            let req = match fade_out {
+                Some(fade_out) => PlayerRequest::FadeOut(fade_out),
+                None => PlayerRequest::FadeIn,
+            };
+            let _ = state.playback_loop_tx.send(req);
+        }
+    }
+
+    pub fn set_equalizer(&mut self, preset: Option<EqPreset>) {
+        self.options.equalizer = preset.clone();
+        if let Some(state) = self.state.as_ref() {
+            let _ = state
+                .playback_loop_tx
+                .send(PlayerRequest::SetEqualizer { preset });
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) -> AppResult {
+        // the song waiting on the fade starts with `options`
+        self.options.speed = speed;
+        if self.pending_play().is_some() {
+            return Ok(());
+        }
+        if let Some(state) = self.state.as_ref() {
+            state.decoder_tx.send(DecoderRequest::SetSpeed(speed))?;
+        }
+        Ok(())
+    }
+
+    pub fn set_loop_point(&mut self, point: LoopPoint, position: Option<Duration>) -> AppResult {
+        if let Some(pending) = self.pending_play() {
+            let position = position.unwrap_or(pending.position);
+            pending.ab_loop.set(point, position);
+        } else if let Some(state) = self.state.as_ref() {
+            state
+                .decoder_tx
+                .send(DecoderRequest::SetLoopPoint(point, position))?;
+        }
+        Ok(())
+    }
+
+    pub fn set_loop(&mut self, ab_loop: AbLoop) -> AppResult {
+        if let Some(pending) = self.pending_play() {
+            pending.ab_loop = ab_loop;
+        } else if let Some(state) = self.state.as_ref() {
+            state.decoder_tx.send(DecoderRequest::SetLoop(ab_loop))?;
+        }
+        Ok(())
+    }
+
+    pub fn switch_track(&mut self, song: &DiziAudioFile, gain: f32) -> AppResult {
+        if let Some(pending) = self.pending_play() {
+            pending.source = DecodeSource::new(song, gain)?;
+        } else if let Some(state) = self.state.as_ref() {
+            let source = DecodeSource::new(song, gain)?;
+            state.decoder_tx.send(DecoderRequest::SwitchTrack(source))?;
+        }
+        Ok(())
+    }
+
+    /// Song waiting for the one playing to fade out,
+    /// changes to playback go to it instead of the stream on its way out
+    fn pending_play(&mut self) -> Option<&mut PendingPlay> {
+        match self.after_fade.as_mut() {
+            Some(AfterFade::Play(pending)) => Some(pending),
+            _ => None,
+        }
+    }
+
     pub fn listen_for_events(&mut self) -> AppResult {
         while let Ok(msg) = self.event_poller.next() {
             match msg {
@@ -104,23 +240,32 @@ impl PlayerStream {
 
     fn process_player_req(&mut self, req: PlayerRequest) -> AppResult {
         match req {
-            PlayerRequest::Play { song, volume } => {
-                let stream_state = self.build_player_stream_state(song.clone(), volume);
-                match stream_state {
-                    Ok(stream_state) => {
-                        stream_state.stream.play()?;
-                        self.state = Some(stream_state);
-                        self.event_poller.player_res().send(Ok(()))?;
-                    }
-                    Err(e) => self.event_poller.player_res().send(Err(e))?,
-                };
+            PlayerRequest::Play {
+                song,
+                volume,
+                gain,
+                position,
+                paused,
+                ab_loop,
+            } => {
+                self.options.volume = volume;
+                let res = self.play(&song, gain, position, paused, ab_loop);
+                self.event_poller.player_res().send(res)?;
+            }
+            PlayerRequest::Enqueue {
+                song,
+                crossfade,
+                gain,
+            } => {
+                let res = self.enqueue(&song, crossfade, gain);
+                self.event_poller.player_res().send(res)?;
             }
             PlayerRequest::Pause => {
                 self.pause()?;
                 self.event_poller.player_res().send(Ok(()))?;
             }
             PlayerRequest::Stop => {
-                self.stop()?;
+                self.fade_stop()?;
                 self.event_poller.player_res().send(Ok(()))?;
             }
             PlayerRequest::Resume => {
@@ -131,11 +276,49 @@ impl PlayerStream {
                 self.set_volume(volume);
                 self.event_poller.player_res().send(Ok(()))?;
             }
-            PlayerRequest::FastForward { offset } => {
-                self.fast_forward(offset)?;
+            PlayerRequest::FadeOut(fade_out) => {
+                self.set_fade_out(Some(fade_out));
+                self.event_poller.player_res().send(Ok(()))?;
+            }
+            PlayerRequest::FadeIn => {
+                self.set_fade_out(None);
+                self.event_poller.player_res().send(Ok(()))?;
+            }
+            PlayerRequest::SetEqualizer { preset } => {
+                self.set_equalizer(preset);
+                self.event_poller.player_res().send(Ok(()))?;
+            }
+            PlayerRequest::Seek { position } => {
+                self.seek(position)?;
+            }
+            PlayerRequest::SeekBy { offset_ms } => {
+                self.seek_by(offset_ms)?;
+            }
+            PlayerRequest::SetLoopPoint { point, position } => {
+                let res = self.set_loop_point(point, position);
+                self.event_poller.player_res().send(res)?;
+            }
+            PlayerRequest::ClearLoop => {
+                let res = self.set_loop(AbLoop::default());
+                self.event_poller.player_res().send(res)?;
             }
-            PlayerRequest::Rewind { offset } => {
-                self.rewind(offset)?;
+            PlayerRequest::SwitchTrack { song, gain } => {
+                let res = self.switch_track(&song, gain);
+                self.event_poller.player_res().send(res)?;
+            }
+            PlayerRequest::SetSpeed { speed } => {
+                let res = self.set_speed(speed);
+                self.event_poller.player_res().send(res)?;
+            }
+            PlayerRequest::SetDevice {
+                device,
+                stream_config,
+            } => {
+                tracing::debug!(?stream_config, "switching output device");
+                self.stop()?;
+                self.device = OutputDevice::Cpal(*device);
+                self.stream_config = stream_config;
+                self.event_poller.player_res().send(Ok(()))?;
             }
         }
         Ok(())
@@ -147,6 +330,35 @@ impl PlayerStream {
                 self.stop()?;
                 self.event_tx.send(ServerEvent::PlayerDone)?;
             }
+            StreamEvent::DecodeFinished => {
+                self.event_tx.send(ServerEvent::PlayerTrackEnding)?;
+            }
+            StreamEvent::TrackChanged => {
+                self.event_tx.send(ServerEvent::PlayerTrackChanged)?;
+            }
+            StreamEvent::LoopChanged(ab_loop) => {
+                self.event_tx
+                    .send(ServerEvent::PlayerLoopChanged(ab_loop))?;
+            }
+            StreamEvent::FadedOut => {
+                // stale if playback was resumed or stopped in the meantime
+                if let Some(then) = self.after_fade.take()
+                    && let Err(err) = self.finish_fade(then)
+                {
+                    tracing::error!(?err, "Failed to start song after fading out");
+                }
+            }
+            StreamEvent::DeviceError(msg) => {
+                // stale if the stream that failed has been replaced since
+                if self
+                    .state
+                    .as_ref()
+                    .is_some_and(PlayerStreamState::has_failed)
+                {
+                    self.stop()?;
+                    self.event_tx.send(ServerEvent::PlayerDeviceError(msg))?;
+                }
+            }
             StreamEvent::Progress(duration) => {
                 self.event_tx
                     .send(ServerEvent::PlayerProgressUpdate(duration))?;
@@ -155,221 +367,260 @@ impl PlayerStream {
         Ok(())
     }
 
-    pub fn build_player_stream_state(
-        &self,
-        audio_file: Box<DiziAudioFile>,
-        volume: f32,
-    ) -> AppResult<PlayerStreamState> {
-        let track_id = audio_file.audio_metadata.track_id;
-
-        let format_reader = audio_file.file.get_probe_result()?;
-
-        let codec_params = format_reader
-            .tracks()
-            .first()
-            .ok_or_else(|| {
-                let error_msg = "No tracks found";
-                tracing::error!(?audio_file.file, "{error_msg}");
-                DiziError::new(DiziErrorKind::Symphonia, error_msg.to_string())
-            })?
-            .codec_params
-            .as_ref()
-            .ok_or_else(|| {
-                let error_msg = "Failed to get default track codec_params";
-                tracing::error!("{error_msg}");
-                DiziError::new(DiziErrorKind::Symphonia, error_msg.to_string())
-            })?;
-
-        let audio_codec_params = match codec_params {
-            CodecParameters::Audio(params) => params,
-            _ => {
-                let error_msg = "Codec not audio";
-                tracing::error!("{error_msg}");
-                let err = DiziError::new(DiziErrorKind::ParseError, error_msg.to_string());
-                return Err(err);
+    /// Does `then` once the stream has faded out,
+    /// or straight away if there's nothing playing to fade out
+    fn fade_out(&mut self, then: AfterFade) -> AppResult {
+        if self.after_fade.is_some() {
+            // already fading out, do this instead once it's done
+            self.after_fade = Some(then);
+            return Ok(());
+        }
+        match self.state.as_ref() {
+            Some(state) if !self.paused && !self.options.fade.is_zero() => {
+                state.playback_loop_tx.send(PlayerRequest::Pause)?;
+                self.after_fade = Some(then);
+                Ok(())
+            }
+            _ => self.finish_fade(then),
+        }
+    }
+
+    fn finish_fade(&mut self, then: AfterFade) -> AppResult {
+        match then {
+            AfterFade::Pause => {
+                if let Some(state) = self.state.as_ref() {
+                    state.stream.pause()?;
+                    self.paused = true;
+                }
+                Ok(())
             }
-        };
+            AfterFade::Stop => self.stop(),
+            AfterFade::Play(pending) => self.start(*pending),
+        }
+    }
 
-        // Use the default options for the decoder.
-        let dec_opts: AudioDecoderOptions = Default::default();
+    fn start(&mut self, pending: PendingPlay) -> AppResult {
+        let PendingPlay {
+            source,
+            position,
+            paused,
+            ab_loop,
+            next,
+        } = pending;
+        // the old stream has to be gone before the new one plays, or both write at once
+        self.state.take();
+        let stream_state = self.build_player_stream_state(source)?;
+        if ab_loop != AbLoop::default() {
+            stream_state
+                .decoder_tx
+                .send(DecoderRequest::SetLoop(ab_loop))?;
+        }
+        if !position.is_zero() {
+            // the stream hasn't started yet, so nothing before `position` is heard
+            stream_state
+                .decoder_tx
+                .send(DecoderRequest::Seek(position))?;
+        }
+        if let Some((source, crossfade)) = next {
+            stream_state
+                .decoder_tx
+                .send(DecoderRequest::Enqueue { source, crossfade })?;
+        }
+        if !paused {
+            stream_state.stream.play()?;
+        }
+        self.state = Some(stream_state);
+        self.paused = paused;
+        // a paused stream doesn't report progress until it plays, so say where it starts
+        if paused {
+            self.event_tx
+                .send(ServerEvent::PlayerProgressUpdate(position))?;
+        }
+        Ok(())
+    }
 
-        // Create a decoder for the track.
-        let decoder =
-            symphonia::default::get_codecs().make_audio_decoder(audio_codec_params, &dec_opts)?;
+    /// Gets `song` ready to play right after the current song.
+    ///
+    /// Its samples are appended to the current stream for gapless playback,
+    /// crossfading into it if `crossfade` is set.
+    fn enqueue(&mut self, song: &DiziAudioFile, crossfade: bool, gain: f32) -> AppResult {
+        if self.state.is_none() {
+            return Ok(());
+        }
+        let source = DecodeSource::new(song, gain)?;
+        if let Some(pending) = self.pending_play() {
+            pending.next = Some((source, crossfade));
+        } else if let Some(state) = self.state.as_ref() {
+            state
+                .decoder_tx
+                .send(DecoderRequest::Enqueue { source, crossfade })?;
+        }
+        Ok(())
+    }
 
+    pub fn build_player_stream_state(&self, source: DecodeSource) -> AppResult<PlayerStreamState> {
         let stream_tx = self.event_poller.stream_tx.clone();
+        let config = self.stream_config.config();
+
+        match self.stream_config.sample_format() {
+            cpal::SampleFormat::U8 => PlayerStreamState::build::<u8>(
+                stream_tx,
+                &self.device,
+                &config,
+                source,
+                self.options.clone(),
+            ),
+            cpal::SampleFormat::U16 => PlayerStreamState::build::<u16>(
+                stream_tx,
+                &self.device,
+                &config,
+                source,
+                self.options.clone(),
+            ),
+            cpal::SampleFormat::U32 => PlayerStreamState::build::<u32>(
+                stream_tx,
+                &self.device,
+                &config,
+                source,
+                self.options.clone(),
+            ),
+            cpal::SampleFormat::I8 => PlayerStreamState::build::<i8>(
+                stream_tx,
+                &self.device,
+                &config,
+                source,
+                self.options.clone(),
+            ),
+            cpal::SampleFormat::I16 => PlayerStreamState::build::<i16>(
+                stream_tx,
+                &self.device,
+                &config,
+                source,
+                self.options.clone(),
+            ),
+            cpal::SampleFormat::I32 => PlayerStreamState::build::<i32>(
+                stream_tx,
+                &self.device,
+                &config,
+                source,
+                self.options.clone(),
+            ),
+            cpal::SampleFormat::F32 => PlayerStreamState::build::<f32>(
+                stream_tx,
+                &self.device,
+                &config,
+                source,
+                self.options.clone(),
+            ),
+            _ => PlayerStreamState::build::<f64>(
+                stream_tx,
+                &self.device,
+                &config,
+                source,
+                self.options.clone(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    use dizi::song::DiziFile;
+
+    use crate::audio::sink::{SINK_SAMPLE_RATE, Sink, SinkKind};
+    use crate::audio::spectrum::SpectrumTap;
+
+    use super::*;
+
+    /// A silent WAV file lasting `secs`
+    fn silent_wav(name: &str, secs: usize) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dizi-test-{}", std::process::id()));
+        let path = dir.join(name);
+        let mut sink = SinkKind::Wav(path.clone()).open().unwrap();
+        sink.write(&vec![0; SINK_SAMPLE_RATE as usize * 2 * secs])
+            .unwrap();
+        path
+    }
 
-        let packet_reader = PacketReader::new(format_reader, track_id);
-        let packet_decoder = PacketDecoder::new(decoder);
-
-        // Fallback used only if decoding produced no packets at all (e.g. an
-        // empty file), in which case there's no audio to misconfigure anyway.
-        let fallback_audio_config = cpal::StreamConfig {
-            channels: audio_file
-                .audio_metadata
-                .channels
-                .map(|c| c as u16)
-                .unwrap_or_else(|| self.stream_config.channels()),
-            sample_rate: audio_file
-                .audio_metadata
-                .sample_rate
-                .unwrap_or_else(|| self.stream_config.sample_rate()),
-            buffer_size: cpal::BufferSize::Default,
+    fn player_stream() -> (PlayerStream, mpsc::Receiver<ServerEvent>) {
+        let (event_tx, event_rx) = mpsc::channel();
+        let (player_res_tx, _) = mpsc::channel();
+        let (_, player_req_rx) = mpsc::channel();
+        let options = StreamOptions {
+            volume: 1.0,
+            crossfade: None,
+            equalizer: None,
+            dither: false,
+            speed: Speed::default(),
+            fade: Duration::from_millis(30),
+            fade_out: None,
+            spectrum: SpectrumTap::new(),
+            progress_interval: Duration::from_millis(50),
         };
+        let stream = PlayerStream::new(
+            event_tx,
+            player_res_tx,
+            player_req_rx,
+            OutputDevice::Sink(Arc::new(Mutex::new(Sink::Null))),
+            SinkKind::Null.stream_config(),
+            options,
+        )
+        .unwrap();
+        (stream, event_rx)
+    }
 
-        match self.stream_config.sample_format() {
-            cpal::SampleFormat::U8 => {
-                let (samples, spec) = decode_all::<u8>(packet_reader, packet_decoder)?;
-                let audio_config = match spec {
-                    Some(spec) => cpal::StreamConfig {
-                        channels: spec.channels as u16,
-                        sample_rate: spec.sample_rate,
-                        buffer_size: cpal::BufferSize::Default,
-                    },
-                    None => fallback_audio_config,
-                };
-                PlayerStreamState::build::<u8>(
-                    stream_tx,
-                    &self.device,
-                    &audio_config,
-                    samples,
-                    volume,
-                    |packet, volume| ((packet as f32) * volume) as u8,
-                )
-            }
-            cpal::SampleFormat::U16 => {
-                let (samples, spec) = decode_all::<u16>(packet_reader, packet_decoder)?;
-                let audio_config = match spec {
-                    Some(spec) => cpal::StreamConfig {
-                        channels: spec.channels as u16,
-                        sample_rate: spec.sample_rate,
-                        buffer_size: cpal::BufferSize::Default,
-                    },
-                    None => fallback_audio_config,
-                };
-                PlayerStreamState::build::<u16>(
-                    stream_tx,
-                    &self.device,
-                    &audio_config,
-                    samples,
-                    volume,
-                    |packet, volume| ((packet as f32) * volume) as u16,
-                )
-            }
-            cpal::SampleFormat::U32 => {
-                let (samples, spec) = decode_all::<u32>(packet_reader, packet_decoder)?;
-                let audio_config = match spec {
-                    Some(spec) => cpal::StreamConfig {
-                        channels: spec.channels as u16,
-                        sample_rate: spec.sample_rate,
-                        buffer_size: cpal::BufferSize::Default,
-                    },
-                    None => fallback_audio_config,
-                };
-                PlayerStreamState::build::<u32>(
-                    stream_tx,
-                    &self.device,
-                    &audio_config,
-                    samples,
-                    volume,
-                    |packet, volume| ((packet as f32) * volume) as u32,
-                )
-            }
-            cpal::SampleFormat::I8 => {
-                let (samples, spec) = decode_all::<i8>(packet_reader, packet_decoder)?;
-                let audio_config = match spec {
-                    Some(spec) => cpal::StreamConfig {
-                        channels: spec.channels as u16,
-                        sample_rate: spec.sample_rate,
-                        buffer_size: cpal::BufferSize::Default,
-                    },
-                    None => fallback_audio_config,
-                };
-                PlayerStreamState::build::<i8>(
-                    stream_tx,
-                    &self.device,
-                    &audio_config,
-                    samples,
-                    volume,
-                    |packet, volume| ((packet as f32) * volume) as i8,
-                )
-            }
-            cpal::SampleFormat::I16 => {
-                let (samples, spec) = decode_all::<i16>(packet_reader, packet_decoder)?;
-                let audio_config = match spec {
-                    Some(spec) => cpal::StreamConfig {
-                        channels: spec.channels as u16,
-                        sample_rate: spec.sample_rate,
-                        buffer_size: cpal::BufferSize::Default,
-                    },
-                    None => fallback_audio_config,
-                };
-                PlayerStreamState::build::<i16>(
-                    stream_tx,
-                    &self.device,
-                    &audio_config,
-                    samples,
-                    volume,
-                    |packet, volume| ((packet as f32) * volume) as i16,
-                )
+    /// Handles stream events until `done` is happy with a progress update
+    fn wait_for_progress(
+        stream: &mut PlayerStream,
+        event_rx: &mpsc::Receiver<ServerEvent>,
+        done: impl Fn(Duration) -> bool,
+    ) -> Option<Duration> {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+            if let Ok(PlayerStreamEvent::Stream(event)) =
+                stream.event_poller.event_rx.recv_timeout(timeout)
+            {
+                stream.process_stream_event(event).unwrap();
             }
-            cpal::SampleFormat::I32 => {
-                let (samples, spec) = decode_all::<i32>(packet_reader, packet_decoder)?;
-                let audio_config = match spec {
-                    Some(spec) => cpal::StreamConfig {
-                        channels: spec.channels as u16,
-                        sample_rate: spec.sample_rate,
-                        buffer_size: cpal::BufferSize::Default,
-                    },
-                    None => fallback_audio_config,
-                };
-                PlayerStreamState::build::<i32>(
-                    stream_tx,
-                    &self.device,
-                    &audio_config,
-                    samples,
-                    volume,
-                    |packet, volume| ((packet as f32) * volume) as i32,
-                )
-            }
-            cpal::SampleFormat::F32 => {
-                let (samples, spec) = decode_all::<f32>(packet_reader, packet_decoder)?;
-                let audio_config = match spec {
-                    Some(spec) => cpal::StreamConfig {
-                        channels: spec.channels as u16,
-                        sample_rate: spec.sample_rate,
-                        buffer_size: cpal::BufferSize::Default,
-                    },
-                    None => fallback_audio_config,
-                };
-                PlayerStreamState::build::<f32>(
-                    stream_tx,
-                    &self.device,
-                    &audio_config,
-                    samples,
-                    volume,
-                    |packet, volume| packet * volume,
-                )
-            }
-            _ => {
-                let (samples, spec) = decode_all::<f64>(packet_reader, packet_decoder)?;
-                let audio_config = match spec {
-                    Some(spec) => cpal::StreamConfig {
-                        channels: spec.channels as u16,
-                        sample_rate: spec.sample_rate,
-                        buffer_size: cpal::BufferSize::Default,
-                    },
-                    None => fallback_audio_config,
-                };
-                PlayerStreamState::build::<f64>(
-                    stream_tx,
-                    &self.device,
-                    &audio_config,
-                    samples,
-                    volume,
-                    |packet, volume| packet * volume as f64,
-                )
+            while let Ok(event) = event_rx.try_recv() {
+                if let ServerEvent::PlayerProgressUpdate(elapsed) = event
+                    && done(elapsed)
+                {
+                    return Some(elapsed);
+                }
             }
         }
+        None
+    }
+
+    #[test]
+    fn seeking_while_fading_out_seeks_the_next_song() {
+        let song = DiziAudioFile::try_from(DiziFile::new(&silent_wav("fade-seek.wav", 6))).unwrap();
+        let (mut stream, event_rx) = player_stream();
+
+        stream
+            .play(&song, 1.0, Duration::ZERO, false, AbLoop::default())
+            .unwrap();
+        wait_for_progress(&mut stream, &event_rx, |elapsed| !elapsed.is_zero()).unwrap();
+
+        // fades out the song playing before starting over
+        stream
+            .play(&song, 1.0, Duration::ZERO, false, AbLoop::default())
+            .unwrap();
+        assert!(stream.pending_play().is_some());
+        stream.seek(Duration::from_secs(3)).unwrap();
+
+        // starting from the top, it wouldn't get there before the deadline
+        let elapsed = wait_for_progress(&mut stream, &event_rx, |elapsed| {
+            elapsed >= Duration::from_secs(3)
+        });
+        assert!(
+            elapsed.is_some_and(|elapsed| elapsed < Duration::from_millis(3500)),
+            "{elapsed:?}"
+        );
     }
 }
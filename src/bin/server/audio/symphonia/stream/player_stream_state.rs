@@ -1,57 +1,112 @@
 use std::num::NonZero;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
-use std::time::Duration;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use cpal::Stream;
 use cpal::StreamConfig;
 use cpal::traits::DeviceTrait;
 use dizi::error::DiziError;
 use dizi::error::DiziErrorKind;
-use symphonia::core::audio::conv::ConvertibleSample;
 use symphonia::core::units::TimeBase;
 
 use dizi::error::AppResult;
 use symphonia::core::units::Timestamp;
 
+use crate::audio::device::{OutputDevice, StreamErrorFilter};
+use crate::audio::equalizer::{EqPreset, Equalizer};
+use crate::audio::fade::{Crossfade, FadeOut, Ramp};
 use crate::audio::request::PlayerRequest;
+use crate::audio::spectrum::SpectrumTap;
+use crate::audio::speed::Speed;
+use crate::audio::symphonia::decode::{
+    DecodeSource, DecodedAudioSpec, DecoderRequest, StreamDecoder,
+};
+use crate::audio::volume::{Dither, to_output_sample};
 
-use super::StreamEvent;
+use super::{OutputStream, Played, SampleBuffer, SinkStream, StreamEvent};
+
+/// How much decoded audio to keep buffered ahead of the output stream
+const BUFFER_DURATION_SECS: usize = 2;
+
+/// Playback settings a new stream starts out with
+#[derive(Clone, Debug)]
+pub struct StreamOptions {
+    pub volume: f32,
+    pub crossfade: Option<Crossfade>,
+    pub equalizer: Option<EqPreset>,
+    /// dither when the output format has fewer bits than the decoded samples
+    pub dither: bool,
+    pub speed: Speed,
+    /// how long to fade out and back in around pauses, stops and seeks
+    pub fade: Duration,
+    /// slow fade towards silence in progress, new streams carry on with it
+    pub fade_out: Option<FadeOut>,
+    /// picks up what's played, for the spectrum sent to visualizers
+    pub spectrum: SpectrumTap,
+    /// how much playback passes between `StreamEvent::Progress` events
+    pub progress_interval: Duration,
+}
 
 /// Stream state
 pub struct PlayerStreamState {
-    pub stream: Stream,
+    pub stream: OutputStream,
     pub playback_loop_tx: mpsc::Sender<PlayerRequest>,
+    pub decoder_tx: mpsc::Sender<DecoderRequest>,
+    // set once the stream has reported a `StreamEvent::DeviceError`
+    failed: Arc<AtomicBool>,
 }
 
 impl PlayerStreamState {
     pub fn build<T>(
         stream_tx: mpsc::Sender<StreamEvent>,
-        device: &cpal::Device,
+        device: &OutputDevice,
         config: &StreamConfig,
-        samples: Vec<T>,
-        volume: f32,
-        volume_change: fn(T, f32) -> T,
+        source: DecodeSource,
+        options: StreamOptions,
     ) -> AppResult<PlayerStreamState>
     where
-        T: ConvertibleSample + cpal::Sample + cpal::SizedSample + std::marker::Send + 'static,
+        T: cpal::SizedSample + cpal::FromSample<f32> + std::marker::Send + 'static,
+        i16: cpal::FromSample<T>,
     {
-        build_stream_state(stream_tx, device, config, samples, volume, volume_change)
+        build_stream_state::<T>(stream_tx, device, config, source, options)
+    }
+
+    /// Whether this stream's device failed
+    pub fn has_failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
     }
 }
 
 fn build_stream_state<T>(
     stream_tx: mpsc::Sender<StreamEvent>,
-    device: &cpal::Device,
+    device: &OutputDevice,
     config: &StreamConfig,
-    samples: Vec<T>,
-    volume: f32,
-    volume_change: fn(T, f32) -> T,
+    source: DecodeSource,
+    options: StreamOptions,
 ) -> AppResult<PlayerStreamState>
 where
-    T: ConvertibleSample + cpal::Sample + cpal::SizedSample + std::marker::Send + 'static,
+    T: cpal::SizedSample + cpal::FromSample<f32> + std::marker::Send + 'static,
+    i16: cpal::FromSample<T>,
 {
-    let err_fn = |err| {
-        tracing::error!(?err, "A playback error has occured!");
+    // only the first failure is reported, the stream is rebuilt after that
+    let failed = Arc::new(AtomicBool::new(false));
+    let report_error = {
+        let failed = failed.clone();
+        let stream_tx = stream_tx.clone();
+        move |msg: String| {
+            if !failed.swap(true, Ordering::Relaxed) {
+                let _ = stream_tx.send(StreamEvent::DeviceError(msg));
+            }
+        }
+    };
+
+    // songs are converted to the device's format, so every song can go through the same stream
+    let config = *config;
+    let spec = DecodedAudioSpec {
+        channels: config.channels as usize,
+        sample_rate: config.sample_rate,
     };
 
     let numer = NonZero::<u32>::MIN;
@@ -62,16 +117,55 @@ where
     })?;
     let time_base = TimeBase { numer, denom };
 
-    let samples_count = samples.len();
+    let buffer = Arc::new(SampleBuffer::new(
+        denom.get() as usize * BUFFER_DURATION_SECS,
+    ));
+    buffer.set_speed(options.speed.value as f64);
 
-    // all vars that the stream will update while its streaming
-    let mut volume = volume;
-    // The currrent frame_index, "where the cursor is within the file"
-    let mut frame_index: usize = 0;
-    let mut playback_duration = 0;
+    // decoder thread, exits once decoder_tx is dropped along with the stream state
+    let (decoder_tx, decoder_rx) = mpsc::channel();
+    {
+        let stream_decoder = StreamDecoder::new(
+            source,
+            spec,
+            buffer.clone(),
+            decoder_rx,
+            stream_tx.clone(),
+            &options,
+        );
+        let _ = thread::spawn(move || stream_decoder.run());
+    }
 
-    // initial event
-    let _ = stream_tx.send(StreamEvent::Progress(Duration::from_secs(0)));
+    // all vars that the stream will update while its streaming
+    let mut volume = options.volume;
+    let new_equalizer =
+        move |preset: &EqPreset| Equalizer::new(preset, spec.channels, spec.sample_rate);
+    let mut equalizer = options.equalizer.as_ref().map(new_equalizer);
+    // channel of the next sample to go through the equalizer
+    let mut channel = 0;
+    let mut dither = if options.dither {
+        Dither::new(T::FORMAT)
+    } else {
+        None
+    };
+    // progress is reported whenever playback moves into another interval
+    let progress_interval = options.progress_interval.as_millis().max(1);
+    let mut reported_interval = None;
+    // new streams fade in, and fade out before being paused or stopped
+    let fade_frames = (options.fade.as_secs_f64() * spec.sample_rate as f64) as usize;
+    let mut fade = Ramp::new(fade_frames, 0.0);
+    fade.fade_to(1.0);
+    // whether to report reaching silence
+    let mut fading_out = false;
+    // the sleep timer's fade, separate so pausing and seeking don't undo it
+    let mut long_fade = match options.fade_out {
+        Some(fade_out) => fade_out.ramp(spec.sample_rate),
+        None => Ramp::new(fade_frames, 1.0),
+    };
+    let spectrum = options.spectrum;
+    spectrum.set_format(spec.channels, spec.sample_rate);
+    // samples as played, gathered for the spectrum tap while anyone is listening
+    let mut tapped: Vec<f32> = Vec::with_capacity(spec.sample_rate as usize * spec.channels / 10);
 
     // if stream_tx is None, then we've already sent a StreamEnded message
     // and we don't need to send another one
@@ -79,91 +173,152 @@ where
 
     let (playback_loop_tx, playback_loop_rx) = mpsc::channel();
 
-    let stream = device.build_output_stream(
-        *config,
-        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-            // Process any user requests
-            if let Ok(msg) = playback_loop_rx.try_recv() {
-                match msg {
-                    PlayerRequest::SetVolume { volume: new_volume } => {
-                        volume = new_volume;
-                    }
-                    PlayerRequest::FastForward { offset } => {
-                        let denom = time_base.denom.get() as usize;
-                        let offset_secs = offset.as_secs() as usize;
-                        let denom_mul = denom * offset_secs;
-
-                        frame_index += denom_mul;
-
-                        if frame_index >= samples_count {
-                            // set to samples_count - denom so the song doesn't immediately finish
-                            frame_index = samples_count - denom;
-                        }
-                    }
-                    PlayerRequest::Rewind { offset } => {
-                        let denom = time_base.denom.get() as usize;
-                        let offset_secs = offset.as_secs() as usize;
-                        let denom_mul = denom * offset_secs;
-
-                        if frame_index < denom_mul {
-                            frame_index = 0;
-                        } else {
-                            frame_index -= denom_mul;
-                        }
-                    }
-                    _ => {}
+    let mut callback = move |data: &mut [T]| {
+        // Process any user requests
+        while let Ok(request) = playback_loop_rx.try_recv() {
+            match request {
+                PlayerRequest::SetVolume { volume: new_volume } => volume = new_volume,
+                PlayerRequest::SetEqualizer { preset } => {
+                    equalizer = preset.as_ref().map(new_equalizer);
+                }
+                PlayerRequest::Pause | PlayerRequest::Stop => {
+                    fade.fade_to(0.0);
+                    fading_out = true;
+                }
+                PlayerRequest::Resume => {
+                    fade.fade_to(1.0);
+                    fading_out = false;
                 }
+                PlayerRequest::FadeOut(fade_out) => long_fade = fade_out.ramp(spec.sample_rate),
+                PlayerRequest::FadeIn => {
+                    long_fade = Ramp::new(fade_frames, long_fade.gain());
+                    long_fade.fade_to(1.0);
+                }
+                _ => {}
             }
+        }
 
-            // if frame_index is greater than samples_count, then we've reached the end
-            if frame_index >= samples_count {
-                if let Some(stream_tx) = stream_tx.take() {
-                    let _ = stream_tx.send(StreamEvent::StreamEnded);
+        // once faded out, the rest of the buffer waits for playback to resume
+        let playable = match fade.frames_to_silence() {
+            Some(frames) => data.len().min(frames * spec.channels),
+            None => data.len(),
+        };
+        let current_volume = volume;
+        let tapping = spectrum.is_enabled();
+        tapped.clear();
+        // rather than wait on the decoder, play silence this once
+        let Some(Played {
+            written,
+            track_changed,
+            finished,
+            position,
+        }) = buffer.pop_into(&mut data[..playable], |sample| {
+            let sample = match equalizer.as_mut() {
+                Some(equalizer) => equalizer.process_sample(channel, sample),
+                None => sample,
+            } * fade.gain()
+                * long_fade.gain();
+            channel = (channel + 1) % spec.channels;
+            if channel == 0 {
+                fade.advance();
+                long_fade.advance();
+            }
+            if tapping {
+                tapped.push(sample * current_volume);
+                // hand it over in parts rather than growing, whatever the device's buffer size
+                if tapped.len() == tapped.capacity() {
+                    spectrum.write(&tapped);
+                    tapped.clear();
                 }
-                return;
             }
+            to_output_sample(sample, current_volume, dither.as_mut())
+        })
+        else {
+            data.fill(T::EQUILIBRIUM);
+            return;
+        };
+        if tapping && !tapped.is_empty() {
+            spectrum.write(&tapped);
+        }
+        // decoder hasn't caught up or the song is over, either way play silence
+        for d in data[written..].iter_mut() {
+            *d = T::EQUILIBRIUM;
+        }
 
-            let current_volume = volume;
-            let mut i = 0;
-            for d in data.iter_mut() {
-                if frame_index + i >= samples_count {
-                    frame_index = samples_count + 1;
-                    break;
-                }
-                *d = volume_change(samples[frame_index + i], current_volume);
-                i += 1;
+        // running out of samples mid fade is as good as silent
+        if fading_out && (written < playable || fade.is_done()) {
+            fade = Ramp::new(fade_frames, 0.0);
+            fading_out = false;
+            if let Some(stream_tx) = stream_tx.as_ref() {
+                let _ = stream_tx.send(StreamEvent::FadedOut);
             }
-            // new offset
-            let new_sample_offset = {
-                frame_index += i;
-                frame_index
-            };
-            // new duration
-            let next_duration = time_base
-                .calc_time(Timestamp::new(new_sample_offset as i64))
-                .ok_or_else(|| {
-                    let error_msg = "Failed to calculate time";
-                    DiziError::new(DiziErrorKind::InvalidParameters, error_msg.to_string())
-                })
-                .unwrap_or_default()
-                .as_secs() as u64;
-            let prev_duration = playback_duration;
-
-            // only update duration if seconds changed
-            if prev_duration != next_duration {
-                let new_duration = Duration::from_secs(next_duration);
-                if let Some(stream_tx) = stream_tx.as_ref() {
-                    let _ = stream_tx.send(StreamEvent::Progress(new_duration));
-                }
-                playback_duration = new_duration.as_secs();
+        }
+
+        // if the decoder is done and the buffer is drained, then we've reached the end
+        if written == 0 && finished {
+            if let Some(stream_tx) = stream_tx.take() {
+                let _ = stream_tx.send(StreamEvent::StreamEnded);
+            }
+            return;
+        }
+
+        if track_changed {
+            if let Some(stream_tx) = stream_tx.as_ref() {
+                let _ = stream_tx.send(StreamEvent::TrackChanged);
             }
-        },
-        err_fn,
-        None,
-    )?;
+            // the next song's progress is reported straight away
+            reported_interval = None;
+        }
+
+        let position = time_base
+            .calc_time(Timestamp::new(position as i64))
+            .ok_or_else(|| {
+                let error_msg = "Failed to calculate time";
+                DiziError::new(DiziErrorKind::InvalidParameters, error_msg.to_string())
+            })
+            .unwrap_or_default()
+            .as_millis()
+            .max(0) as u128;
+
+        // only update progress once playback reaches the next interval
+        let interval = position / progress_interval;
+        if reported_interval != Some(interval) {
+            if let Some(stream_tx) = stream_tx.as_ref() {
+                let _ = stream_tx.send(StreamEvent::Progress(Duration::from_millis(
+                    position as u64,
+                )));
+            }
+            reported_interval = Some(interval);
+        }
+    };
+    let stream = match device {
+        OutputDevice::Cpal(device) => {
+            let mut error_filter = StreamErrorFilter::new();
+            let err_fn = move |err: cpal::Error| {
+                tracing::error!(?err, "A playback error has occured!");
+                if error_filter.is_fatal(err.kind(), Instant::now()) {
+                    report_error(err.to_string());
+                }
+            };
+            OutputStream::Cpal(device.build_output_stream(
+                config,
+                move |data: &mut [T], _: &cpal::OutputCallbackInfo| callback(data),
+                err_fn,
+                None,
+            )?)
+        }
+        OutputDevice::Sink(sink) => OutputStream::Sink(SinkStream::new(
+            sink.clone(),
+            &config,
+            callback,
+            move |err| report_error(err.to_string()),
+        )),
+    };
     let state = PlayerStreamState {
         stream,
         playback_loop_tx,
+        decoder_tx,
+        failed,
     };
     Ok(state)
 }
@@ -0,0 +1,364 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError, TryLockError};
+use std::time::Duration;
+
+use crate::audio::fade::Ramp;
+
+#[derive(Debug)]
+struct SampleBufferInner<T> {
+    samples: VecDeque<T>,
+    // interleaved sample index of the sample at the front of the queue,
+    // "where the cursor is within the file"
+    position: f64,
+    // samples of the file each buffered sample stands for, when not playing at normal speed
+    speed: f64,
+    // decoder has no more samples to give
+    end_of_stream: bool,
+    // total samples pushed/popped since the last reset
+    pushed: usize,
+    popped: usize,
+    // values of `pushed` at which the next track's samples begin
+    track_starts: VecDeque<usize>,
+    // positions where the decoder jumps from the end of an A–B loop back to its start
+    ab_loop: Option<(f64, f64)>,
+}
+
+/// What one call to `SampleBuffer::pop_into` played
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Played {
+    /// samples written to the output
+    pub written: usize,
+    /// playback crossed into the next track, the cursor restarted from its beginning
+    pub track_changed: bool,
+    /// the decoder is done and every sample has been played
+    pub finished: bool,
+    /// where the cursor is afterwards, as in `SampleBuffer::position`
+    pub position: usize,
+}
+
+/// Bounded queue of interleaved samples shared between the decoder thread
+/// (producer) and the cpal output callback (consumer).
+///
+/// Memory use is capped at `capacity` samples regardless of the length of the file.
+#[derive(Debug)]
+pub struct SampleBuffer<T> {
+    inner: Mutex<SampleBufferInner<T>>,
+    space_available: Condvar,
+    capacity: usize,
+}
+
+impl<T> SampleBuffer<T>
+where
+    T: Copy,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(SampleBufferInner {
+                samples: VecDeque::with_capacity(capacity),
+                position: 0.0,
+                speed: 1.0,
+                end_of_stream: false,
+                pushed: 0,
+                popped: 0,
+                track_starts: VecDeque::new(),
+                ab_loop: None,
+            }),
+            space_available: Condvar::new(),
+            capacity,
+        }
+    }
+
+    fn inner(&self) -> MutexGuard<'_, SampleBufferInner<T>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn has_space(&self, inner: &SampleBufferInner<T>, count: usize) -> bool {
+        // an empty buffer always accepts samples, so oversized packets can't stall the decoder
+        inner.samples.is_empty() || inner.samples.len() + count <= self.capacity
+    }
+
+    /// Appends `samples` if there is room for all of them
+    pub fn try_push(&self, samples: &[T]) -> bool {
+        let mut inner = self.inner();
+        if !self.has_space(&inner, samples.len()) {
+            return false;
+        }
+        inner.samples.extend(samples.iter().copied());
+        inner.pushed += samples.len();
+        true
+    }
+
+    /// Blocks until there is room for `count` samples or `timeout` elapses
+    pub fn wait_for_space(&self, count: usize, timeout: Duration) {
+        let inner = self.inner();
+        if self.has_space(&inner, count) {
+            return;
+        }
+        let _ = self.space_available.wait_timeout(inner, timeout);
+    }
+
+    /// Fills `data` with as many buffered samples as possible, passing each through `f`.
+    ///
+    /// Never waits on the decoder, `None` means it had the buffer and nothing was played
+    pub fn pop_into<U, F>(&self, data: &mut [U], mut f: F) -> Option<Played>
+    where
+        F: FnMut(T) -> U,
+    {
+        let mut inner = match self.inner.try_lock() {
+            Ok(inner) => inner,
+            Err(TryLockError::Poisoned(err)) => err.into_inner(),
+            Err(TryLockError::WouldBlock) => return None,
+        };
+        let mut written = 0;
+        let mut track_changed = false;
+        loop {
+            let mut count = (data.len() - written).min(inner.samples.len());
+            if let Some(track_start) = inner.track_starts.front() {
+                count = count.min(track_start - inner.popped);
+            }
+            let data = &mut data[written..written + count];
+            for (d, s) in data.iter_mut().zip(inner.samples.drain(..count)) {
+                *d = f(s);
+            }
+            written += count;
+            inner.popped += count;
+            let previous = inner.position;
+            inner.position += count as f64 * inner.speed;
+            if let Some((start, end)) = inner.ab_loop
+                && previous < end
+                && inner.position >= end
+            {
+                inner.position -= end - start;
+            }
+
+            if inner.track_starts.front() == Some(&inner.popped) {
+                inner.track_starts.pop_front();
+                inner.position = 0.0;
+                track_changed = true;
+                continue;
+            }
+            break;
+        }
+        let played = Played {
+            written,
+            track_changed,
+            finished: inner.end_of_stream && inner.samples.is_empty(),
+            position: inner.position as usize,
+        };
+        drop(inner);
+
+        if written > 0 {
+            self.space_available.notify_one();
+        }
+        Some(played)
+    }
+
+    /// Samples pushed from now on belong to the next track
+    pub fn mark_track_start(&self) {
+        let mut inner = self.inner();
+        let pushed = inner.pushed;
+        inner.track_starts.push_back(pushed);
+        inner.end_of_stream = false;
+    }
+
+    /// Playback has yet to reach a track marked with `mark_track_start`
+    pub fn has_pending_track_start(&self) -> bool {
+        !self.inner().track_starts.is_empty()
+    }
+
+    pub fn set_end_of_stream(&self) {
+        self.inner().end_of_stream = true;
+    }
+
+    pub fn position(&self) -> usize {
+        self.inner().position as usize
+    }
+
+    /// Samples pushed after reaching position `end` continue from `start`,
+    /// as the decoder loops back at `end`
+    pub fn set_loop(&self, ab_loop: Option<(usize, usize)>) {
+        self.inner().ab_loop = ab_loop.map(|(start, end)| (start as f64, end as f64));
+    }
+
+    /// Samples pushed from now on are played at `speed`
+    pub fn set_speed(&self, speed: f64) {
+        self.inner().speed = speed;
+    }
+}
+
+impl SampleBuffer<f32> {
+    /// Drops buffered samples and moves the cursor to `position`.
+    /// The next `frames` frames are kept and faded to silence,
+    /// so jumping to `position` doesn't click
+    pub fn reset_faded(&self, position: usize, frames: usize, channels: usize) {
+        let mut inner = self.inner();
+        let kept = (frames * channels).min(inner.samples.len());
+        inner.samples.truncate(kept);
+        let mut ramp = Ramp::new(kept / channels, 1.0);
+        ramp.fade_to(0.0);
+        ramp.apply(inner.samples.make_contiguous(), channels);
+        // the faded out samples lead up to `position`
+        let lead = (kept as f64 * inner.speed) as usize;
+        inner.position = position.saturating_sub(lead) as f64;
+        inner.end_of_stream = false;
+        inner.pushed = kept;
+        inner.popped = 0;
+        inner.track_starts.clear();
+        drop(inner);
+        self.space_available.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Instant;
+
+    use super::*;
+
+    fn pop(buffer: &SampleBuffer<f32>, count: usize) -> (Vec<f32>, Played) {
+        let mut data = vec![0.0; count];
+        let played = buffer.pop_into(&mut data, |s| s).unwrap();
+        data.truncate(played.written);
+        (data, played)
+    }
+
+    #[test]
+    fn full_buffer_turns_samples_away() {
+        let buffer = SampleBuffer::new(4);
+        assert!(buffer.try_push(&[0.0; 3]));
+        assert!(!buffer.try_push(&[0.0; 2]));
+        assert!(buffer.try_push(&[0.0; 1]));
+
+        // an empty buffer takes anything, however big
+        pop(&buffer, 4);
+        assert!(buffer.try_push(&[0.0; 10]));
+    }
+
+    #[test]
+    fn waiting_for_space_ends_once_samples_are_played() {
+        let buffer = Arc::new(SampleBuffer::new(4));
+        assert!(buffer.try_push(&[0.0; 4]));
+
+        let start = Instant::now();
+        buffer.wait_for_space(2, Duration::from_millis(50));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let player = {
+            let buffer = buffer.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                pop(&buffer, 2);
+            })
+        };
+        let start = Instant::now();
+        buffer.wait_for_space(2, Duration::from_secs(5));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        player.join().unwrap();
+        assert!(buffer.try_push(&[0.0; 2]));
+    }
+
+    #[test]
+    fn next_track_starts_from_zero() {
+        let buffer = SampleBuffer::new(100);
+        buffer.try_push(&[1.0; 4]);
+        buffer.mark_track_start();
+        buffer.try_push(&[2.0; 4]);
+        assert!(buffer.has_pending_track_start());
+
+        let (samples, played) = pop(&buffer, 2);
+        assert_eq!((samples, played.track_changed), (vec![1.0; 2], false));
+        // crosses into the next track part way through
+        let (samples, played) = pop(&buffer, 4);
+        assert_eq!(samples, vec![1.0, 1.0, 2.0, 2.0]);
+        assert!(played.track_changed);
+        assert_eq!(played.position, 2);
+        assert!(!buffer.has_pending_track_start());
+    }
+
+    #[test]
+    fn track_starting_at_the_end_of_a_pop_is_reported() {
+        let buffer = SampleBuffer::new(100);
+        buffer.try_push(&[1.0; 4]);
+        buffer.mark_track_start();
+        buffer.try_push(&[2.0; 4]);
+
+        let (samples, played) = pop(&buffer, 4);
+        assert_eq!(samples, vec![1.0; 4]);
+        assert!(played.track_changed);
+        assert_eq!(played.position, 0);
+    }
+
+    #[test]
+    fn end_of_stream_waits_for_the_buffer_to_drain() {
+        let buffer = SampleBuffer::new(100);
+        buffer.try_push(&[0.0; 4]);
+        buffer.set_end_of_stream();
+        assert!(!pop(&buffer, 0).1.finished);
+        assert!(pop(&buffer, 4).1.finished);
+    }
+
+    #[test]
+    fn playing_never_waits_on_the_decoder() {
+        let buffer = SampleBuffer::new(100);
+        buffer.try_push(&[1.0; 4]);
+
+        let decoder = buffer.inner();
+        assert_eq!(buffer.pop_into(&mut [0.0; 4], |s| s), None);
+        drop(decoder);
+        assert_eq!(pop(&buffer, 4).0, vec![1.0; 4]);
+    }
+
+    #[test]
+    fn reset_fades_out_what_it_keeps() {
+        let buffer = SampleBuffer::new(100);
+        buffer.try_push(&[1.0; 20]);
+        buffer.mark_track_start();
+        buffer.set_end_of_stream();
+
+        // keeps 4 stereo frames leading up to sample 100
+        buffer.reset_faded(100, 4, 2);
+        assert!(!buffer.has_pending_track_start());
+        assert_eq!(buffer.position(), 92);
+
+        let (samples, played) = pop(&buffer, 20);
+        assert!(!played.finished);
+        assert_eq!(samples.len(), 8);
+        let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+        assert!(left.windows(2).all(|pair| pair[1] < pair[0]), "{left:?}");
+        assert!(left[3] < 0.5, "{left:?}");
+        assert_eq!(buffer.position(), 100);
+
+        // the decoder carries on from there
+        buffer.try_push(&[1.0; 2]);
+        pop(&buffer, 2);
+        assert_eq!(buffer.position(), 102);
+    }
+
+    #[test]
+    fn position_follows_playback_speed() {
+        let buffer = SampleBuffer::new(100);
+        buffer.try_push(&[0.0; 10]);
+        pop(&buffer, 4);
+        assert_eq!(buffer.position(), 4);
+
+        buffer.set_speed(2.0);
+        pop(&buffer, 4);
+        assert_eq!(buffer.position(), 12);
+    }
+
+    #[test]
+    fn position_wraps_around_the_loop() {
+        let buffer = SampleBuffer::new(100);
+        buffer.set_loop(Some((2, 6)));
+        buffer.try_push(&[0.0; 20]);
+        pop(&buffer, 5);
+        assert_eq!(buffer.position(), 5);
+        pop(&buffer, 3);
+        assert_eq!(buffer.position(), 4);
+        // only reaching B goes back, not being past it
+        pop(&buffer, 1);
+        assert_eq!(buffer.position(), 5);
+    }
+}
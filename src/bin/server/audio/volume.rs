@@ -0,0 +1,113 @@
+use rand::RngExt;
+use rand::rngs::SmallRng;
+
+use crate::audio::replaygain::db_to_linear;
+
+/// Loudest to quietest step of the dB volume curve
+const DECIBEL_RANGE: f32 = 60.0;
+
+/// How the 0-100 volume level maps to the gain applied to samples
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VolumeCurve {
+    Linear,
+    /// gain is the cube of the volume, roughly matches how loudness is perceived
+    #[default]
+    Cubic,
+    /// every volume step changes the gain by the same number of dB
+    Decibel,
+}
+
+impl VolumeCurve {
+    /// Linear gain for `volume`, which goes from 0 to 100
+    pub fn gain(&self, volume: usize) -> f32 {
+        let volume = volume.min(100) as f32 / 100.0;
+        match self {
+            Self::Linear => volume,
+            Self::Cubic => volume.powi(3),
+            Self::Decibel if volume == 0.0 => 0.0,
+            Self::Decibel => db_to_linear((volume - 1.0) * DECIBEL_RANGE),
+        }
+    }
+}
+
+/// Adds triangular (TPDF) noise of up to one least significant bit of the output format,
+/// so rounding to fewer bits doesn't correlate with the signal
+#[derive(Clone, Debug)]
+pub struct Dither {
+    // size of one output step, with full scale being 1.0
+    lsb: f32,
+    rng: SmallRng,
+}
+
+impl Dither {
+    /// `None` for formats that don't lose precision when converting from f32
+    pub fn new(format: cpal::SampleFormat) -> Option<Self> {
+        let bits = match format {
+            cpal::SampleFormat::I8 | cpal::SampleFormat::U8 => 8,
+            cpal::SampleFormat::I16 | cpal::SampleFormat::U16 => 16,
+            _ => return None,
+        };
+        Some(Self {
+            lsb: 2.0_f32.powi(1 - bits),
+            rng: rand::make_rng(),
+        })
+    }
+
+    pub fn apply(&mut self, sample: f32) -> f32 {
+        let noise = self.rng.random::<f32>() - self.rng.random::<f32>();
+        sample + noise * self.lsb
+    }
+}
+
+/// The gain stage: applies `gain` to a sample and converts it to the output format.
+///
+/// Gain is applied while the sample is still centered on zero,
+/// so unsigned formats are scaled around their midpoint.
+pub fn to_output_sample<T>(sample: f32, gain: f32, dither: Option<&mut Dither>) -> T
+where
+    T: cpal::Sample + cpal::FromSample<f32>,
+{
+    let mut sample = sample * gain;
+    if let Some(dither) = dither {
+        sample = dither.apply(sample);
+    }
+    T::from_sample(sample.clamp(-1.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curves_span_silence_to_full_volume() {
+        for curve in [
+            VolumeCurve::Linear,
+            VolumeCurve::Cubic,
+            VolumeCurve::Decibel,
+        ] {
+            assert_eq!(curve.gain(0), 0.0);
+            assert_eq!(curve.gain(100), 1.0);
+            assert!(curve.gain(50) > curve.gain(49));
+        }
+        let half = 20.0 * VolumeCurve::Decibel.gain(50).log10();
+        assert!((half + DECIBEL_RANGE / 2.0).abs() < 1e-3, "{half}");
+    }
+
+    #[test]
+    fn unsigned_formats_scale_around_midpoint() {
+        assert_eq!(to_output_sample::<u8>(0.0, 0.5, None), 128);
+        assert_eq!(to_output_sample::<u16>(0.0, 0.25, None), 32768);
+        assert_eq!(to_output_sample::<u8>(1.0, 0.5, None), 192);
+        assert_eq!(to_output_sample::<i16>(-1.0, 0.5, None), -16384);
+    }
+
+    #[test]
+    fn dither_stays_within_one_step() {
+        let mut dither = Dither::new(cpal::SampleFormat::I16).unwrap();
+        for _ in 0..10000 {
+            let sample: i16 = to_output_sample(0.0, 1.0, Some(&mut dither));
+            assert!(sample.abs() <= 1, "{sample}");
+        }
+        assert!(Dither::new(cpal::SampleFormat::F32).is_none());
+    }
+}
@@ -1,14 +1,20 @@
 use std::io::{BufRead, BufReader, Write};
-use std::os::unix::net::UnixStream;
+use std::net::Shutdown;
 use std::sync::mpsc;
 use std::thread;
+use std::time::Duration;
 
-use dizi::error::AppResult;
-use dizi::request::client::ClientRequest;
+use dizi::error::{AppResult, DiziError, DiziErrorKind};
+use dizi::protocol;
+use dizi::request::client::{ClientRequest, ClientRequestMessage};
 use dizi::response::server::ServerBroadcastEvent;
-use dizi::utils;
+use dizi::utils::{self, DiziStream};
 
 use crate::events::{ClientRequestSender, ServerBroadcastEventReceiver};
+use crate::util::auth::tokens_match;
+
+/// How long a client that has to authenticate gets to do it
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Clone, Debug)]
 pub enum ClientMessage {
@@ -18,10 +24,26 @@ pub enum ClientMessage {
 
 pub fn handle_client(
     uuid: uuid::Uuid,
-    mut stream: UnixStream,
+    mut stream: DiziStream,
+    token: Option<String>,
     client_request_tx: ClientRequestSender,
     server_event_rx: ServerBroadcastEventReceiver,
 ) -> AppResult {
+    let uuid_string = uuid.to_string();
+    let mut cursor = BufReader::new(stream.try_clone().expect("Failed to clone stream"));
+
+    // nothing goes either way until a client with a token to give has given it
+    if let Some(token) = token.as_deref()
+        && let Err(err) = authenticate(&mut cursor, &mut stream, token)
+    {
+        tracing::debug!(?err, "Client failed to authenticate");
+        let _ = stream.shutdown(Shutdown::Both);
+        client_request_tx.send((uuid_string, client_leave(&uuid)))?;
+        return Ok(());
+    }
+    // whatever was broadcast before then isn't for this client
+    while server_event_rx.try_recv().is_ok() {}
+
     let (event_tx, event_rx) = mpsc::channel();
 
     // listen for events broadcasted by the server
@@ -37,13 +59,9 @@ pub fn handle_client(
         }
     });
 
-    let uuid_string = uuid.to_string();
-
     // listen for requests sent by client
     let event_tx_clone = event_tx;
-    let stream_clone = stream.try_clone().expect("Failed to clone UnixStream");
     let _ = thread::spawn(move || {
-        let cursor = BufReader::new(stream_clone);
         // keep listening for client requests
         for line in cursor.lines().map_while(Result::ok) {
             if event_tx_clone.send(ClientMessage::Client(line)).is_err() {
@@ -51,13 +69,14 @@ pub fn handle_client(
             }
         }
 
-        let response = ClientRequest::ClientLeave {
-            uuid: uuid.to_string(),
-        };
-        let json = serde_json::to_string(&response).expect("Failed to serialize ClientRequest");
+        let json =
+            serde_json::to_string(&client_leave(&uuid)).expect("Failed to serialize ClientRequest");
         let _ = event_tx_clone.send(ClientMessage::Client(json));
     });
 
+    // clients that never say hello don't know about replies
+    let mut said_hello = false;
+
     // process events
     while let Ok(event) = event_rx.recv() {
         match event {
@@ -68,27 +87,170 @@ pub fn handle_client(
                 if line.is_empty() {
                     continue;
                 }
-                forward_client_request(&client_request_tx, &uuid_string, &line)?;
+                let res = serde_json::from_str(&line)
+                    .map_err(DiziError::from)
+                    .and_then(check_hello);
+                let message = match res {
+                    Ok(message) => message,
+                    Err(err) => {
+                        tracing::debug!(?err, line, "Invalid client request");
+                        process_server_event(&mut stream, &error_reply(&line, &err, said_hello))?;
+                        // there's no talking to a client too old for us
+                        if matches!(err.kind(), DiziErrorKind::IncompatibleProtocol) {
+                            let _ = stream.shutdown(Shutdown::Both);
+                            client_request_tx.send((uuid_string.clone(), client_leave(&uuid)))?;
+                            return Ok(());
+                        }
+                        continue;
+                    }
+                };
+                // tokens are only ever checked in `authenticate`, keep them away from the server loop
+                if let ClientRequest::ClientAuth { .. } = message.request {
+                    if said_hello || message.id.is_some() {
+                        let reply = ServerBroadcastEvent::Reply {
+                            id: message.id,
+                            result: Ok(None),
+                        };
+                        process_server_event(&mut stream, &reply)?;
+                    }
+                    continue;
+                }
+                said_hello |= matches!(message.request, ClientRequest::ServerHello { .. });
+                client_request_tx.send((uuid_string.clone(), message))?;
             }
         }
     }
     Ok(())
 }
 
-/// Forwards client requests to the server via `ClientRequestSender`
-pub fn forward_client_request(
-    client_request_tx: &ClientRequestSender,
-    uuid: &str,
-    line: &str,
+/// Turns away hellos from clients speaking a protocol too old for us
+fn check_hello(message: ClientRequestMessage) -> AppResult<ClientRequestMessage> {
+    if let ClientRequest::ServerHello { version, .. } = message.request
+        && !protocol::is_compatible(version)
+    {
+        return Err(DiziError::new(
+            DiziErrorKind::IncompatibleProtocol,
+            format!(
+                "Client speaks protocol version {version}, dizi-server speaks {}",
+                protocol::supported_versions()
+            ),
+        ));
+    }
+    Ok(message)
+}
+
+/// Reads the client's first request, which has to be `/client/auth` with the right token.
+///
+/// It's answered right here, so the token never reaches the server loop
+fn authenticate(
+    cursor: &mut BufReader<DiziStream>,
+    stream: &mut DiziStream,
+    token: &str,
 ) -> AppResult {
-    let request: ClientRequest = serde_json::from_str(line)?;
-    client_request_tx.send((uuid.to_string(), request))?;
-    Ok(())
+    // don't let anyone hold a connection open without authenticating
+    stream.set_read_timeout(Some(AUTH_TIMEOUT))?;
+    let mut line = String::new();
+    cursor.read_line(&mut line)?;
+    let line = line.trim_end();
+
+    let res = serde_json::from_str(line)
+        .map_err(DiziError::from)
+        .and_then(|message| check_auth(message, token));
+    match res {
+        Ok(message) => {
+            stream.set_read_timeout(None)?;
+            if message.id.is_some() {
+                let reply = ServerBroadcastEvent::Reply {
+                    id: message.id,
+                    result: Ok(None),
+                };
+                process_server_event(stream, &reply)?;
+            }
+            Ok(())
+        }
+        Err(err) => {
+            let _ = process_server_event(stream, &error_reply(line, &err, false));
+            Err(err)
+        }
+    }
 }
 
-pub fn process_server_event(stream: &mut UnixStream, event: &ServerBroadcastEvent) -> AppResult {
+/// Lets the client know which of its requests didn't go through
+fn error_reply(line: &str, err: &DiziError, said_hello: bool) -> ServerBroadcastEvent {
+    let id = serde_json::from_str::<serde_json::Value>(line)
+        .ok()
+        .and_then(|value| value.get("id")?.as_u64());
+    if said_hello || id.is_some() {
+        ServerBroadcastEvent::Reply {
+            id,
+            result: Err(err.to_string()),
+        }
+    } else {
+        ServerBroadcastEvent::ServerError {
+            msg: err.to_string(),
+        }
+    }
+}
+
+/// Lets through nothing but `/client/auth` with the right token
+fn check_auth(message: ClientRequestMessage, token: &str) -> AppResult<ClientRequestMessage> {
+    match &message.request {
+        ClientRequest::ClientAuth { token: given } if tokens_match(&given.0, token) => Ok(message),
+        ClientRequest::ClientAuth { .. } => Err(DiziError::new(
+            DiziErrorKind::Unauthorized,
+            "Wrong token".to_string(),
+        )),
+        _ => Err(DiziError::new(
+            DiziErrorKind::Unauthorized,
+            "Authenticate with /client/auth first".to_string(),
+        )),
+    }
+}
+
+fn client_leave(uuid: &uuid::Uuid) -> ClientRequestMessage {
+    ClientRequestMessage {
+        id: None,
+        request: ClientRequest::ClientLeave {
+            uuid: uuid.to_string(),
+        },
+    }
+}
+
+pub fn process_server_event(stream: &mut DiziStream, event: &ServerBroadcastEvent) -> AppResult {
     let json = serde_json::to_string(&event)?;
     stream.write_all(json.as_bytes())?;
     utils::flush(stream)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dizi::request::client::AuthToken;
+
+    fn message(request: ClientRequest) -> ClientRequestMessage {
+        ClientRequestMessage { id: None, request }
+    }
+
+    #[test]
+    fn only_the_right_token_gets_through() {
+        let auth = |token: &str| {
+            message(ClientRequest::ClientAuth {
+                token: AuthToken(token.to_string()),
+            })
+        };
+        assert!(check_auth(auth("secret"), "secret").is_ok());
+        assert!(check_auth(auth("secreT"), "secret").is_err());
+        assert!(check_auth(auth("secret2"), "secret").is_err());
+        assert!(check_auth(auth(""), "secret").is_err());
+        assert!(check_auth(message(ClientRequest::PlayerPause), "secret").is_err());
+    }
+
+    #[test]
+    fn tokens_stay_out_of_debug_output() {
+        let request = ClientRequest::ClientAuth {
+            token: AuthToken("secret".to_string()),
+        };
+        assert!(!format!("{request:?}").contains("secret"));
+    }
+}
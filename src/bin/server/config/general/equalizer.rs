@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::convert::From;
+
+use serde::Deserialize;
+
+use crate::audio::equalizer::{EqBand, EqFilter, EqPreset};
+
+fn default_eq_filter_string() -> String {
+    "peaking".to_string()
+}
+
+const fn default_q() -> f32 {
+    std::f32::consts::FRAC_1_SQRT_2
+}
+
+fn str_to_eq_filter(s: &str) -> Option<EqFilter> {
+    match s {
+        "peaking" => Some(EqFilter::Peaking),
+        "low_shelf" => Some(EqFilter::LowShelf),
+        "high_shelf" => Some(EqFilter::HighShelf),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EqBandRaw {
+    #[serde(default = "default_eq_filter_string")]
+    pub filter: String,
+    pub frequency: f32,
+    #[serde(default)]
+    pub gain: f32,
+    #[serde(default = "default_q")]
+    pub q: f32,
+}
+
+impl From<EqBandRaw> for EqBand {
+    fn from(raw: EqBandRaw) -> Self {
+        Self {
+            filter: str_to_eq_filter(&raw.filter.to_lowercase()).unwrap_or_default(),
+            frequency: raw.frequency,
+            gain: raw.gain,
+            q: raw.q,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct EqPresetRaw {
+    #[serde(default)]
+    pub bands: Vec<EqBandRaw>,
+}
+
+/// Presets sorted by name
+pub fn eq_presets_from_raw(raw: HashMap<String, EqPresetRaw>) -> Vec<EqPreset> {
+    let mut presets: Vec<EqPreset> = raw
+        .into_iter()
+        .map(|(name, preset)| EqPreset {
+            name,
+            bands: preset.bands.into_iter().map(EqBand::from).collect(),
+        })
+        .collect();
+    presets.sort_by(|a, b| a.name.cmp(&b.name));
+    presets
+}
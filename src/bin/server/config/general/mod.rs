@@ -1,7 +1,12 @@
 pub mod app;
+pub mod equalizer;
+pub mod mpd;
 pub mod player;
 pub mod server;
+pub mod tcp;
 
 pub use self::app::*;
+pub use self::mpd::*;
 pub use self::player::*;
 pub use self::server::*;
+pub use self::tcp::*;
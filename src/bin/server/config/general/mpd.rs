@@ -0,0 +1,69 @@
+use std::convert::From;
+use std::fmt;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use shellexpand::tilde_with_context;
+
+use crate::HOME_DIR;
+
+fn default_address() -> String {
+    "127.0.0.1".to_string()
+}
+
+const fn default_port() -> u16 {
+    6600
+}
+
+fn default_music_directory_string() -> String {
+    "~/music".to_string()
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MpdOptionRaw {
+    #[serde(default = "default_address")]
+    pub address: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default = "default_music_directory_string")]
+    pub music_directory: String,
+}
+
+#[derive(Clone)]
+pub struct MpdOption {
+    /// address to listen on, like "0.0.0.0" for every interface
+    pub address: String,
+    pub port: u16,
+    /// clients have to send `password` with this before anything else
+    pub password: Option<String>,
+    /// where relative song paths start from, like MPD's own
+    pub music_directory: PathBuf,
+}
+
+impl From<MpdOptionRaw> for MpdOption {
+    fn from(raw: MpdOptionRaw) -> Self {
+        let home_dir_func = || HOME_DIR.as_ref().map(|s| s.to_string_lossy());
+        let music_directory = tilde_with_context(&raw.music_directory, home_dir_func);
+
+        Self {
+            address: raw.address,
+            port: raw.port,
+            password: raw.password.filter(|password| !password.is_empty()),
+            music_directory: PathBuf::from(music_directory.as_ref()),
+        }
+    }
+}
+
+// the config gets logged on startup, the password shouldn't be
+impl fmt::Debug for MpdOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MpdOption")
+            .field("address", &self.address)
+            .field("port", &self.port)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("music_directory", &self.music_directory)
+            .finish()
+    }
+}
@@ -1,5 +1,16 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
 use serde::Deserialize;
 
+use crate::audio::equalizer::EqPreset;
+use crate::audio::fade::FadeCurve;
+use crate::audio::replaygain::{ReplayGain, ReplayGainMode};
+use crate::audio::speed::{MAX_SPEED, MIN_SPEED, Speed, SpeedMode};
+use crate::audio::volume::VolumeCurve;
+
+use super::equalizer::{EqPresetRaw, eq_presets_from_raw};
+
 const fn default_true() -> bool {
     true
 }
@@ -8,6 +19,80 @@ const fn default_volume() -> usize {
     50
 }
 
+const fn default_fade() -> f32 {
+    0.03
+}
+
+const fn default_sleep_fade() -> f32 {
+    30.0
+}
+
+fn default_crossfade_curve_string() -> String {
+    "linear".to_string()
+}
+
+fn default_replaygain_string() -> String {
+    "off".to_string()
+}
+
+fn str_to_replaygain_mode(s: &str) -> Option<ReplayGainMode> {
+    match s {
+        "off" => Some(ReplayGainMode::Off),
+        "track" => Some(ReplayGainMode::Track),
+        "album" => Some(ReplayGainMode::Album),
+        _ => None,
+    }
+}
+
+fn default_volume_curve_string() -> String {
+    "cubic".to_string()
+}
+
+fn str_to_volume_curve(s: &str) -> Option<VolumeCurve> {
+    match s {
+        "linear" => Some(VolumeCurve::Linear),
+        "cubic" => Some(VolumeCurve::Cubic),
+        "db" => Some(VolumeCurve::Decibel),
+        _ => None,
+    }
+}
+
+const fn default_speed() -> f32 {
+    1.0
+}
+
+fn default_speed_mode_string() -> String {
+    "stretch".to_string()
+}
+
+fn str_to_speed_mode(s: &str) -> Option<SpeedMode> {
+    match s {
+        "stretch" => Some(SpeedMode::Stretch),
+        "resample" => Some(SpeedMode::Resample),
+        _ => None,
+    }
+}
+
+const fn default_spectrum_rate() -> u32 {
+    30
+}
+
+const fn default_spectrum_bands() -> usize {
+    32
+}
+
+const fn default_progress_interval_ms() -> u64 {
+    1000
+}
+
+fn str_to_fade_curve(s: &str) -> Option<FadeCurve> {
+    match s {
+        "linear" => Some(FadeCurve::Linear),
+        "equal_power" => Some(FadeCurve::EqualPower),
+        _ => None,
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct PlayerOptionRaw {
     #[serde(default)]
@@ -18,6 +103,40 @@ pub struct PlayerOptionRaw {
     pub next: bool,
     #[serde(default = "default_volume")]
     pub volume: usize,
+    #[serde(default = "default_volume_curve_string")]
+    pub volume_curve: String,
+    #[serde(default)]
+    pub dither: bool,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    #[serde(default = "default_speed_mode_string")]
+    pub speed_mode: String,
+    #[serde(default = "default_fade")]
+    pub fade: f32,
+    #[serde(default)]
+    pub crossfade: f32,
+    #[serde(default = "default_crossfade_curve_string")]
+    pub crossfade_curve: String,
+    #[serde(default = "default_sleep_fade")]
+    pub sleep_fade: f32,
+    #[serde(default)]
+    pub album_gapless: bool,
+    #[serde(default = "default_replaygain_string")]
+    pub replaygain: String,
+    #[serde(default)]
+    pub replaygain_preamp: f32,
+    #[serde(default = "default_true")]
+    pub replaygain_prevent_clipping: bool,
+    #[serde(default)]
+    pub equalizer: String,
+    #[serde(default)]
+    pub equalizer_presets: HashMap<String, EqPresetRaw>,
+    #[serde(default = "default_spectrum_rate")]
+    pub spectrum_rate: u32,
+    #[serde(default = "default_spectrum_bands")]
+    pub spectrum_bands: usize,
+    #[serde(default = "default_progress_interval_ms")]
+    pub progress_interval_ms: u64,
 }
 
 impl std::default::Default for PlayerOptionRaw {
@@ -27,6 +146,23 @@ impl std::default::Default for PlayerOptionRaw {
             repeat: true,
             next: true,
             volume: default_volume(),
+            volume_curve: default_volume_curve_string(),
+            dither: false,
+            speed: default_speed(),
+            speed_mode: default_speed_mode_string(),
+            fade: default_fade(),
+            crossfade: 0.0,
+            crossfade_curve: default_crossfade_curve_string(),
+            sleep_fade: default_sleep_fade(),
+            album_gapless: false,
+            replaygain: default_replaygain_string(),
+            replaygain_preamp: 0.0,
+            replaygain_prevent_clipping: true,
+            equalizer: String::new(),
+            equalizer_presets: HashMap::new(),
+            spectrum_rate: default_spectrum_rate(),
+            spectrum_bands: default_spectrum_bands(),
+            progress_interval_ms: default_progress_interval_ms(),
         }
     }
 }
@@ -38,6 +174,27 @@ impl From<PlayerOptionRaw> for PlayerOption {
             repeat: crude.repeat,
             next: crude.next,
             volume: crude.volume,
+            volume_curve: str_to_volume_curve(&crude.volume_curve).unwrap_or_default(),
+            dither: crude.dither,
+            speed: Speed {
+                value: crude.speed.clamp(MIN_SPEED, MAX_SPEED),
+                mode: str_to_speed_mode(&crude.speed_mode).unwrap_or_default(),
+            },
+            fade: Duration::try_from_secs_f32(crude.fade).unwrap_or_default(),
+            crossfade: Duration::try_from_secs_f32(crude.crossfade).unwrap_or_default(),
+            crossfade_curve: str_to_fade_curve(&crude.crossfade_curve).unwrap_or_default(),
+            sleep_fade: Duration::try_from_secs_f32(crude.sleep_fade).unwrap_or_default(),
+            album_gapless: crude.album_gapless,
+            replaygain: ReplayGain {
+                mode: str_to_replaygain_mode(&crude.replaygain).unwrap_or_default(),
+                preamp: crude.replaygain_preamp,
+                prevent_clipping: crude.replaygain_prevent_clipping,
+            },
+            equalizer: (!crude.equalizer.is_empty()).then_some(crude.equalizer),
+            equalizer_presets: eq_presets_from_raw(crude.equalizer_presets),
+            spectrum_rate: crude.spectrum_rate.clamp(1, 60),
+            spectrum_bands: crude.spectrum_bands.clamp(1, 256),
+            progress_interval: Duration::from_millis(crude.progress_interval_ms.clamp(10, 60_000)),
         }
     }
 }
@@ -48,6 +205,26 @@ pub struct PlayerOption {
     pub repeat: bool,
     pub next: bool,
     pub volume: usize,
+    pub volume_curve: VolumeCurve,
+    pub dither: bool,
+    pub speed: Speed,
+    /// fade out and back in around pauses, stops, seeks and skips
+    pub fade: Duration,
+    pub crossfade: Duration,
+    pub crossfade_curve: FadeCurve,
+    /// how long the sleep timer takes to fade out before stopping
+    pub sleep_fade: Duration,
+    pub album_gapless: bool,
+    pub replaygain: ReplayGain,
+    /// name of the equalizer preset to start with
+    pub equalizer: Option<String>,
+    pub equalizer_presets: Vec<EqPreset>,
+    /// spectrum updates sent to visualizers per second
+    pub spectrum_rate: u32,
+    /// frequency bands in each spectrum update
+    pub spectrum_bands: usize,
+    /// how much playback passes between progress updates
+    pub progress_interval: Duration,
 }
 
 impl std::default::Default for PlayerOption {
@@ -57,6 +234,23 @@ impl std::default::Default for PlayerOption {
             repeat: true,
             next: true,
             volume: default_volume(),
+            volume_curve: VolumeCurve::default(),
+            dither: false,
+            speed: Speed::default(),
+            fade: Duration::from_secs_f32(default_fade()),
+            crossfade: Duration::ZERO,
+            crossfade_curve: FadeCurve::default(),
+            sleep_fade: Duration::from_secs_f32(default_sleep_fade()),
+            album_gapless: false,
+            replaygain: ReplayGain {
+                prevent_clipping: true,
+                ..ReplayGain::default()
+            },
+            equalizer: None,
+            equalizer_presets: Vec::new(),
+            spectrum_rate: default_spectrum_rate(),
+            spectrum_bands: default_spectrum_bands(),
+            progress_interval: Duration::from_millis(default_progress_interval_ms()),
         }
     }
 }
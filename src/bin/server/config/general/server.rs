@@ -1,12 +1,15 @@
 use std::convert::From;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use serde::Deserialize;
 use shellexpand::tilde_with_context;
 
 use crate::HOME_DIR;
+use crate::audio::device::{AudioSystem, RetryPolicy};
+use crate::audio::sink::SinkKind;
 
-use super::{PlayerOption, PlayerOptionRaw};
+use super::{MpdOption, MpdOptionRaw, PlayerOption, PlayerOptionRaw, TcpOption, TcpOptionRaw};
 
 fn default_socket_string() -> String {
     "~/dizi-server-socket".to_string()
@@ -16,6 +19,18 @@ fn default_playlist_string() -> String {
     "~/dizi-playlist.m3u".to_string()
 }
 
+fn default_loudness_cache_string() -> String {
+    "~/.cache/dizi/loudness.json".to_string()
+}
+
+const fn default_device_retries() -> usize {
+    5
+}
+
+const fn default_device_retry_interval() -> f32 {
+    2.0
+}
+
 fn default_socket_path() -> PathBuf {
     let s = default_socket_string();
 
@@ -30,6 +45,13 @@ fn default_playlist_path() -> PathBuf {
     PathBuf::from(tilde_with_context(&s, home_dir_func).as_ref())
 }
 
+fn default_loudness_cache_path() -> PathBuf {
+    let s = default_loudness_cache_string();
+
+    let home_dir_func = || HOME_DIR.as_ref().map(|s| s.to_string_lossy());
+    PathBuf::from(tilde_with_context(&s, home_dir_func).as_ref())
+}
+
 fn default_audio_system() -> cpal::HostId {
     #[cfg(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd"))]
     {
@@ -69,18 +91,46 @@ fn str_to_cpal_hostid(s: &str) -> Option<cpal::HostId> {
     Some(cpal::HostId::Asio)
 }
 
+/// Parses `null`, `wav:<path>` and `fifo:<path>`, falling back to an audio host
+fn str_to_audio_system(s: &str) -> Option<AudioSystem> {
+    let home_dir_func = || HOME_DIR.as_ref().map(|s| s.to_string_lossy());
+    let expand = |path: &str| PathBuf::from(tilde_with_context(path, home_dir_func).as_ref());
+
+    if s == "null" {
+        Some(AudioSystem::Sink(SinkKind::Null))
+    } else if let Some(path) = s.strip_prefix("wav:") {
+        Some(AudioSystem::Sink(SinkKind::Wav(expand(path))))
+    } else if let Some(path) = s.strip_prefix("fifo:") {
+        Some(AudioSystem::Sink(SinkKind::Fifo(expand(path))))
+    } else {
+        str_to_cpal_hostid(&s.to_lowercase()).map(AudioSystem::Host)
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct ServerConfigRaw {
     #[serde(default = "default_socket_string")]
     pub socket: String,
     #[serde(default = "default_playlist_string")]
     pub playlist: String,
+    #[serde(default = "default_loudness_cache_string")]
+    pub loudness_cache: String,
     #[serde(default = "default_audio_system_string")]
     pub audio_system: String,
     #[serde(default)]
+    pub device: Option<String>,
+    #[serde(default = "default_device_retries")]
+    pub device_retries: usize,
+    #[serde(default = "default_device_retry_interval")]
+    pub device_retry_interval: f32,
+    #[serde(default)]
     pub on_song_change: Option<String>,
     #[serde(default)]
     pub player: PlayerOptionRaw,
+    #[serde(default)]
+    pub tcp: Option<TcpOptionRaw>,
+    #[serde(default)]
+    pub mpd: Option<MpdOptionRaw>,
 }
 
 impl std::default::Default for ServerConfigRaw {
@@ -88,9 +138,15 @@ impl std::default::Default for ServerConfigRaw {
         Self {
             socket: default_socket_string(),
             playlist: default_playlist_string(),
+            loudness_cache: default_loudness_cache_string(),
             audio_system: default_audio_system_string(),
+            device: None,
+            device_retries: default_device_retries(),
+            device_retry_interval: default_device_retry_interval(),
             on_song_change: None,
             player: PlayerOptionRaw::default(),
+            tcp: None,
+            mpd: None,
         }
     }
 }
@@ -99,9 +155,18 @@ impl std::default::Default for ServerConfigRaw {
 pub struct ServerConfig {
     pub socket: PathBuf,
     pub playlist: PathBuf,
-    pub audio_system: cpal::HostId,
+    pub loudness_cache: PathBuf,
+    pub audio_system: AudioSystem,
+    /// output device to start with, the host's default if `None`
+    pub device: Option<String>,
+    /// reopening the output device after it fails
+    pub device_retry: RetryPolicy,
     pub on_song_change: Option<PathBuf>,
     pub player: PlayerOption,
+    /// also listen for clients over TCP
+    pub tcp: Option<TcpOption>,
+    /// also listen for MPD clients
+    pub mpd: Option<MpdOption>,
 }
 
 impl ServerConfig {
@@ -111,9 +176,18 @@ impl ServerConfig {
     pub fn playlist_ref(&self) -> &Path {
         self.playlist.as_path()
     }
+    pub fn loudness_cache_ref(&self) -> &Path {
+        self.loudness_cache.as_path()
+    }
     pub fn player_ref(&self) -> &PlayerOption {
         &self.player
     }
+    pub fn tcp_ref(&self) -> Option<&TcpOption> {
+        self.tcp.as_ref()
+    }
+    pub fn mpd_ref(&self) -> Option<&MpdOption> {
+        self.mpd.as_ref()
+    }
 }
 
 impl std::default::Default for ServerConfig {
@@ -121,22 +195,31 @@ impl std::default::Default for ServerConfig {
         Self {
             socket: default_socket_path(),
             playlist: default_playlist_path(),
-            audio_system: default_audio_system(),
+            loudness_cache: default_loudness_cache_path(),
+            audio_system: AudioSystem::Host(default_audio_system()),
+            device: None,
+            device_retry: RetryPolicy {
+                attempts: default_device_retries(),
+                interval: Duration::from_secs_f32(default_device_retry_interval()),
+            },
             on_song_change: None,
             player: PlayerOption::default(),
+            tcp: None,
+            mpd: None,
         }
     }
 }
 
 impl From<ServerConfigRaw> for ServerConfig {
     fn from(raw: ServerConfigRaw) -> Self {
-        let audio_system = str_to_cpal_hostid(&raw.audio_system.to_lowercase())
-            .unwrap_or_else(default_audio_system);
+        let audio_system = str_to_audio_system(&raw.audio_system)
+            .unwrap_or_else(|| AudioSystem::Host(default_audio_system()));
 
         let home_dir_func = || HOME_DIR.as_ref().map(|s| s.to_string_lossy());
 
         let socket = tilde_with_context(&raw.socket, home_dir_func);
         let playlist = tilde_with_context(&raw.playlist, home_dir_func);
+        let loudness_cache = tilde_with_context(&raw.loudness_cache, home_dir_func);
         let on_song_change = raw
             .on_song_change
             .map(|path| PathBuf::from(tilde_with_context(&path, home_dir_func).as_ref()));
@@ -144,9 +227,18 @@ impl From<ServerConfigRaw> for ServerConfig {
         Self {
             socket: PathBuf::from(socket.as_ref()),
             playlist: PathBuf::from(playlist.as_ref()),
+            loudness_cache: PathBuf::from(loudness_cache.as_ref()),
             audio_system,
+            device: raw.device,
+            device_retry: RetryPolicy {
+                attempts: raw.device_retries,
+                interval: Duration::try_from_secs_f32(raw.device_retry_interval)
+                    .unwrap_or_default(),
+            },
             on_song_change,
             player: PlayerOption::from(raw.player),
+            tcp: raw.tcp.map(TcpOption::from),
+            mpd: raw.mpd.map(MpdOption::from),
         }
     }
 }
@@ -0,0 +1,52 @@
+use std::convert::From;
+use std::fmt;
+
+use serde::Deserialize;
+
+fn default_address() -> String {
+    "127.0.0.1".to_string()
+}
+
+const fn default_port() -> u16 {
+    6655
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TcpOptionRaw {
+    #[serde(default = "default_address")]
+    pub address: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub token: String,
+}
+
+#[derive(Clone)]
+pub struct TcpOption {
+    /// address to listen on, like "0.0.0.0" for every interface
+    pub address: String,
+    pub port: u16,
+    /// shared secret clients have to give before anything else
+    pub token: String,
+}
+
+impl From<TcpOptionRaw> for TcpOption {
+    fn from(raw: TcpOptionRaw) -> Self {
+        Self {
+            address: raw.address,
+            port: raw.port,
+            token: raw.token,
+        }
+    }
+}
+
+// the config gets logged on startup, the token shouldn't be
+impl fmt::Debug for TcpOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TcpOption")
+            .field("address", &self.address)
+            .field("port", &self.port)
+            .field("token", &"<redacted>")
+            .finish()
+    }
+}
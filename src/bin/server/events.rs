@@ -1,20 +1,51 @@
-use std::collections::HashMap;
-use std::os::unix::net::UnixStream;
+use std::collections::{HashMap, HashSet};
+use std::net::TcpStream;
+use std::path::PathBuf;
 use std::sync::mpsc;
 use std::thread;
 use std::time;
 
-use dizi::request::client::ClientRequest;
+use dizi::error::{AppResult, DiziError, DiziErrorKind};
+use dizi::player::Spectrum;
+use dizi::protocol::{Feature, Topic};
+use dizi::request::client::{ClientRequest, ClientRequestMessage, RequestId};
 use dizi::response::server::ServerBroadcastEvent;
+use dizi::utils::DiziStream;
+
+use crate::audio::ab_loop::AbLoop;
+use crate::audio::loudness::cache::LoudnessCacheEntry;
 
 #[derive(Debug)]
 pub enum ServerEvent {
     // new client connected
-    NewClient(UnixStream),
+    NewClient(DiziStream),
+    // new MPD client connected
+    NewMpdClient(TcpStream),
     /// player duration update
     PlayerProgressUpdate(time::Duration),
+    /// song is almost done, time to queue up the next one
+    PlayerTrackEnding,
+    /// player moved on to the queued song
+    PlayerTrackChanged,
     /// song is done
     PlayerDone,
+    /// A–B loop points moved
+    PlayerLoopChanged(AbLoop),
+    /// levels of what's being played, for visualizers
+    PlayerSpectrum(Spectrum),
+    /// another second of the sleep timer has passed
+    SleepTick,
+    /// the output device failed and playback stopped
+    PlayerDeviceError(String),
+    /// time to try reopening the output device
+    DeviceRetry,
+    /// background loudness scan finished a file
+    LoudnessScanned {
+        path: PathBuf,
+        result: AppResult<LoudnessCacheEntry>,
+        scanned: usize,
+        total: usize,
+    },
 }
 
 #[derive(Debug)]
@@ -24,15 +55,31 @@ pub enum AppEvent {
     },
     Client {
         uuid: String,
+        // sent back with the reply, if the client gave one
+        id: Option<RequestId>,
         request: ClientRequest,
     },
 }
 
+/// What a client said it understands in its hello
+#[derive(Clone, Debug)]
+pub struct ClientHello {
+    // features both sides support
+    pub features: Vec<Feature>,
+}
+
+/// Where to send a client's events, and which ones it wants
+#[derive(Debug)]
+pub struct BroadcastListener {
+    pub server_tx: ServerBroadcastEventSender,
+    pub topics: HashSet<Topic>,
+}
+
 pub type AppEventReceiver = mpsc::Receiver<AppEvent>;
 
 /// Send client requests for the server to process
-pub type ClientRequestSender = mpsc::Sender<(String, ClientRequest)>;
-// pub type ClientRequestReceiver = mpsc::Receiver<(String, ClientRequest)>;
+pub type ClientRequestSender = mpsc::Sender<(String, ClientRequestMessage)>;
+// pub type ClientRequestReceiver = mpsc::Receiver<(String, ClientRequestMessage)>;
 
 pub type ServerEventSender = mpsc::Sender<ServerEvent>;
 // pub type ServerEventReceiver = mpsc::Receiver<ServerEvent>;
@@ -52,7 +99,9 @@ pub struct Events {
     // main listening loop
     pub app_event_rx: AppEventReceiver,
 
-    pub server_broadcast_listeners: HashMap<String, ServerBroadcastEventSender>,
+    pub server_broadcast_listeners: HashMap<String, BroadcastListener>,
+    // clients that said hello, the rest are taken to predate it
+    pub client_hellos: HashMap<String, ClientHello>,
 }
 
 impl Events {
@@ -71,8 +120,9 @@ impl Events {
             let event_tx = app_event_tx.clone();
             let _ = thread::spawn(move || {
                 loop {
-                    if let Ok((uuid, request)) = client_request_rx.recv() {
-                        let _ = event_tx.send(AppEvent::Client { uuid, request });
+                    if let Ok((uuid, message)) = client_request_rx.recv() {
+                        let ClientRequestMessage { id, request } = message;
+                        let _ = event_tx.send(AppEvent::Client { uuid, id, request });
                     }
                 }
             });
@@ -95,6 +145,7 @@ impl Events {
             server_event_tx,
             app_event_rx,
             server_broadcast_listeners: HashMap::new(),
+            client_hellos: HashMap::new(),
         }
     }
 
@@ -111,12 +162,90 @@ impl Events {
     }
 
     pub fn add_broadcast_listener(&mut self, uuid: String, server_tx: ServerBroadcastEventSender) {
-        self.server_broadcast_listeners.insert(uuid, server_tx);
+        let listener = BroadcastListener {
+            server_tx,
+            topics: Topic::DEFAULT.iter().copied().collect(),
+        };
+        self.server_broadcast_listeners.insert(uuid, listener);
+    }
+
+    /// Adds or removes `topics` for the client `uuid`.
+    ///
+    /// The spectrum is only for clients that asked for `Feature::Spectrum` in their hello,
+    /// or never said hello at all
+    pub fn subscribe(&mut self, uuid: &str, topics: &[Topic], subscribed: bool) -> AppResult {
+        if subscribed
+            && topics.contains(&Topic::Spectrum)
+            && !self.client_supports(uuid, Feature::Spectrum)
+        {
+            return Err(DiziError::new(
+                DiziErrorKind::IncompatibleProtocol,
+                "Spectrum wasn't agreed on in the hello".to_string(),
+            ));
+        }
+        let Some(listener) = self.server_broadcast_listeners.get_mut(uuid) else {
+            return Ok(());
+        };
+        for topic in topics {
+            if subscribed {
+                listener.topics.insert(*topic);
+            } else {
+                listener.topics.remove(topic);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether any client wants events of `topic`
+    pub fn is_subscribed(&self, topic: Topic) -> bool {
+        self.server_broadcast_listeners
+            .values()
+            .any(|listener| listener.topics.contains(&topic))
     }
 
+    /// Sends `event` to the client `uuid` only
+    pub fn send_event(&self, uuid: &str, event: ServerBroadcastEvent) {
+        if let Some(listener) = self.server_broadcast_listeners.get(uuid) {
+            let _ = listener.server_tx.send(event);
+        }
+    }
+
+    /// Answers a request from the client `uuid`.
+    ///
+    /// Clients that never said hello don't know about replies,
+    /// so they get the answer on its own and errors as `ServerError`
+    pub fn send_reply(
+        &self,
+        uuid: &str,
+        id: Option<RequestId>,
+        result: Result<Option<Box<ServerBroadcastEvent>>, String>,
+    ) {
+        if id.is_some() || self.client_hellos.contains_key(uuid) {
+            self.send_event(uuid, ServerBroadcastEvent::Reply { id, result });
+            return;
+        }
+        match result {
+            Ok(Some(payload)) => self.send_event(uuid, *payload),
+            Ok(None) => {}
+            Err(msg) => self.send_event(uuid, ServerBroadcastEvent::ServerError { msg }),
+        }
+    }
+
+    /// Whether the client `uuid` can take `feature`,
+    /// clients that never said hello get the benefit of the doubt
+    pub fn client_supports(&self, uuid: &str, feature: Feature) -> bool {
+        self.client_hellos
+            .get(uuid)
+            .is_none_or(|hello| hello.features.contains(&feature))
+    }
+
+    /// Sends `event` to every client subscribed to its topic
     pub fn broadcast_event(&mut self, event: ServerBroadcastEvent) {
+        let topic = event.topic();
         match &event {
-            ServerBroadcastEvent::PlayerState { .. } => {}
+            // sent many times a second
+            ServerBroadcastEvent::PlayerState { .. }
+            | ServerBroadcastEvent::PlayerSpectrum { .. } => {}
             event => {
                 tracing::debug!(
                     ?event,
@@ -125,8 +254,130 @@ impl Events {
                 );
             }
         }
-        for server_tx in self.server_broadcast_listeners.values() {
-            let _ = server_tx.send(event.clone());
+        for listener in self.server_broadcast_listeners.values() {
+            if topic.is_none_or(|topic| listener.topics.contains(&topic)) {
+                let _ = listener.server_tx.send(event.clone());
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replies_are_unwrapped_for_clients_that_never_said_hello() {
+        let mut events = Events::new();
+        let (legacy_tx, legacy_rx) = mpsc::channel();
+        let (hello_tx, hello_rx) = mpsc::channel();
+        events.add_broadcast_listener("legacy".to_string(), legacy_tx);
+        events.add_broadcast_listener("hello".to_string(), hello_tx);
+        events.client_hellos.insert(
+            "hello".to_string(),
+            ClientHello {
+                features: Vec::new(),
+            },
+        );
+
+        events.send_reply("legacy", None, Ok(None));
+        events.send_reply("legacy", None, Err("nope".to_string()));
+        events.send_reply("hello", None, Ok(None));
+        events.send_reply("hello", Some(2), Err("nope".to_string()));
+        assert!(matches!(
+            legacy_rx.try_recv(),
+            Ok(ServerBroadcastEvent::ServerError { msg }) if msg == "nope"
+        ));
+        assert!(legacy_rx.try_recv().is_err());
+        assert!(matches!(
+            hello_rx.try_recv(),
+            Ok(ServerBroadcastEvent::Reply {
+                id: None,
+                result: Ok(None)
+            })
+        ));
+        assert!(matches!(
+            hello_rx.try_recv(),
+            Ok(ServerBroadcastEvent::Reply {
+                id: Some(2),
+                result: Err(_)
+            })
+        ));
+    }
+
+    #[test]
+    fn spectrum_is_only_for_clients_that_asked_for_it() {
+        let mut events = Events::new();
+        for uuid in ["legacy", "without", "with"] {
+            events.add_broadcast_listener(uuid.to_string(), mpsc::channel().0);
+        }
+        events.client_hellos.insert(
+            "without".to_string(),
+            ClientHello {
+                features: Vec::new(),
+            },
+        );
+        events.client_hellos.insert(
+            "with".to_string(),
+            ClientHello {
+                features: vec![Feature::Spectrum],
+            },
+        );
+
+        assert!(
+            events
+                .subscribe("without", &[Topic::Spectrum], true)
+                .is_err()
+        );
+        assert!(!events.is_subscribed(Topic::Spectrum));
+        // turning it off is always fine
+        assert!(
+            events
+                .subscribe("without", &[Topic::Spectrum], false)
+                .is_ok()
+        );
+
+        assert!(events.subscribe("with", &[Topic::Spectrum], true).is_ok());
+        assert!(events.subscribe("legacy", &[Topic::Spectrum], true).is_ok());
+        let subscribed: Vec<&str> = ["legacy", "without", "with"]
+            .into_iter()
+            .filter(|uuid| {
+                events.server_broadcast_listeners[*uuid]
+                    .topics
+                    .contains(&Topic::Spectrum)
+            })
+            .collect();
+        assert_eq!(subscribed, ["legacy", "with"]);
+    }
+
+    #[test]
+    fn broadcasts_only_reach_subscribed_clients() {
+        let mut events = Events::new();
+        let (status_tx, status_rx) = mpsc::channel();
+        let (all_tx, all_rx) = mpsc::channel();
+        events.add_broadcast_listener("status".to_string(), status_tx);
+        events.add_broadcast_listener("all".to_string(), all_tx);
+        events
+            .subscribe("status", &[Topic::Progress, Topic::Playlist], false)
+            .unwrap();
+
+        events.broadcast_event(ServerBroadcastEvent::PlayerProgressUpdate {
+            elapsed: time::Duration::from_secs(1),
+        });
+        events.broadcast_event(ServerBroadcastEvent::PlaylistClear);
+        events.broadcast_event(ServerBroadcastEvent::PlayerPause);
+        events.broadcast_event(ServerBroadcastEvent::ServerQuit);
+
+        assert!(matches!(
+            status_rx.try_recv(),
+            Ok(ServerBroadcastEvent::PlayerPause)
+        ));
+        assert!(matches!(
+            status_rx.try_recv(),
+            Ok(ServerBroadcastEvent::ServerQuit)
+        ));
+        assert!(status_rx.try_recv().is_err());
+        assert_eq!(all_rx.try_iter().count(), 4);
+        assert!(!events.is_subscribed(Topic::Spectrum));
+    }
+}
@@ -3,6 +3,7 @@ mod client;
 mod config;
 mod context;
 mod events;
+mod mpd;
 mod playlist;
 mod server;
 mod server_commands;
@@ -0,0 +1,128 @@
+use std::ops::Range;
+use std::path::{Component, Path};
+
+use super::response::Ack;
+
+/// Splits a command line into its words,
+/// words in double quotes can have spaces and `\"` or `\\` in them
+pub fn split_line(line: &str) -> Result<Vec<String>, Ack> {
+    let mut words = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut word = String::new();
+        if c == '"' {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(escaped) => word.push(escaped),
+                        None => return Err(Ack::arg("Missing closing '\"'")),
+                    },
+                    Some(c) => word.push(c),
+                    None => return Err(Ack::arg("Missing closing '\"'")),
+                }
+            }
+        } else {
+            word.push(c);
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                word.push(c);
+            }
+        }
+        words.push(word);
+    }
+    Ok(words)
+}
+
+pub fn parse_usize(s: &str) -> Result<usize, Ack> {
+    s.parse()
+        .map_err(|_| Ack::arg(format!("Integer expected: {s}")))
+}
+
+pub fn parse_bool(s: &str) -> Result<bool, Ack> {
+    match s {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(Ack::arg(format!("Boolean (0/1) expected: {s}"))),
+    }
+}
+
+/// Seconds like `12` or `12.5`
+pub fn parse_seconds(s: &str) -> Result<f64, Ack> {
+    s.parse()
+        .ok()
+        .filter(|secs: &f64| secs.is_finite() && *secs >= 0.0)
+        .ok_or_else(|| Ack::arg(format!("Number expected: {s}")))
+}
+
+/// A position like `3`, or a range like `3:7` or `3:` running to `len`
+pub fn parse_range(s: &str, len: usize) -> Result<Range<usize>, Ack> {
+    let range = match s.split_once(':') {
+        Some((start, "")) => parse_usize(start)?..len,
+        Some((start, end)) => parse_usize(start)?..parse_usize(end)?,
+        None => {
+            let pos = parse_usize(s)?;
+            let end = pos
+                .checked_add(1)
+                .ok_or_else(|| Ack::arg("Bad song index"))?;
+            pos..end
+        }
+    };
+    if range.start >= range.end || range.end > len {
+        return Err(Ack::arg("Bad song index"));
+    }
+    Ok(range)
+}
+
+/// A song uri, relative to the music directory and not leaving it
+pub fn parse_uri(s: &str) -> Result<&Path, Ack> {
+    let path = Path::new(s.strip_prefix("file://").unwrap_or(s));
+    let inside = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    match inside && !s.is_empty() {
+        true => Ok(path),
+        false => Err(Ack::arg("Bad song uri")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_words_keep_their_spaces() {
+        assert_eq!(
+            split_line(r#"add "My Music/a \"b\".flac"  "#).unwrap(),
+            vec!["add", r#"My Music/a "b".flac"#]
+        );
+        assert_eq!(
+            split_line("seek 2 10.5").unwrap(),
+            vec!["seek", "2", "10.5"]
+        );
+        assert!(split_line(r#"add "unfinished"#).is_err());
+    }
+
+    #[test]
+    fn ranges_stay_within_the_queue() {
+        assert_eq!(parse_range("2", 5).unwrap(), 2..3);
+        assert_eq!(parse_range("1:3", 5).unwrap(), 1..3);
+        assert_eq!(parse_range("3:", 5).unwrap(), 3..5);
+        assert!(parse_range("5", 5).is_err());
+        assert!(parse_range("3:1", 5).is_err());
+        assert!(parse_range("x", 5).is_err());
+        assert!(parse_range(&usize::MAX.to_string(), 5).is_err());
+    }
+
+    #[test]
+    fn uris_stay_in_the_music_directory() {
+        assert_eq!(parse_uri("a/b.flac").unwrap(), Path::new("a/b.flac"));
+        assert_eq!(parse_uri("file://a/b.flac").unwrap(), Path::new("a/b.flac"));
+        assert!(parse_uri("/etc/passwd").is_err());
+        assert!(parse_uri("file:///etc/passwd").is_err());
+        assert!(parse_uri("a/../../b.flac").is_err());
+        assert!(parse_uri("").is_err());
+    }
+}
@@ -0,0 +1,655 @@
+pub mod command;
+pub mod response;
+
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+
+use dizi::error::AppResult;
+use dizi::player::{PlayerState, PlayerStatus};
+use dizi::request::client::{ClientRequest, ClientRequestMessage, RequestId};
+use dizi::response::server::ServerBroadcastEvent;
+
+use crate::config::MpdOption;
+use crate::events::{ClientRequestSender, ServerBroadcastEventReceiver};
+use crate::util::auth::tokens_match;
+
+use self::command::{parse_bool, parse_range, parse_seconds, parse_uri, parse_usize, split_line};
+use self::response::{
+    ACK_ERROR_PASSWORD, ACK_ERROR_PERMISSION, ACK_ERROR_SYSTEM, ACK_ERROR_UNKNOWN, Ack, Subsystem,
+};
+
+/// Commands we understand, for `commands`
+const COMMANDS: &[&str] = &[
+    "add",
+    "clear",
+    "close",
+    "command_list_begin",
+    "command_list_end",
+    "command_list_ok_begin",
+    "commands",
+    "currentsong",
+    "delete",
+    "idle",
+    "move",
+    "next",
+    "noidle",
+    "password",
+    "pause",
+    "ping",
+    "play",
+    "playlistinfo",
+    "previous",
+    "random",
+    "repeat",
+    "seek",
+    "seekcur",
+    "setvol",
+    "single",
+    "status",
+];
+
+enum MpdMessage {
+    Client(String),
+    Server(Box<ServerBroadcastEvent>),
+    Closed,
+}
+
+/// Talks MPD with a client, carrying out its commands as `ClientRequest`s
+/// like any other client's
+pub fn handle_mpd_client(
+    uuid: uuid::Uuid,
+    stream: TcpStream,
+    option: MpdOption,
+    client_request_tx: ClientRequestSender,
+    server_event_rx: ServerBroadcastEventReceiver,
+) -> AppResult {
+    let (message_tx, message_rx) = mpsc::channel();
+
+    // listen for events broadcasted by the server
+    let message_tx_clone = message_tx.clone();
+    let _ = thread::spawn(move || {
+        while let Ok(event) = server_event_rx.recv() {
+            if message_tx_clone
+                .send(MpdMessage::Server(Box::new(event)))
+                .is_err()
+            {
+                return;
+            }
+        }
+    });
+
+    // listen for commands sent by client
+    let stream_clone = stream.try_clone()?;
+    let _ = thread::spawn(move || {
+        let cursor = BufReader::new(stream_clone);
+        for line in cursor.lines().map_while(Result::ok) {
+            if message_tx.send(MpdMessage::Client(line)).is_err() {
+                return;
+            }
+        }
+        let _ = message_tx.send(MpdMessage::Closed);
+    });
+
+    let mut session = Session {
+        uuid: uuid.to_string(),
+        music_directory: option.music_directory,
+        authenticated: option.password.is_none(),
+        password: option.password,
+        client_request_tx,
+        messages: message_rx,
+        deferred: VecDeque::new(),
+        request_id: 0,
+        changed: BTreeSet::new(),
+        playlist_version: 1,
+        closed: false,
+    };
+    let res = session.run(&mut &stream);
+    tracing::debug!(uuid = session.uuid, ?res, "MPD client left");
+
+    let leave = ClientRequestMessage {
+        id: None,
+        request: ClientRequest::ClientLeave {
+            uuid: session.uuid.clone(),
+        },
+    };
+    let _ = session.client_request_tx.send((session.uuid, leave));
+    res
+}
+
+struct Session {
+    uuid: String,
+    music_directory: PathBuf,
+    password: Option<String>,
+    authenticated: bool,
+    client_request_tx: ClientRequestSender,
+    messages: mpsc::Receiver<MpdMessage>,
+    // commands that came in while waiting on the server
+    deferred: VecDeque<String>,
+    request_id: RequestId,
+    // subsystems changed since the client last went idle
+    changed: BTreeSet<Subsystem>,
+    // bumped every time the queue changes
+    playlist_version: u32,
+    // the client or the server went away
+    closed: bool,
+}
+
+impl Session {
+    fn run(&mut self, stream: &mut impl Write) -> AppResult {
+        stream.write_all(response::GREETING.as_bytes())?;
+
+        while let Some(line) = self.next_line() {
+            let words = match split_line(&line) {
+                Ok(words) => words,
+                Err(ack) => {
+                    stream.write_all(ack.to_line(0, "").as_bytes())?;
+                    continue;
+                }
+            };
+            let Some(command) = words.first() else {
+                continue;
+            };
+            let reply = match command.as_str() {
+                "close" => return Ok(()),
+                "idle" => match self.idle(&words[1..]) {
+                    Ok(reply) => reply,
+                    Err(ack) => ack.to_line(0, command),
+                },
+                // not idling, nothing to stop
+                "noidle" => continue,
+                "command_list_begin" => self.command_list(false),
+                "command_list_ok_begin" => self.command_list(true),
+                _ => match self.execute(&words) {
+                    Ok(reply) => reply + "OK\n",
+                    Err(ack) => ack.to_line(0, command),
+                },
+            };
+            stream.write_all(reply.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// The next command to carry out, `None` once the connection is done
+    fn next_line(&mut self) -> Option<String> {
+        if let Some(line) = self.deferred.pop_front() {
+            return Some(line);
+        }
+        while !self.closed {
+            match self.messages.recv() {
+                Ok(MpdMessage::Client(line)) => return Some(line),
+                Ok(MpdMessage::Server(event)) => self.note(&event),
+                Ok(MpdMessage::Closed) | Err(_) => self.closed = true,
+            }
+        }
+        None
+    }
+
+    fn note(&mut self, event: &ServerBroadcastEvent) {
+        if let ServerBroadcastEvent::ServerQuit = event {
+            self.closed = true;
+            return;
+        }
+        let changed = Subsystem::changed_by(event);
+        if changed.contains(&Subsystem::Playlist) {
+            self.playlist_version = self.playlist_version.wrapping_add(1);
+        }
+        self.changed.extend(changed);
+    }
+
+    /// Waits for any of `args` to change, or anything if none are given,
+    /// until the client sends `noidle`.
+    ///
+    /// Any other command ends the wait with an error and isn't carried out,
+    /// where MPD itself would drop the connection
+    fn idle(&mut self, args: &[String]) -> Result<String, Ack> {
+        let wanted = args
+            .iter()
+            .map(|arg| {
+                Subsystem::from_str(arg)
+                    .ok_or_else(|| Ack::arg(format!("Unrecognized idle event: {arg}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let wanted = match wanted.is_empty() {
+            true => Subsystem::ALL.to_vec(),
+            false => wanted,
+        };
+
+        loop {
+            let changed: Vec<Subsystem> = self
+                .changed
+                .iter()
+                .filter(|subsystem| wanted.contains(subsystem))
+                .copied()
+                .collect();
+            if !changed.is_empty() {
+                let mut reply = String::new();
+                for subsystem in changed {
+                    self.changed.remove(&subsystem);
+                    reply.push_str(&format!("changed: {}\n", subsystem.as_str()));
+                }
+                return Ok(reply + "OK\n");
+            }
+            let message = match self.deferred.pop_front() {
+                Some(line) => MpdMessage::Client(line),
+                None if self.closed => MpdMessage::Closed,
+                None => self.messages.recv().unwrap_or(MpdMessage::Closed),
+            };
+            match message {
+                MpdMessage::Client(line) => {
+                    let command = line.split_whitespace().next().unwrap_or_default();
+                    if command == "noidle" {
+                        return Ok("OK\n".to_string());
+                    }
+                    let ack = Ack::arg(format!("\"{command}\" isn't allowed while idle"));
+                    return Ok(ack.to_line(0, command));
+                }
+                MpdMessage::Server(event) => self.note(&event),
+                MpdMessage::Closed => {
+                    self.closed = true;
+                    return Ok(String::new());
+                }
+            }
+        }
+    }
+
+    /// Carries out the commands up to `command_list_end`,
+    /// stopping at the first one that fails
+    fn command_list(&mut self, list_ok: bool) -> String {
+        let mut commands = Vec::new();
+        loop {
+            match self.next_line() {
+                Some(line) if line.trim() == "command_list_end" => break,
+                Some(line) => commands.push(line),
+                None => return String::new(),
+            }
+        }
+        let mut reply = String::new();
+        for (index, line) in commands.iter().enumerate() {
+            let (command, res) = match split_line(line) {
+                Ok(words) => (
+                    words.first().cloned().unwrap_or_default(),
+                    self.execute(&words),
+                ),
+                Err(ack) => (String::new(), Err(ack)),
+            };
+            match res {
+                Ok(output) => {
+                    reply.push_str(&output);
+                    if list_ok {
+                        reply.push_str("list_OK\n");
+                    }
+                }
+                Err(ack) => {
+                    reply.push_str(&ack.to_line(index, &command));
+                    return reply;
+                }
+            }
+        }
+        reply + "OK\n"
+    }
+
+    /// Carries out one command, returning what to send back before the `OK`
+    fn execute(&mut self, words: &[String]) -> Result<String, Ack> {
+        let Some((command, args)) = words.split_first() else {
+            return Ok(String::new());
+        };
+        if !self.authenticated && !matches!(command.as_str(), "password" | "ping" | "commands") {
+            return Err(Ack::new(
+                ACK_ERROR_PERMISSION,
+                format!("you don't have permission for \"{command}\""),
+            ));
+        }
+        let arg = |index: usize| -> Result<&String, Ack> {
+            args.get(index)
+                .ok_or_else(|| Ack::arg(format!("wrong number of arguments for \"{command}\"")))
+        };
+
+        match command.as_str() {
+            "ping" => {}
+            "commands" => {
+                return Ok(COMMANDS
+                    .iter()
+                    .map(|command| format!("command: {command}\n"))
+                    .collect());
+            }
+            "password" => {
+                let given = arg(0)?;
+                if self
+                    .password
+                    .as_ref()
+                    .is_some_and(|password| !tokens_match(given, password))
+                {
+                    return Err(Ack::new(ACK_ERROR_PASSWORD, "incorrect password"));
+                }
+                self.authenticated = true;
+            }
+            "status" => {
+                let state = self.player_state()?;
+                return Ok(response::status(&state, self.playlist_version));
+            }
+            "currentsong" => {
+                let state = self.player_state()?;
+                return Ok(response::current_song(&state, &self.music_directory));
+            }
+            "playlistinfo" => {
+                let state = self.player_state()?;
+                let list = &state.playlist.list;
+                let range = match args.first() {
+                    Some(arg) => parse_range(arg, list.len())?,
+                    None => 0..list.len(),
+                };
+                return Ok(range
+                    .map(|pos| response::queued_song(&list[pos], pos, &self.music_directory))
+                    .collect());
+            }
+            "play" => match args.first() {
+                Some(pos) => {
+                    let index = parse_usize(pos)?;
+                    self.request(ClientRequest::PlaylistPlay { index: Some(index) })?;
+                }
+                None => {
+                    let state = self.player_state()?;
+                    match state.status {
+                        PlayerStatus::Playing => {}
+                        PlayerStatus::Paused => {
+                            self.request(ClientRequest::PlayerResume)?;
+                        }
+                        PlayerStatus::Stopped if !state.playlist.list.is_empty() => {
+                            let index = response::playing_position(&state).unwrap_or(0);
+                            self.request(ClientRequest::PlaylistPlay { index: Some(index) })?;
+                        }
+                        PlayerStatus::Stopped => {}
+                    }
+                }
+            },
+            "pause" => {
+                let request = match args.first().map(|arg| parse_bool(arg)).transpose()? {
+                    Some(true) => ClientRequest::PlayerPause,
+                    Some(false) => ClientRequest::PlayerResume,
+                    None => ClientRequest::PlayerTogglePlay,
+                };
+                self.request(request)?;
+            }
+            "next" => {
+                self.request(ClientRequest::PlayerPlayNext)?;
+            }
+            "previous" => {
+                self.request(ClientRequest::PlayerPlayPrevious)?;
+            }
+            "seek" => {
+                let pos = parse_usize(arg(0)?)?;
+                let position_ms = (parse_seconds(arg(1)?)? * 1000.0) as u64;
+                let state = self.player_state()?;
+                if response::playing_position(&state) != Some(pos) {
+                    self.request(ClientRequest::PlaylistPlay { index: Some(pos) })?;
+                }
+                self.request(ClientRequest::PlayerSeek { position_ms })?;
+                // seeking doesn't make an event of its own
+                self.changed.insert(Subsystem::Player);
+            }
+            "seekcur" => {
+                let time = arg(0)?;
+                let request = if let Some(offset) = time.strip_prefix('+') {
+                    let offset_ms = (parse_seconds(offset)? * 1000.0) as i64;
+                    ClientRequest::PlayerSeekRelative { offset_ms }
+                } else if let Some(offset) = time.strip_prefix('-') {
+                    let offset_ms = (parse_seconds(offset)? * 1000.0) as i64;
+                    ClientRequest::PlayerSeekRelative {
+                        offset_ms: -offset_ms,
+                    }
+                } else {
+                    let position_ms = (parse_seconds(time)? * 1000.0) as u64;
+                    ClientRequest::PlayerSeek { position_ms }
+                };
+                self.request(request)?;
+                self.changed.insert(Subsystem::Player);
+            }
+            "setvol" => {
+                let volume = parse_usize(arg(0)?)?;
+                if volume > 100 {
+                    return Err(Ack::arg("Invalid volume value"));
+                }
+                let current = self.player_state()?.volume;
+                if volume > current {
+                    self.request(ClientRequest::PlayerVolumeUp {
+                        amount: volume - current,
+                    })?;
+                } else if volume < current {
+                    self.request(ClientRequest::PlayerVolumeDown {
+                        amount: current - volume,
+                    })?;
+                }
+            }
+            "add" => {
+                let path = self.music_directory.join(parse_uri(arg(0)?)?);
+                self.request(ClientRequest::PlaylistAppend { path: Some(path) })?;
+            }
+            "delete" => {
+                let len = self.player_state()?.playlist.list.len();
+                let range = parse_range(arg(0)?, len)?;
+                // from the back, so the positions left to go don't move
+                for index in range.rev() {
+                    self.request(ClientRequest::PlaylistRemove { index: Some(index) })?;
+                }
+            }
+            "move" => {
+                let len = self.player_state()?.playlist.list.len();
+                let from = parse_range(arg(0)?, len)?;
+                if from.len() != 1 {
+                    return Err(Ack::arg("Moving ranges isn't supported"));
+                }
+                let (from, to) = (from.start, parse_usize(arg(1)?)?);
+                if to >= len {
+                    return Err(Ack::arg("Bad song index"));
+                }
+                for index in from..to {
+                    self.request(ClientRequest::PlaylistMoveDown { index: Some(index) })?;
+                }
+                for index in (to + 1..=from).rev() {
+                    self.request(ClientRequest::PlaylistMoveUp { index: Some(index) })?;
+                }
+            }
+            "clear" => {
+                self.request(ClientRequest::PlaylistClear)?;
+            }
+            "random" | "repeat" | "single" => {
+                let on = match (command.as_str(), arg(0)?.as_str()) {
+                    // stopping after the song is all there is
+                    ("single", "oneshot") => true,
+                    (_, arg) => parse_bool(arg)?,
+                };
+                let state = self.player_state()?;
+                let (current, toggle) = match command.as_str() {
+                    "random" => (state.shuffle, ClientRequest::PlayerToggleShuffle),
+                    "repeat" => (state.repeat, ClientRequest::PlayerToggleRepeat),
+                    _ => (!state.next, ClientRequest::PlayerToggleNext),
+                };
+                if current != on {
+                    self.request(toggle)?;
+                }
+            }
+            _ => {
+                return Err(Ack::new(
+                    ACK_ERROR_UNKNOWN,
+                    format!("unknown command \"{command}\""),
+                ));
+            }
+        }
+        Ok(String::new())
+    }
+
+    /// Sends `request` to the server and waits for its reply
+    fn request(&mut self, request: ClientRequest) -> Result<Option<ServerBroadcastEvent>, Ack> {
+        self.request_id += 1;
+        let id = self.request_id;
+        let message = ClientRequestMessage {
+            id: Some(id),
+            request,
+        };
+        self.client_request_tx
+            .send((self.uuid.clone(), message))
+            .map_err(|err| Ack::new(ACK_ERROR_SYSTEM, err.to_string()))?;
+
+        loop {
+            match self.messages.recv() {
+                Ok(MpdMessage::Server(event)) => match *event {
+                    ServerBroadcastEvent::Reply {
+                        id: Some(reply_id),
+                        result,
+                    } if reply_id == id => {
+                        return result
+                            .map(|payload| payload.map(|payload| *payload))
+                            .map_err(|msg| Ack::new(ACK_ERROR_SYSTEM, msg));
+                    }
+                    event => self.note(&event),
+                },
+                Ok(MpdMessage::Client(line)) => self.deferred.push_back(line),
+                // the request still goes through, so see it through
+                Ok(MpdMessage::Closed) => self.closed = true,
+                Err(_) => {
+                    self.closed = true;
+                    return Err(Ack::new(ACK_ERROR_SYSTEM, "Connection closed"));
+                }
+            }
+        }
+    }
+
+    fn player_state(&mut self) -> Result<PlayerState, Ack> {
+        match self.request(ClientRequest::PlayerState)? {
+            Some(ServerBroadcastEvent::PlayerState { state }) => Ok(state),
+            _ => Err(Ack::new(ACK_ERROR_SYSTEM, "Server didn't send its state")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::thread::JoinHandle;
+
+    use dizi::playlist::PlaylistType;
+    use dizi::song::{DiziFile, DiziSongEntry};
+
+    use super::*;
+
+    /// Runs `lines` through a session, with a server answering with `state`
+    /// and taking every other request as done.
+    /// Returns what was sent back and the requests the server got
+    fn run_session(state: PlayerState, lines: &[&str]) -> (String, Vec<ClientRequest>) {
+        let (client_request_tx, client_request_rx) = mpsc::channel();
+        let (message_tx, message_rx) = mpsc::channel();
+
+        let server: JoinHandle<Vec<ClientRequest>> = {
+            let message_tx = message_tx.clone();
+            thread::spawn(move || {
+                let mut requests = Vec::new();
+                for (_, message) in client_request_rx {
+                    let ClientRequestMessage { id, request } = message;
+                    let payload = match request {
+                        ClientRequest::PlayerState => {
+                            Some(Box::new(ServerBroadcastEvent::PlayerState {
+                                state: state.clone(),
+                            }))
+                        }
+                        _ => None,
+                    };
+                    requests.push(request);
+                    let reply = ServerBroadcastEvent::Reply {
+                        id,
+                        result: Ok(payload),
+                    };
+                    let _ = message_tx.send(MpdMessage::Server(Box::new(reply)));
+                }
+                requests
+            })
+        };
+        for line in lines {
+            message_tx
+                .send(MpdMessage::Client(line.to_string()))
+                .unwrap();
+        }
+        message_tx.send(MpdMessage::Closed).unwrap();
+
+        let mut session = Session {
+            uuid: "mpd".to_string(),
+            music_directory: PathBuf::from("/music"),
+            password: None,
+            authenticated: true,
+            client_request_tx,
+            messages: message_rx,
+            deferred: VecDeque::new(),
+            request_id: 0,
+            changed: BTreeSet::new(),
+            playlist_version: 1,
+            closed: false,
+        };
+        let mut output = Vec::new();
+        session.run(&mut output).unwrap();
+        drop(session);
+        (String::from_utf8(output).unwrap(), server.join().unwrap())
+    }
+
+    fn playing_first_of(songs: &[&str]) -> PlayerState {
+        let mut state = PlayerState::new();
+        state.status = PlayerStatus::Playing;
+        state.playlist_status = PlaylistType::PlaylistFile;
+        state.playlist.list = songs
+            .iter()
+            .map(|song| DiziSongEntry::Unloaded(DiziFile::new(Path::new(song))))
+            .collect();
+        state.playlist.playing_index = Some(0);
+        state
+    }
+
+    #[test]
+    fn seeking_another_song_plays_it_first() {
+        let state = playing_first_of(&["/music/a.flac", "/music/b.flac"]);
+        let (output, requests) = run_session(state, &["seek 1 2.5"]);
+        assert_eq!(output, format!("{}OK\n", response::GREETING));
+        assert!(
+            matches!(
+                requests.as_slice(),
+                [
+                    ClientRequest::PlayerState,
+                    ClientRequest::PlaylistPlay { index: Some(1) },
+                    ClientRequest::PlayerSeek { position_ms: 2500 },
+                ]
+            ),
+            "{requests:?}"
+        );
+    }
+
+    #[test]
+    fn commands_while_idle_fail_and_end_the_wait() {
+        let state = playing_first_of(&["/music/a.flac"]);
+        let (output, requests) = run_session(state, &["idle", "next", "idle player", "noidle"]);
+        assert_eq!(
+            output,
+            format!(
+                "{}ACK [2@0] {{next}} \"next\" isn't allowed while idle\nOK\n",
+                response::GREETING
+            )
+        );
+        assert!(requests.is_empty(), "{requests:?}");
+    }
+
+    #[test]
+    fn seeking_the_playing_song_only_seeks() {
+        let state = playing_first_of(&["/music/a.flac", "/music/b.flac"]);
+        let (_, requests) = run_session(state, &["seek 0 10"]);
+        assert!(
+            matches!(
+                requests.as_slice(),
+                [
+                    ClientRequest::PlayerState,
+                    ClientRequest::PlayerSeek { position_ms: 10000 },
+                ]
+            ),
+            "{requests:?}"
+        );
+    }
+}
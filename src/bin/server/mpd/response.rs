@@ -0,0 +1,301 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use dizi::player::{PlayerState, PlayerStatus};
+use dizi::playlist::PlaylistType;
+use dizi::response::server::ServerBroadcastEvent;
+use dizi::song::{DiziAudioFile, DiziFile, DiziSongEntry};
+
+/// Greeting sent as soon as a client connects, the version being
+/// the protocol we mostly follow
+pub const GREETING: &str = "OK MPD 0.23.5\n";
+
+// error codes, as MPD numbers them
+pub const ACK_ERROR_ARG: u32 = 2;
+pub const ACK_ERROR_PASSWORD: u32 = 3;
+pub const ACK_ERROR_PERMISSION: u32 = 4;
+pub const ACK_ERROR_UNKNOWN: u32 = 5;
+pub const ACK_ERROR_SYSTEM: u32 = 52;
+
+/// A failed command, sent back as `ACK [code@index] {command} msg`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ack {
+    pub code: u32,
+    pub msg: String,
+}
+
+impl Ack {
+    pub fn new(code: u32, msg: impl Into<String>) -> Self {
+        Self {
+            code,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn arg(msg: impl Into<String>) -> Self {
+        Self::new(ACK_ERROR_ARG, msg)
+    }
+
+    /// `index` is the command's place in a command list, 0 outside of one
+    pub fn to_line(&self, index: usize, command: &str) -> String {
+        format!(
+            "ACK [{}@{}] {{{}}} {}\n",
+            self.code, index, command, self.msg
+        )
+    }
+}
+
+/// Parts of the player `idle` waits on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Subsystem {
+    Playlist,
+    Player,
+    Mixer,
+    Options,
+    Output,
+}
+
+impl Subsystem {
+    pub const ALL: &'static [Subsystem] = &[
+        Self::Playlist,
+        Self::Player,
+        Self::Mixer,
+        Self::Options,
+        Self::Output,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Playlist => "playlist",
+            Self::Player => "player",
+            Self::Mixer => "mixer",
+            Self::Options => "options",
+            Self::Output => "output",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|subsystem| subsystem.as_str() == s)
+    }
+
+    /// What changed with `event`, nothing for events `idle` doesn't wake up for
+    pub fn changed_by(event: &ServerBroadcastEvent) -> &'static [Subsystem] {
+        match event {
+            ServerBroadcastEvent::PlayerFilePlay { .. }
+            | ServerBroadcastEvent::PlayerPause
+            | ServerBroadcastEvent::PlayerResume
+            | ServerBroadcastEvent::PlayerStop
+            | ServerBroadcastEvent::PlayerTrack { .. }
+            | ServerBroadcastEvent::PlaylistPlay { .. } => &[Self::Player],
+            ServerBroadcastEvent::PlayerVolumeUpdate { .. } => &[Self::Mixer],
+            ServerBroadcastEvent::PlayerRepeat { .. }
+            | ServerBroadcastEvent::PlayerShuffle { .. }
+            | ServerBroadcastEvent::PlayerNext { .. } => &[Self::Options],
+            ServerBroadcastEvent::PlaylistAppend { .. }
+            | ServerBroadcastEvent::PlaylistRemove { .. }
+            | ServerBroadcastEvent::PlaylistSwapMove { .. }
+            | ServerBroadcastEvent::PlaylistClear => &[Self::Playlist],
+            ServerBroadcastEvent::PlaylistOpen { .. } => &[Self::Playlist, Self::Player],
+            ServerBroadcastEvent::AudioDeviceChanged { .. } => &[Self::Output],
+            _ => &[],
+        }
+    }
+}
+
+fn flag(on: bool) -> u8 {
+    on as u8
+}
+
+/// Position of the playing song in the queue,
+/// songs played from a directory listing aren't in it
+pub fn playing_position(state: &PlayerState) -> Option<usize> {
+    match state.playlist_status {
+        PlaylistType::PlaylistFile => state.playlist.playing_index,
+        PlaylistType::DirectoryListing => None,
+    }
+}
+
+/// Reply to `status`
+pub fn status(state: &PlayerState, playlist_version: u32) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "volume: {}", state.volume);
+    let _ = writeln!(out, "repeat: {}", flag(state.repeat));
+    let _ = writeln!(out, "random: {}", flag(state.shuffle));
+    // not moving on to the next song is as close as we get to single mode
+    let _ = writeln!(out, "single: {}", flag(!state.next));
+    let _ = writeln!(out, "consume: 0");
+    let _ = writeln!(out, "playlist: {playlist_version}");
+    let _ = writeln!(out, "playlistlength: {}", state.playlist.list.len());
+    let state_str = match state.status {
+        PlayerStatus::Playing => "play",
+        PlayerStatus::Paused => "pause",
+        PlayerStatus::Stopped => "stop",
+    };
+    let _ = writeln!(out, "state: {state_str}");
+    if let Some(pos) = playing_position(state) {
+        // no songid, queue entries have nothing that stays the same when they move
+        let _ = writeln!(out, "song: {pos}");
+    }
+    if let Some(song) = state.song.as_ref()
+        && state.status != PlayerStatus::Stopped
+    {
+        let elapsed = state.elapsed;
+        let duration = song.audio_metadata().total_duration;
+        let _ = writeln!(
+            out,
+            "time: {}:{}",
+            elapsed.as_secs(),
+            duration.unwrap_or_default().as_secs()
+        );
+        let _ = writeln!(out, "elapsed: {:.3}", elapsed.as_secs_f64());
+        if let Some(duration) = duration {
+            let _ = writeln!(out, "duration: {:.3}", duration.as_secs_f64());
+        }
+        if let Some(format) = state.output_format.as_ref() {
+            let bits = song.audio_metadata().bit_depth;
+            let _ = writeln!(
+                out,
+                "audio: {}:{}:{}",
+                format.sample_rate, bits, format.channels
+            );
+        }
+    }
+    out
+}
+
+/// Reply to `currentsong`
+pub fn current_song(state: &PlayerState, music_directory: &Path) -> String {
+    match state.song.as_ref() {
+        Some(song) => {
+            let mut out = audio_file(song, music_directory);
+            if let Some(pos) = playing_position(state) {
+                let _ = writeln!(out, "Pos: {pos}");
+            }
+            out
+        }
+        None => String::new(),
+    }
+}
+
+/// One song of `playlistinfo`, songs are only known by their position
+pub fn queued_song(entry: &DiziSongEntry, pos: usize, music_directory: &Path) -> String {
+    let mut out = match entry {
+        DiziSongEntry::Loaded(song) => audio_file(song, music_directory),
+        DiziSongEntry::Unloaded(file) => unloaded_file(file, music_directory),
+    };
+    let _ = writeln!(out, "Pos: {pos}");
+    out
+}
+
+/// MPD's tag names for the tags we read
+const TAGS: &[(&str, &str)] = &[
+    ("Artist", "Artist"),
+    ("AlbumArtist", "AlbumArtist"),
+    ("TrackTitle", "Title"),
+    ("Album", "Album"),
+    ("TrackNumber", "Track"),
+    ("Genre", "Genre"),
+    ("Date", "Date"),
+];
+
+fn audio_file(song: &DiziAudioFile, music_directory: &Path) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "file: {}", uri(song.file_path(), music_directory));
+    let tags = &song.music_metadata().standard_tags;
+    for (key, name) in TAGS {
+        if let Some(value) = tags.get(*key) {
+            let _ = writeln!(out, "{name}: {}", single_line(value));
+        }
+    }
+    if let Some(duration) = song.audio_metadata().total_duration {
+        write_duration(&mut out, duration);
+    }
+    out
+}
+
+/// Songs that haven't been opened yet only have what their CUE sheet says
+fn unloaded_file(file: &DiziFile, music_directory: &Path) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "file: {}", uri(&file.file_path, music_directory));
+    if let Some(cue) = file.cue.as_ref() {
+        if let Some(performer) = cue.performer.as_ref() {
+            let _ = writeln!(out, "Artist: {}", single_line(performer));
+        }
+        if let Some(title) = cue.title.as_ref() {
+            let _ = writeln!(out, "Title: {}", single_line(title));
+        }
+        if let Some(album) = cue.album.as_ref() {
+            let _ = writeln!(out, "Album: {}", single_line(album));
+        }
+        let _ = writeln!(out, "Track: {}", cue.number);
+        if let Some(end) = cue.end {
+            write_duration(&mut out, end.saturating_sub(cue.start));
+        }
+    }
+    out
+}
+
+fn write_duration(out: &mut String, duration: Duration) {
+    let _ = writeln!(out, "Time: {}", duration.as_secs());
+    let _ = writeln!(out, "duration: {:.3}", duration.as_secs_f64());
+}
+
+/// Songs under the music directory are relative to it, like in MPD
+pub fn uri(path: &Path, music_directory: &Path) -> String {
+    path.strip_prefix(music_directory)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+// a newline in a value would end the field early
+fn single_line(value: &str) -> String {
+    value.replace(['\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn status_of_a_stopped_player() {
+        let mut state = PlayerState::new();
+        state.volume = 40;
+        state.repeat = true;
+        state.next = true;
+        state.status = PlayerStatus::Stopped;
+        let status = status(&state, 3);
+        assert!(status.contains("volume: 40\n"));
+        assert!(status.contains("repeat: 1\n"));
+        assert!(status.contains("single: 0\n"));
+        assert!(status.contains("playlist: 3\n"));
+        assert!(status.contains("state: stop\n"));
+        assert!(!status.contains("song:"));
+    }
+
+    #[test]
+    fn queued_songs_are_relative_to_the_music_directory() {
+        let music_directory = PathBuf::from("/music");
+        let entry = DiziSongEntry::Unloaded(DiziFile::new(Path::new("/music/a/b.flac")));
+        assert_eq!(
+            queued_song(&entry, 2, &music_directory),
+            "file: a/b.flac\nPos: 2\n"
+        );
+        let entry = DiziSongEntry::Unloaded(DiziFile::new(Path::new("/elsewhere/c.flac")));
+        assert!(queued_song(&entry, 0, &music_directory).starts_with("file: /elsewhere/c.flac\n"));
+    }
+
+    #[test]
+    fn acks_name_the_failed_command() {
+        assert_eq!(
+            Ack::arg("Bad song index").to_line(1, "delete"),
+            "ACK [2@1] {delete} Bad song index\n"
+        );
+    }
+}
@@ -0,0 +1,432 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use dizi::song::{CueTrack, DiziFile};
+
+/// CUE sheet timestamps count CD frames, 75 to a second
+const CD_FRAMES_PER_SEC: u64 = 75;
+
+const FLAC_STREAMINFO: u8 = 0;
+const FLAC_VORBIS_COMMENT: u8 = 4;
+const FLAC_CUESHEET: u8 = 5;
+
+pub fn is_cue_sheet(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+}
+
+/// Tracks `path` stands for: those of a `.cue` file,
+/// or of the CUE sheet embedded in a FLAC file. `None` for anything else.
+pub fn cue_tracks(path: &Path) -> Option<Vec<DiziFile>> {
+    if is_cue_sheet(path) {
+        cue_sheet_tracks(path)
+    } else {
+        embedded_cue_tracks(path)
+    }
+}
+
+/// Tracks listed in the `.cue` file at `path`
+pub fn cue_sheet_tracks(path: &Path) -> Option<Vec<DiziFile>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            tracing::error!(?err, ?path, "Failed to read CUE sheet");
+            return None;
+        }
+    };
+    // sheets ripped on windows are often not UTF-8
+    let text = String::from_utf8_lossy(&bytes);
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let tracks: Vec<DiziFile> = parse_cue_sheet(&text, path)
+        .into_iter()
+        .filter_map(|(file, track)| {
+            let file_path = resolve_file(dir, &file?);
+            Some(DiziFile::new(&file_path).with_cue_track(track))
+        })
+        .collect();
+    (!tracks.is_empty()).then_some(tracks)
+}
+
+/// Tracks of the CUE sheet embedded in the FLAC file at `path`, if it has one.
+///
+/// A `CUESHEET` tag is preferred over the `CUESHEET` metadata block,
+/// as only the tag has titles and performers.
+pub fn embedded_cue_tracks(path: &Path) -> Option<Vec<DiziFile>> {
+    let is_flac = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("flac"));
+    if !is_flac {
+        return None;
+    }
+    let tracks = match read_flac_cue_sheet(path) {
+        Ok(tracks) => tracks,
+        Err(err) => {
+            tracing::debug!(?err, ?path, "Failed to read FLAC metadata");
+            return None;
+        }
+    };
+    let tracks: Vec<DiziFile> = tracks
+        .into_iter()
+        .map(|track| DiziFile::new(path).with_cue_track(track))
+        .collect();
+    (!tracks.is_empty()).then_some(tracks)
+}
+
+fn read_flac_cue_sheet(path: &Path) -> io::Result<Vec<CueTrack>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != b"fLaC" {
+        return Ok(Vec::new());
+    }
+
+    let mut sample_rate = 0;
+    let mut cue_sheet_block = None;
+    loop {
+        let mut header = [0; 4];
+        reader.read_exact(&mut header)?;
+        let is_last = header[0] & 0x80 != 0;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        match header[0] & 0x7f {
+            block_type @ (FLAC_STREAMINFO | FLAC_VORBIS_COMMENT | FLAC_CUESHEET) => {
+                let mut block = vec![0; len];
+                reader.read_exact(&mut block)?;
+                match block_type {
+                    FLAC_STREAMINFO if block.len() >= 13 => {
+                        sample_rate = u32::from_be_bytes([0, block[10], block[11], block[12]]) >> 4;
+                    }
+                    FLAC_VORBIS_COMMENT => {
+                        if let Some(text) = vorbis_comment(&block, "CUESHEET") {
+                            return Ok(parse_cue_sheet(&text, path)
+                                .into_iter()
+                                .map(|(_, track)| track)
+                                .collect());
+                        }
+                    }
+                    FLAC_CUESHEET => cue_sheet_block = Some(block),
+                    _ => {}
+                }
+            }
+            // pictures and the like can be large, skip them without reading
+            _ => reader.seek_relative(len as i64)?,
+        }
+        if is_last {
+            break;
+        }
+    }
+    let tracks = match cue_sheet_block {
+        Some(block) if sample_rate > 0 => parse_cue_sheet_block(&block, sample_rate, path),
+        _ => Vec::new(),
+    };
+    Ok(tracks)
+}
+
+/// Value of the comment called `key` in a FLAC `VORBIS_COMMENT` block
+fn vorbis_comment(block: &[u8], key: &str) -> Option<String> {
+    let read_u32 = |offset: usize| -> Option<usize> {
+        let bytes = block.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+    };
+    let vendor_len = read_u32(0)?;
+    let count = read_u32(4 + vendor_len)?;
+    let mut offset = 8 + vendor_len;
+    for _ in 0..count {
+        let len = read_u32(offset)?;
+        let comment = block.get(offset + 4..offset + 4 + len)?;
+        offset += 4 + len;
+
+        let comment = String::from_utf8_lossy(comment);
+        if let Some((name, value)) = comment.split_once('=')
+            && name.eq_ignore_ascii_case(key)
+        {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+/// Tracks of a FLAC `CUESHEET` block, whose offsets are in samples
+fn parse_cue_sheet_block(block: &[u8], sample_rate: u32, sheet: &Path) -> Vec<CueTrack> {
+    const TRACKS_OFFSET: usize = 395;
+    const TRACK_LEN: usize = 36;
+    const INDEX_LEN: usize = 12;
+
+    let read_u64 = |offset: usize| -> Option<u64> {
+        let bytes = block.get(offset..offset + 8)?;
+        Some(u64::from_be_bytes(bytes.try_into().ok()?))
+    };
+    let to_duration =
+        |samples: u64| Duration::from_nanos((samples * 1_000_000_000).div_ceil(sample_rate as u64));
+
+    // (number, is audio, start in samples), the last one being the lead-out
+    let mut starts: Vec<(u32, bool, Option<u64>)> = Vec::new();
+    let count = block.get(TRACKS_OFFSET - 1).copied().unwrap_or_default();
+    let mut offset = TRACKS_OFFSET;
+    for _ in 0..count {
+        let (Some(track_offset), Some(header)) =
+            (read_u64(offset), block.get(offset + 8..offset + TRACK_LEN))
+        else {
+            break;
+        };
+        let number = header[0] as u32;
+        let is_audio = header[13] & 0x80 == 0;
+        let index_count = header[27] as usize;
+        offset += TRACK_LEN;
+
+        let mut start = None;
+        for _ in 0..index_count {
+            let (Some(index_offset), Some(index)) =
+                (read_u64(offset), block.get(offset + 8).copied())
+            else {
+                break;
+            };
+            if index == 1 {
+                start = Some(track_offset + index_offset);
+            }
+            offset += INDEX_LEN;
+        }
+        // the lead-out has no indices
+        if index_count == 0 {
+            start = Some(track_offset);
+        }
+        starts.push((number, is_audio, start));
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, is_audio, _))| *is_audio)
+        .filter_map(|(i, (number, _, start))| {
+            // the lead-out isn't a track, only where the last one ends
+            let (_, _, end) = starts.get(i + 1)?;
+            Some(CueTrack {
+                sheet: sheet.to_path_buf(),
+                number: *number,
+                title: None,
+                performer: None,
+                album: None,
+                start: to_duration((*start)?),
+                end: end.map(to_duration),
+            })
+        })
+        .collect()
+}
+
+/// A `TRACK` of a CUE sheet as it's read
+#[derive(Default)]
+struct SheetTrack {
+    file: Option<String>,
+    number: u32,
+    is_audio: bool,
+    title: Option<String>,
+    performer: Option<String>,
+    start: Option<Duration>,
+}
+
+/// Audio tracks of a CUE sheet, along with the name of the file each one is in
+fn parse_cue_sheet(text: &str, sheet: &Path) -> Vec<(Option<String>, CueTrack)> {
+    let mut album = None;
+    let mut album_performer = None;
+    let mut file = None;
+    let mut tracks: Vec<SheetTrack> = Vec::new();
+
+    for line in text.lines() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        let (command, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = args.trim();
+        // commands before the first track describe the whole album
+        let track = tracks.last_mut();
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => file = Some(file_name(args)),
+            "TRACK" => {
+                let mut args = args.split_whitespace();
+                let number = args.next().and_then(|n| n.parse().ok());
+                let is_audio = args
+                    .next()
+                    .is_none_or(|kind| kind.eq_ignore_ascii_case("AUDIO"));
+                tracks.push(SheetTrack {
+                    file: file.clone(),
+                    number: number.unwrap_or(tracks.len() as u32 + 1),
+                    is_audio,
+                    ..Default::default()
+                });
+            }
+            "TITLE" => match track {
+                Some(track) => track.title = Some(unquote(args)),
+                None => album = Some(unquote(args)),
+            },
+            "PERFORMER" => match track {
+                Some(track) => track.performer = Some(unquote(args)),
+                None => album_performer = Some(unquote(args)),
+            },
+            "INDEX" => {
+                let mut args = args.split_whitespace();
+                if let (Some(track), Some(1), Some(time)) = (
+                    track,
+                    args.next().and_then(|n| n.parse::<u32>().ok()),
+                    args.next().and_then(parse_timestamp),
+                ) {
+                    track.start = Some(time);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    tracks
+        .iter()
+        .enumerate()
+        .filter(|(_, track)| track.is_audio)
+        .filter_map(|(i, track)| {
+            // tracks in the same file play until the next one starts
+            let end = tracks
+                .get(i + 1)
+                .filter(|next| next.file == track.file)
+                .and_then(|next| next.start);
+            let cue_track = CueTrack {
+                sheet: sheet.to_path_buf(),
+                number: track.number,
+                title: track.title.clone(),
+                performer: track.performer.clone().or_else(|| album_performer.clone()),
+                album: album.clone(),
+                start: track.start?,
+                end,
+            };
+            Some((track.file.clone(), cue_track))
+        })
+        .collect()
+}
+
+/// Parses `mm:ss:ff`, where `ff` is in CD frames
+fn parse_timestamp(s: &str) -> Option<Duration> {
+    let mut parts = s.split(':').map(|part| part.parse::<u64>().ok());
+    let (Some(Some(minutes)), Some(Some(seconds)), Some(Some(frames)), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    let frames = (minutes * 60 + seconds) * CD_FRAMES_PER_SEC + frames;
+    // round up, so converting back to samples lands exactly on frame boundaries
+    Some(Duration::from_nanos(
+        (frames * 1_000_000_000).div_ceil(CD_FRAMES_PER_SEC),
+    ))
+}
+
+/// File name of a `FILE "name" TYPE` command
+fn file_name(args: &str) -> String {
+    match args.strip_prefix('"') {
+        Some(rest) => rest.split('"').next().unwrap_or_default().to_string(),
+        None => args
+            .rsplit_once(char::is_whitespace)
+            .map(|(name, _)| name)
+            .unwrap_or(args)
+            .to_string(),
+    }
+}
+
+fn unquote(s: &str) -> String {
+    s.trim().trim_matches('"').to_string()
+}
+
+/// Finds the file a sheet refers to as `name`.
+///
+/// Sheets are often left naming the file they were ripped to
+/// after it's been converted, so fall back to a file with the same stem.
+fn resolve_file(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(name);
+    if path.exists() {
+        return path;
+    }
+    let stem = Path::new(name).file_stem();
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|candidate| {
+            candidate.file_stem() == stem && !is_cue_sheet(candidate) && candidate.is_file()
+        })
+        .unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET: &str = r#"
+REM GENRE Jazz
+PERFORMER "The Band"
+TITLE "The Album"
+FILE "album.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "First"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Second"
+    PERFORMER "Guest"
+    INDEX 00 03:10:00
+    INDEX 01 03:12:37
+FILE "bonus.wav" WAVE
+  TRACK 03 AUDIO
+    TITLE "Bonus"
+    INDEX 01 00:00:00
+"#;
+
+    #[test]
+    fn reads_tracks_of_a_sheet() {
+        let tracks = parse_cue_sheet(SHEET, Path::new("album.cue"));
+        assert_eq!(tracks.len(), 3);
+
+        let (file, first) = &tracks[0];
+        assert_eq!(file.as_deref(), Some("album.flac"));
+        assert_eq!(first.number, 1);
+        assert_eq!(first.title.as_deref(), Some("First"));
+        assert_eq!(first.performer.as_deref(), Some("The Band"));
+        assert_eq!(first.album.as_deref(), Some("The Album"));
+        assert_eq!(first.start, Duration::ZERO);
+        // tracks end where the next one's INDEX 01 is, with the pregap
+        assert_eq!(first.end, tracks[1].1.start.into());
+
+        let (_, second) = &tracks[1];
+        assert_eq!(second.performer.as_deref(), Some("Guest"));
+        assert_eq!(second.start.as_millis(), 192_493);
+        // the last track in a file plays to its end
+        assert_eq!(second.end, None);
+
+        let (file, bonus) = &tracks[2];
+        assert_eq!(file.as_deref(), Some("bonus.wav"));
+        assert_eq!(bonus.start, Duration::ZERO);
+    }
+
+    #[test]
+    fn timestamps_land_on_sample_boundaries() {
+        // one CD frame is exactly 588 samples at 44.1 kHz
+        let time = parse_timestamp("00:00:01").unwrap();
+        assert_eq!((time.as_secs_f64() * 44100.0) as usize, 588);
+        assert_eq!(parse_timestamp("01:02"), None);
+    }
+
+    #[test]
+    fn reads_cue_sheet_from_vorbis_comment() {
+        let mut block = Vec::new();
+        let mut push = |bytes: &[u8]| {
+            block.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            block.extend_from_slice(bytes);
+        };
+        push(b"vendor");
+        let comments = [b"TITLE=Album".as_slice(), b"cuesheet=TRACK 01 AUDIO"];
+        block.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            block.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            block.extend_from_slice(comment);
+        }
+        assert_eq!(
+            vorbis_comment(&block, "CUESHEET").as_deref(),
+            Some("TRACK 01 AUDIO")
+        );
+        assert_eq!(vorbis_comment(&block, "ARTIST"), None);
+    }
+}
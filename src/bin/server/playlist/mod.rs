@@ -1,8 +1,10 @@
+pub mod cue;
 mod impl_playlist;
 
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use dizi::error::AppResult;
 use dizi::playlist::FilePlaylist;
@@ -28,13 +30,16 @@ impl DiziPlaylist {
     pub fn from_dir(path: &Path) -> io::Result<Self> {
         // only process regular files
         // if we can't read it, then don't play it
-        let mut contents: Vec<_> = fs::read_dir(path)?
+        let mut paths: Vec<_> = fs::read_dir(path)?
             .filter_map(|entry| entry.ok())
             .map(|entry| entry.path())
             .filter(|p| p.is_file())
-            .map(|path| DiziSongEntry::Unloaded(DiziFile::new(&path)))
             .collect();
-        contents.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+        paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+        let contents: Vec<_> = expand_cue_sheets(&paths)
+            .into_iter()
+            .map(DiziSongEntry::Unloaded)
+            .collect();
 
         let len = contents.len();
         Ok(Self {
@@ -57,8 +62,12 @@ impl DiziPlaylist {
                     new_path.push(p);
                     new_path
                 };
-                let entry = DiziSongEntry::Unloaded(DiziFile::new(&file_path));
-                entries.push(entry);
+                match cue::cue_tracks(&file_path) {
+                    Some(tracks) => {
+                        entries.extend(tracks.into_iter().map(DiziSongEntry::Unloaded));
+                    }
+                    None => entries.push(DiziSongEntry::Unloaded(DiziFile::new(&file_path))),
+                }
             }
         }
         let playlist = DiziPlaylist::new(entries);
@@ -101,3 +110,32 @@ impl DiziPlaylist {
         self.order = new_order;
     }
 }
+
+/// Replaces CUE sheets, and FLAC files with one embedded, with the tracks they list.
+///
+/// Audio files a `.cue` file covers are left out so they aren't played twice.
+pub fn expand_cue_sheets(paths: &[PathBuf]) -> Vec<DiziFile> {
+    let sheets: HashMap<&Path, Vec<DiziFile>> = paths
+        .iter()
+        .filter(|path| cue::is_cue_sheet(path))
+        .filter_map(|path| Some((path.as_path(), cue::cue_sheet_tracks(path)?)))
+        .collect();
+    let covered: HashSet<&Path> = sheets
+        .values()
+        .flatten()
+        .map(|track| track.file_path.as_path())
+        .collect();
+
+    let mut files = Vec::new();
+    for path in paths {
+        if let Some(tracks) = sheets.get(path.as_path()) {
+            files.extend(tracks.iter().cloned());
+        } else if !covered.contains(path.as_path()) {
+            match cue::embedded_cue_tracks(path) {
+                Some(tracks) => files.extend(tracks),
+                None => files.push(DiziFile::new(path)),
+            }
+        }
+    }
+    files
+}
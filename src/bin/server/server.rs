@@ -1,9 +1,10 @@
 use std::fs;
+use std::net::TcpListener;
 use std::os::unix::net::UnixListener;
 use std::path::Path;
 use std::thread;
 
-use dizi::error::AppResult;
+use dizi::error::{AppResult, DiziError, DiziErrorKind};
 use dizi::response::server::ServerBroadcastEvent;
 
 use crate::audio::symphonia::player::SymphoniaPlayer;
@@ -22,6 +23,36 @@ pub fn setup_socket(config: &AppConfig) -> AppResult<UnixListener> {
     Ok(stream)
 }
 
+/// Setup a TCP socket, if there's a `[server.tcp]` section
+pub fn setup_tcp(config: &AppConfig) -> AppResult<Option<TcpListener>> {
+    let Some(tcp) = config.server_ref().tcp_ref() else {
+        return Ok(None);
+    };
+    if tcp.token.is_empty() {
+        return Err(DiziError::new(
+            DiziErrorKind::InvalidParameters,
+            "[server.tcp] needs a token for clients to authenticate with".to_string(),
+        ));
+    }
+    let listener = TcpListener::bind((tcp.address.as_str(), tcp.port))?;
+    tracing::info!(address = tcp.address, port = tcp.port, "Listening over TCP");
+    Ok(Some(listener))
+}
+
+/// Setup a TCP socket for MPD clients, if there's a `[server.mpd]` section
+pub fn setup_mpd(config: &AppConfig) -> AppResult<Option<TcpListener>> {
+    let Some(mpd) = config.server_ref().mpd_ref() else {
+        return Ok(None);
+    };
+    let listener = TcpListener::bind((mpd.address.as_str(), mpd.port))?;
+    tracing::info!(
+        address = mpd.address,
+        port = mpd.port,
+        "Listening for MPD clients"
+    );
+    Ok(Some(listener))
+}
+
 /// run server
 pub fn run(config: AppConfig) -> AppResult {
     let events = Events::new();
@@ -44,6 +75,14 @@ pub fn run(config: AppConfig) -> AppResult {
         let server_event_tx = context.events.server_event_sender().clone();
         thread::spawn(|| listen_for_clients(listener, server_event_tx));
     }
+    if let Some(listener) = setup_tcp(context.config_ref())? {
+        let server_event_tx = context.events.server_event_sender().clone();
+        thread::spawn(|| listen_for_tcp_clients(listener, server_event_tx));
+    }
+    if let Some(listener) = setup_mpd(context.config_ref())? {
+        let server_event_tx = context.events.server_event_sender().clone();
+        thread::spawn(|| listen_for_mpd_clients(listener, server_event_tx));
+    }
 
     while context.quit == QuitType::DoNot {
         let event = match context.events.next() {
@@ -53,16 +92,15 @@ pub fn run(config: AppConfig) -> AppResult {
 
         tracing::debug!(?event, "Received server event");
         match event {
-            AppEvent::Client { uuid, request } => {
+            AppEvent::Client { uuid, id, request } => {
                 let res = server_util::process_client_request(&mut context, &uuid, &request);
-                if let Err(err) = res {
+                if let Err(err) = res.as_ref() {
                     tracing::debug!(?err, ?uuid, ?request, "Failed to process client request");
-                    context
-                        .events
-                        .broadcast_event(ServerBroadcastEvent::ServerError {
-                            msg: err.to_string(),
-                        });
                 }
+                let result = res
+                    .map(|payload| payload.map(Box::new))
+                    .map_err(|err| err.to_string());
+                context.events.send_reply(&uuid, id, result);
             }
             AppEvent::Server { event } => {
                 let res = server_util::process_server_event(&mut context, event);
@@ -80,8 +118,16 @@ pub fn run(config: AppConfig) -> AppResult {
 
     let mut file = std::fs::File::create(playlist_path)?;
     let mut writer = m3u::Writer::new(&mut file);
+    let mut previous_sheet = None;
     for song in playlist.contents.iter() {
-        let entry = m3u::Entry::Path(song.file_path().to_path_buf());
+        // CUE tracks are saved as their sheet, which loads back as all of its tracks
+        let sheet = song.file().cue.as_ref().map(|cue| cue.sheet.as_path());
+        if sheet.is_some() && sheet == previous_sheet {
+            continue;
+        }
+        previous_sheet = sheet;
+        let path = sheet.unwrap_or_else(|| song.file_path());
+        let entry = m3u::Entry::Path(path.to_path_buf());
         writer.write_entry(&entry)?;
     }
     tracing::debug!(?playlist_path, "Playlist saved!");
@@ -96,7 +142,26 @@ pub fn run(config: AppConfig) -> AppResult {
 
 pub fn listen_for_clients(listener: UnixListener, event_tx: ServerEventSender) -> AppResult {
     for stream in listener.incoming().flatten() {
-        let _ = event_tx.send(ServerEvent::NewClient(stream));
+        let _ = event_tx.send(ServerEvent::NewClient(stream.into()));
+    }
+    Ok(())
+}
+
+pub fn listen_for_tcp_clients(listener: TcpListener, event_tx: ServerEventSender) -> AppResult {
+    for stream in listener.incoming().flatten() {
+        tracing::debug!(peer = ?stream.peer_addr(), "New TCP client");
+        // events are small and should go out right away
+        let _ = stream.set_nodelay(true);
+        let _ = event_tx.send(ServerEvent::NewClient(stream.into()));
+    }
+    Ok(())
+}
+
+pub fn listen_for_mpd_clients(listener: TcpListener, event_tx: ServerEventSender) -> AppResult {
+    for stream in listener.incoming().flatten() {
+        tracing::debug!(peer = ?stream.peer_addr(), "New MPD client");
+        let _ = stream.set_nodelay(true);
+        let _ = event_tx.send(ServerEvent::NewMpdClient(stream));
     }
     Ok(())
 }
@@ -0,0 +1,18 @@
+use dizi::error::AppResult;
+use dizi::player::{AudioDevice, OutputFormat};
+
+use crate::context::AppContext;
+use crate::traits::AudioPlayer;
+
+pub fn audio_devices_list(context: &mut AppContext) -> AppResult<Vec<AudioDevice>> {
+    context.player.audio_devices()
+}
+
+pub fn audio_device_select(context: &mut AppContext, name: &str) -> AppResult<OutputFormat> {
+    context.player.set_audio_device(name)
+}
+
+/// Gets playback going again after the output device failed
+pub fn audio_device_reopen(context: &mut AppContext) -> AppResult<OutputFormat> {
+    context.player.reopen_audio_device()
+}
@@ -0,0 +1,24 @@
+use std::path::Path;
+
+use dizi::error::AppResult;
+
+use crate::audio::loudness::scan::spawn_scan;
+use crate::context::AppContext;
+
+/// Starts measuring the loudness of `path`, or of the current playlist if there's no `path`
+pub fn loudness_scan(context: &mut AppContext, path: Option<&Path>) -> AppResult {
+    let paths = match path {
+        Some(path) => vec![path.to_path_buf()],
+        None => context
+            .player
+            .playlist_context
+            .current_playlist_ref()
+            .contents
+            .iter()
+            .map(|entry| entry.file_path().to_path_buf())
+            .collect(),
+    };
+    let event_tx = context.events.server_event_sender().clone();
+    spawn_scan(paths, context.player.loudness_cache.clone(), event_tx);
+    Ok(())
+}
@@ -1,3 +1,5 @@
+pub mod audio;
+pub mod loudness;
 pub mod player;
 pub mod playlist;
 pub mod server;
@@ -1,8 +1,12 @@
 use std::path::Path;
+use std::time::Duration;
 
-use dizi::error::AppResult;
+use dizi::error::{AppResult, DiziError, DiziErrorKind};
 use dizi::player::PlayerStatus;
 
+use crate::audio::ab_loop::LoopPoint;
+use crate::audio::fade::FadeOut;
+use crate::audio::sleep::Sleep;
 use crate::context::AppContext;
 use crate::server_util::run_on_song_change;
 use crate::traits::AudioPlayer;
@@ -77,3 +81,161 @@ pub fn player_play_previous(context: &mut AppContext) -> AppResult {
     run_on_song_change(context);
     Ok(())
 }
+
+pub fn player_seek(context: &mut AppContext, position: Duration) -> AppResult {
+    context.player.seek(position)
+}
+
+pub fn player_seek_by(context: &mut AppContext, offset_ms: i64) -> AppResult {
+    context.player.seek_by(offset_ms)
+}
+
+/// Seeks to `percent` of the way through the current song
+pub fn player_seek_percent(context: &mut AppContext, percent: usize) -> AppResult {
+    let total_duration = context
+        .player
+        .current_song_ref()
+        .and_then(|song| song.audio_metadata.total_duration)
+        .ok_or_else(|| {
+            DiziError::new(
+                DiziErrorKind::InvalidParameters,
+                "Length of current song is unknown".to_string(),
+            )
+        })?;
+    let position = total_duration.mul_f64(percent.min(100) as f64 / 100.0);
+    player_seek(context, position)
+}
+
+pub fn player_select_track(context: &mut AppContext, track_id: u32) -> AppResult {
+    context.player.select_track(track_id)
+}
+
+/// The new loop is broadcast once the stream has picked it up
+pub fn player_set_loop_point(
+    context: &mut AppContext,
+    point: LoopPoint,
+    position_ms: Option<u64>,
+) -> AppResult {
+    if context.player.current_song_ref().is_none() {
+        return Err(DiziError::new(
+            DiziErrorKind::InvalidParameters,
+            "No song is playing".to_string(),
+        ));
+    }
+    context
+        .player
+        .set_loop_point(point, position_ms.map(Duration::from_millis))
+}
+
+pub fn player_clear_loop(context: &mut AppContext) -> AppResult {
+    context.player.clear_loop()
+}
+
+/// Returns the speed actually set, after clamping
+pub fn player_set_speed(context: &mut AppContext, speed: f32) -> AppResult<f32> {
+    context.player.set_speed(speed)?;
+    Ok(context.player.get_speed())
+}
+
+pub fn player_set_equalizer(context: &mut AppContext, preset: Option<&str>) -> AppResult {
+    context.player.set_equalizer(preset)
+}
+
+/// Switches to the preset after the active one, or to the first preset if the equalizer is off.
+/// Turns the equalizer off after the last preset.
+pub fn player_equalizer_next(context: &mut AppContext) -> AppResult<Option<String>> {
+    let presets = context.player.equalizer_presets();
+    let next = match context.player.get_equalizer() {
+        Some(current) => presets
+            .iter()
+            .position(|name| name == current)
+            .and_then(|index| presets.get(index + 1)),
+        None => presets.first(),
+    }
+    .cloned();
+    player_set_equalizer(context, next.as_deref())?;
+    Ok(next)
+}
+
+/// Stops playback once `minutes` have passed, fading out over the last `sleep_fade` seconds
+pub fn player_sleep(context: &mut AppContext, minutes: f32) -> AppResult {
+    let duration = Duration::try_from_secs_f32(minutes * 60.0)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| {
+            DiziError::new(
+                DiziErrorKind::InvalidParameters,
+                format!("Invalid sleep timer of {} minutes", minutes),
+            )
+        })?;
+    let event_tx = context.events.server_event_tx.clone();
+    player_set_sleep(context, Some(Sleep::timer(duration, event_tx)))?;
+    // a timer shorter than the fade starts fading straight away
+    player_sleep_tick(context)?;
+    Ok(())
+}
+
+/// Stops playback once `count` songs have finished, counting the current one
+pub fn player_sleep_tracks(context: &mut AppContext, count: usize) -> AppResult {
+    if count == 0 {
+        return Err(DiziError::new(
+            DiziErrorKind::InvalidParameters,
+            "Sleep timer needs at least one song".to_string(),
+        ));
+    }
+    player_set_sleep(context, Some(Sleep::Tracks { remaining: count }))
+}
+
+pub fn player_sleep_cancel(context: &mut AppContext) -> AppResult {
+    player_set_sleep(context, None)
+}
+
+/// Keeps the sleep timer fading out, stopping playback once its time is up.
+/// Returns whether it stopped playback
+pub fn player_sleep_tick(context: &mut AppContext) -> AppResult<bool> {
+    let Some(remaining) = context
+        .player
+        .sleep
+        .as_ref()
+        .and_then(Sleep::remaining_time)
+    else {
+        return Ok(false);
+    };
+    if remaining.is_zero() {
+        context.player.stop()?;
+        player_set_sleep(context, None)?;
+        return Ok(true);
+    }
+    let sleep_fade = context.config_ref().server_ref().player_ref().sleep_fade;
+    if remaining <= sleep_fade {
+        context.player.fade_out(FadeOut {
+            gain: remaining.as_secs_f32() / sleep_fade.as_secs_f32(),
+            remaining,
+        })?;
+    }
+    Ok(false)
+}
+
+/// Counts a finished song towards the sleep timer, stopping playback after the last one.
+/// Returns whether it stopped playback
+pub fn player_sleep_count_track(context: &mut AppContext) -> AppResult<bool> {
+    let Some(Sleep::Tracks { remaining }) = context.player.sleep.as_mut() else {
+        return Ok(false);
+    };
+    *remaining -= 1;
+    if *remaining > 0 {
+        return Ok(false);
+    }
+    context.player.sleep = None;
+    context.player.stop()?;
+    Ok(true)
+}
+
+/// Replaces the sleep timer, undoing any fade the old one started
+fn player_set_sleep(context: &mut AppContext, sleep: Option<Sleep>) -> AppResult {
+    let old = std::mem::replace(&mut context.player.sleep, sleep);
+    if matches!(old, Some(Sleep::Timer { .. })) {
+        context.player.fade_in()?;
+    }
+    Ok(())
+}
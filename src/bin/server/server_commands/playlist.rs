@@ -6,7 +6,7 @@ use dizi::error::{AppResult, DiziError, DiziErrorKind};
 use dizi::song::{DiziAudioFile, DiziFile, DiziSongEntry};
 
 use crate::context::AppContext;
-use crate::playlist::DiziPlaylist;
+use crate::playlist::{DiziPlaylist, cue, expand_cue_sheets};
 use crate::server_util::run_on_song_change;
 use crate::traits::{AudioPlayer, DiziPlaylistTrait};
 use crate::util::mimetype::is_playable;
@@ -48,6 +48,15 @@ pub fn playlist_append(context: &mut AppContext, path: &Path) -> AppResult<Vec<D
             playlist.push_entry(entry);
         }
         Ok(audio_files)
+    } else if let Some(tracks) = cue::cue_tracks(path) {
+        let audio_files = tracks
+            .into_iter()
+            .map(DiziAudioFile::try_from)
+            .collect::<AppResult<Vec<_>>>()?;
+        for audio_file in audio_files.iter() {
+            playlist.push_entry(DiziSongEntry::Loaded(audio_file.clone()));
+        }
+        Ok(audio_files)
     } else if is_playable(path)? {
         let file = DiziFile::new(path);
         let audio_file = DiziAudioFile::try_from(file)?;
@@ -130,15 +139,15 @@ fn find_songs_rec(songs: &mut Vec<DiziAudioFile>, path: &Path) {
     if let Ok(readdir) = fs::read_dir(path) {
         let mut paths: Vec<PathBuf> = readdir.flatten().map(|entry| entry.path()).collect();
         paths.sort_by(|p1, p2| sort_function(p1, p2));
-        for entry_path in paths.iter() {
-            if entry_path.is_dir() {
-                find_songs_rec(songs, entry_path);
-                continue;
-            }
+        let (dirs, files): (Vec<PathBuf>, Vec<PathBuf>) =
+            paths.into_iter().partition(|path| path.is_dir());
+        for dir in dirs.iter() {
+            find_songs_rec(songs, dir);
+        }
 
-            if let Ok(true) = is_playable(entry_path) {
-                tracing::debug!(file_path=?entry_path, "Adding file to playlist");
-                let file = DiziFile::new(entry_path);
+        for file in expand_cue_sheets(&files) {
+            if file.cue.is_some() || matches!(is_playable(&file.file_path), Ok(true)) {
+                tracing::debug!(file_path=?file.file_path, "Adding file to playlist");
                 if let Ok(audio_file) = DiziAudioFile::try_from(file) {
                     songs.push(audio_file);
                 }
@@ -1,7 +1,11 @@
 use dizi::error::AppResult;
+use dizi::protocol::{self, Feature};
+use dizi::request::client::ClientRequest;
+use dizi::response::server::ServerBroadcastEvent;
 
 use crate::{
     context::{AppContext, QuitType},
+    events::ClientHello,
     traits::AudioPlayer,
 };
 
@@ -15,3 +19,33 @@ pub fn query(context: &mut AppContext, query: &str) -> AppResult<String> {
     let res = player_state.query(query)?;
     Ok(res)
 }
+
+/// Settles on the features to use with the client `uuid`,
+/// answering with the version and features the server supports.
+///
+/// Clients too old to talk to are turned away before getting here,
+/// newer ones are expected to talk down to us
+pub fn hello(
+    context: &mut AppContext,
+    uuid: &str,
+    version: u32,
+    features: &[Feature],
+) -> ServerBroadcastEvent {
+    let hello = ClientHello {
+        features: features
+            .iter()
+            .filter(|feature| protocol::FEATURES.contains(feature))
+            .copied()
+            .collect(),
+    };
+    tracing::debug!(uuid, version, ?hello, "Client said hello");
+    context.events.client_hellos.insert(uuid.to_string(), hello);
+    ServerBroadcastEvent::ServerHello {
+        version: protocol::PROTOCOL_VERSION,
+        apis: ClientRequest::API_PATHS
+            .iter()
+            .map(|api| api.to_string())
+            .collect(),
+        features: protocol::FEATURES.to_vec(),
+    }
+}
@@ -1,19 +1,23 @@
 use std::process::Command;
 use std::sync::mpsc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use uuid::Uuid;
 
 use dizi::error::AppResult;
 use dizi::player::PlayerStatus;
 use dizi::playlist::PlaylistType;
+use dizi::protocol::Topic;
 use dizi::request::client::ClientRequest;
 use dizi::response::server::ServerBroadcastEvent;
 
+use crate::audio::ab_loop::LoopPoint;
+use crate::audio::sleep::Sleep;
 use crate::client;
 use crate::context::AppContext;
 use crate::events::ServerEvent;
+use crate::mpd;
 use crate::server_commands::*;
 use crate::traits::AudioPlayer;
 
@@ -27,30 +31,159 @@ pub fn process_server_event(context: &mut AppContext, event: ServerEvent) -> App
             let client_uuid = Uuid::new_v4();
             let uuid_string = client_uuid.to_string();
 
+            // TCP clients have to give the token first
+            let token = match stream.is_tcp() {
+                true => context
+                    .config_ref()
+                    .server_ref()
+                    .tcp_ref()
+                    .map(|tcp| tcp.token.clone()),
+                false => None,
+            };
             // thread to listen to client requests
-            thread::spawn(move || client::handle_client(client_uuid, stream, client_tx, server_rx));
+            thread::spawn(move || {
+                client::handle_client(client_uuid, stream, token, client_tx, server_rx)
+            });
             context
                 .events
                 .add_broadcast_listener(uuid_string, server_tx);
         }
+        ServerEvent::NewMpdClient(stream) => {
+            let Some(option) = context.config_ref().server_ref().mpd_ref().cloned() else {
+                return Ok(());
+            };
+            let client_tx = context.events.client_request_sender().clone();
+            let (server_tx, server_rx) = mpsc::channel();
+
+            let client_uuid = Uuid::new_v4();
+            let uuid_string = client_uuid.to_string();
+            context
+                .events
+                .add_broadcast_listener(uuid_string.clone(), server_tx);
+            // MPD has no use for these, they'd only pile up
+            context.events.subscribe(
+                &uuid_string,
+                &[Topic::Progress, Topic::Errors, Topic::Library],
+                false,
+            )?;
+            thread::spawn(move || {
+                mpd::handle_mpd_client(client_uuid, stream, option, client_tx, server_rx)
+            });
+        }
         ServerEvent::PlayerProgressUpdate(elapsed) => {
             context.player.set_elapsed(elapsed);
             context
                 .events
                 .broadcast_event(ServerBroadcastEvent::PlayerProgressUpdate { elapsed });
         }
+        ServerEvent::PlayerTrackEnding => {
+            process_song_ending(context)?;
+        }
+        ServerEvent::PlayerTrackChanged => {
+            context.player.play_preloaded()?;
+            // the sleep timer was set after the next song was queued up
+            if count_sleep_track(context)? {
+                return Ok(());
+            }
+            run_on_song_change(context);
+            send_latest_song_info(context)?;
+        }
         ServerEvent::PlayerDone => {
             process_done_song(context)?;
         }
+        ServerEvent::PlayerLoopChanged(ab_loop) => {
+            context.player.set_ab_loop(ab_loop);
+            context
+                .events
+                .broadcast_event(ServerBroadcastEvent::PlayerLoop {
+                    a: ab_loop.a,
+                    b: ab_loop.b,
+                });
+        }
+        ServerEvent::PlayerSpectrum(spectrum) => {
+            context
+                .events
+                .broadcast_event(ServerBroadcastEvent::PlayerSpectrum { spectrum });
+        }
+        ServerEvent::SleepTick => {
+            if player_sleep_tick(context)? {
+                context
+                    .events
+                    .broadcast_event(ServerBroadcastEvent::PlayerStop);
+            }
+            broadcast_sleep(context);
+        }
+        ServerEvent::PlayerDeviceError(msg) => {
+            tracing::error!(msg, "Output device failed");
+            context
+                .events
+                .broadcast_event(ServerBroadcastEvent::ServerError {
+                    msg: format!("Audio device failed: {msg}"),
+                });
+            retry_audio_device(context)?;
+        }
+        ServerEvent::DeviceRetry => match audio::audio_device_reopen(context) {
+            Ok(output_format) => {
+                let name = context
+                    .player
+                    .state
+                    .audio_device
+                    .clone()
+                    .unwrap_or_default();
+                tracing::info!(name, "Reopened output device");
+                context
+                    .events
+                    .broadcast_event(ServerBroadcastEvent::AudioDeviceChanged {
+                        name,
+                        output_format,
+                    });
+            }
+            Err(err) => {
+                tracing::warn!(?err, "Failed to reopen output device");
+                retry_audio_device(context)?;
+            }
+        },
+        ServerEvent::LoudnessScanned {
+            path,
+            result,
+            scanned,
+            total,
+        } => {
+            let integrated = match result {
+                Ok(entry) => {
+                    let integrated = entry.loudness.integrated;
+                    context.player.loudness_cache.insert(path.clone(), entry);
+                    Some(integrated)
+                }
+                Err(err) => {
+                    tracing::debug!(?path, ?err, "Failed to scan loudness");
+                    None
+                }
+            };
+            // save as we go, so an interrupted scan doesn't have to start over
+            if let Err(err) = context.player.loudness_cache.save() {
+                tracing::error!(?err, "Failed to save loudness cache");
+            }
+            context
+                .events
+                .broadcast_event(ServerBroadcastEvent::LoudnessScanProgress {
+                    path,
+                    scanned,
+                    total,
+                    integrated,
+                });
+        }
     }
     Ok(())
 }
 
+/// Carries out a client's request,
+/// returning what to send back to that client if it asked for anything
 pub fn process_client_request(
     context: &mut AppContext,
     uuid: &str,
     event: &ClientRequest,
-) -> AppResult {
+) -> AppResult<Option<ServerBroadcastEvent>> {
     tracing::debug!(uuid, request=?event, "Processing client request");
     match event {
         ClientRequest::ServerQuit => {
@@ -58,18 +191,27 @@ pub fn process_client_request(
         }
         ClientRequest::ServerQuery { query } => {
             let res = server::query(context, query)?;
-            context
-                .events
-                .broadcast_event(ServerBroadcastEvent::ServerQuery { query: res });
+            return Ok(Some(ServerBroadcastEvent::ServerQuery { query: res }));
+        }
+        ClientRequest::ServerHello { version, features } => {
+            return Ok(Some(server::hello(context, uuid, *version, features)));
         }
         ClientRequest::ClientLeave { uuid } => {
             let _ = context.events.server_broadcast_listeners.remove(uuid);
+            let _ = context.events.client_hellos.remove(uuid);
+            update_spectrum_tap(context);
+        }
+        // answered by the client's own thread, never gets here
+        ClientRequest::ClientAuth { .. } => {}
+        ClientRequest::ClientSubscribe { topics } => {
+            subscribe(context, uuid, topics, true)?;
+        }
+        ClientRequest::ClientUnsubscribe { topics } => {
+            subscribe(context, uuid, topics, false)?;
         }
         ClientRequest::PlayerState => {
             let state = context.player.player_state();
-            context
-                .events
-                .broadcast_event(ServerBroadcastEvent::PlayerState { state });
+            return Ok(Some(ServerBroadcastEvent::PlayerState { state }));
         }
         ClientRequest::PlayerFilePlay { path: Some(p) } => {
             player_play(context, p.as_path())?;
@@ -104,6 +246,67 @@ pub fn process_client_request(
                 .events
                 .broadcast_event(ServerBroadcastEvent::PlayerVolumeUpdate { volume });
         }
+        ClientRequest::PlayerTrackSelect { track_id } => {
+            player_select_track(context, *track_id)?;
+            context
+                .events
+                .broadcast_event(ServerBroadcastEvent::PlayerTrack {
+                    track_id: *track_id,
+                });
+        }
+        ClientRequest::PlayerLoopSetA { position_ms } => {
+            player_set_loop_point(context, LoopPoint::A, *position_ms)?;
+        }
+        ClientRequest::PlayerLoopSetB { position_ms } => {
+            player_set_loop_point(context, LoopPoint::B, *position_ms)?;
+        }
+        ClientRequest::PlayerLoopClear => {
+            player_clear_loop(context)?;
+        }
+        ClientRequest::PlayerSpeedSet { speed } => {
+            let speed = player_set_speed(context, *speed)?;
+            context
+                .events
+                .broadcast_event(ServerBroadcastEvent::PlayerSpeed { speed });
+        }
+        ClientRequest::PlayerEqSet { preset } => {
+            player_set_equalizer(context, Some(preset))?;
+            context
+                .events
+                .broadcast_event(ServerBroadcastEvent::PlayerEqualizer {
+                    preset: Some(preset.clone()),
+                });
+        }
+        ClientRequest::PlayerEqNext => {
+            let preset = player_equalizer_next(context)?;
+            context
+                .events
+                .broadcast_event(ServerBroadcastEvent::PlayerEqualizer { preset });
+        }
+        ClientRequest::PlayerEqOff => {
+            player_set_equalizer(context, None)?;
+            context
+                .events
+                .broadcast_event(ServerBroadcastEvent::PlayerEqualizer { preset: None });
+        }
+        ClientRequest::PlayerSleep { minutes } => {
+            player_sleep(context, *minutes)?;
+            broadcast_sleep(context);
+        }
+        ClientRequest::PlayerSleepTracks { count } => {
+            player_sleep_tracks(context, *count)?;
+            broadcast_sleep(context);
+        }
+        ClientRequest::PlayerSleepCancel => {
+            player_sleep_cancel(context)?;
+            broadcast_sleep(context);
+        }
+        ClientRequest::PlayerSpectrumSubscribe => {
+            subscribe(context, uuid, &[Topic::Spectrum], true)?;
+        }
+        ClientRequest::PlayerSpectrumUnsubscribe => {
+            subscribe(context, uuid, &[Topic::Spectrum], false)?;
+        }
         ClientRequest::PlayerTogglePlay => {
             let status = player_toggle_play(context)?;
             match status {
@@ -206,19 +409,42 @@ pub fn process_client_request(
                 .broadcast_event(ServerBroadcastEvent::PlayerShuffle { on: !enabled });
         }
         ClientRequest::PlayerFastForward { amount } => {
-            let duration = Duration::from_secs(*amount as u64);
-            context.player.fast_forward(duration)?;
+            player_seek_by(context, *amount as i64 * 1000)?;
         }
         ClientRequest::PlayerRewind { amount } => {
-            let duration = Duration::from_secs(*amount as u64);
-            context.player.rewind(duration)?;
+            player_seek_by(context, -(*amount as i64) * 1000)?;
+        }
+        ClientRequest::PlayerSeek { position_ms } => {
+            player_seek(context, Duration::from_millis(*position_ms))?;
+        }
+        ClientRequest::PlayerSeekRelative { offset_ms } => {
+            player_seek_by(context, *offset_ms)?;
+        }
+        ClientRequest::PlayerSeekPercent { percent } => {
+            player_seek_percent(context, *percent)?;
+        }
+        ClientRequest::LoudnessScan { path } => {
+            loudness::loudness_scan(context, path.as_deref())?;
+        }
+        ClientRequest::AudioDevicesList => {
+            let devices = audio::audio_devices_list(context)?;
+            return Ok(Some(ServerBroadcastEvent::AudioDevices { devices }));
+        }
+        ClientRequest::AudioDeviceSelect { name: Some(name) } => {
+            let output_format = audio::audio_device_select(context, name)?;
+            context
+                .events
+                .broadcast_event(ServerBroadcastEvent::AudioDeviceChanged {
+                    name: name.clone(),
+                    output_format,
+                });
         }
         ClientRequest::ServerQueryAll => {}
         request => {
             tracing::warn!(?request, "Client request not implemented");
         }
     }
-    Ok(())
+    Ok(None)
 }
 
 pub fn send_latest_song_info(context: &mut AppContext) -> AppResult {
@@ -246,6 +472,10 @@ pub fn send_latest_song_info(context: &mut AppContext) -> AppResult {
 pub fn process_done_song(context: &mut AppContext) -> AppResult {
     tracing::debug!("Processing done song trigger");
 
+    if count_sleep_track(context)? {
+        return Ok(());
+    }
+
     let next_enabled = context.player.next_enabled();
     let repeat_enabled = context.player.repeat_enabled();
 
@@ -267,6 +497,37 @@ pub fn process_done_song(context: &mut AppContext) -> AppResult {
     Ok(())
 }
 
+/// Preloads whichever song `process_done_song` would play next,
+/// so playback can continue without a gap
+pub fn process_song_ending(context: &mut AppContext) -> AppResult {
+    tracing::debug!("Processing song ending trigger");
+
+    // leave nothing queued after the sleep timer's last song
+    if matches!(context.player.sleep, Some(Sleep::Tracks { remaining: 1 })) {
+        return Ok(());
+    }
+
+    let next_enabled = context.player.next_enabled();
+    let repeat_enabled = context.player.repeat_enabled();
+
+    let res = if next_enabled {
+        if !repeat_enabled && end_of_playlist(context) {
+            Ok(())
+        } else {
+            context.player.preload_next()
+        }
+    } else if repeat_enabled {
+        context.player.preload_again()
+    } else {
+        Ok(())
+    };
+    // not fatal, process_done_song will try again once the song ends
+    if let Err(err) = res {
+        tracing::debug!(?err, "Failed to preload next song");
+    }
+    Ok(())
+}
+
 pub fn end_of_playlist(context: &AppContext) -> bool {
     context.player.playlist_context.is_end()
 }
@@ -282,3 +543,76 @@ pub fn run_on_song_change(context: &AppContext) {
         });
     }
 }
+
+/// Counts a finished song towards the sleep timer, letting clients know.
+/// Returns whether that was the last song to play
+fn count_sleep_track(context: &mut AppContext) -> AppResult<bool> {
+    if !matches!(context.player.sleep, Some(Sleep::Tracks { .. })) {
+        return Ok(false);
+    }
+    let stopped = player_sleep_count_track(context)?;
+    if stopped {
+        context
+            .events
+            .broadcast_event(ServerBroadcastEvent::PlayerStop);
+    }
+    broadcast_sleep(context);
+    Ok(stopped)
+}
+
+/// Tries the output device again once the retry policy says so,
+/// or stops playback if it has run out of attempts
+fn retry_audio_device(context: &mut AppContext) -> AppResult {
+    let policy = context.config_ref().server_ref().device_retry;
+    match context
+        .player
+        .device_recovery
+        .failed(&policy, Instant::now())
+    {
+        Some(wait) => {
+            let event_tx = context.events.server_event_tx.clone();
+            thread::spawn(move || {
+                thread::sleep(wait);
+                let _ = event_tx.send(ServerEvent::DeviceRetry);
+            });
+        }
+        None => {
+            let attempts = context.player.device_recovery.failures() - 1;
+            context.player.stop()?;
+            context
+                .events
+                .broadcast_event(ServerBroadcastEvent::ServerError {
+                    msg: format!("Gave up on the audio device after {attempts} attempts"),
+                });
+            context
+                .events
+                .broadcast_event(ServerBroadcastEvent::PlayerStop);
+        }
+    }
+    Ok(())
+}
+
+fn broadcast_sleep(context: &mut AppContext) {
+    let sleep = context.player.sleep.as_ref().map(Sleep::state);
+    context
+        .events
+        .broadcast_event(ServerBroadcastEvent::PlayerSleep { sleep });
+}
+
+/// Adds or removes `topics` for the client `uuid`
+fn subscribe(
+    context: &mut AppContext,
+    uuid: &str,
+    topics: &[Topic],
+    subscribed: bool,
+) -> AppResult {
+    context.events.subscribe(uuid, topics, subscribed)?;
+    update_spectrum_tap(context);
+    Ok(())
+}
+
+/// The spectrum is only worked out while a client wants it
+fn update_spectrum_tap(context: &mut AppContext) {
+    let enabled = context.events.is_subscribed(Topic::Spectrum);
+    context.player.spectrum_tap.set_enabled(enabled);
+}
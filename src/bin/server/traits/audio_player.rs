@@ -2,9 +2,11 @@ use std::path::Path;
 use std::time;
 
 use dizi::error::AppResult;
-use dizi::player::{PlayerState, PlayerStatus};
+use dizi::player::{AudioDevice, OutputFormat, PlayerState, PlayerStatus};
 use dizi::song::DiziAudioFile;
 
+use crate::audio::ab_loop::{AbLoop, LoopPoint};
+use crate::audio::fade::FadeOut;
 use crate::context::PlaylistContext;
 
 pub trait AudioPlayer {
@@ -17,17 +19,60 @@ pub trait AudioPlayer {
     fn play_next(&mut self) -> AppResult;
     fn play_previous(&mut self) -> AppResult;
 
+    /// Queue up the song `play_next`/`play_again` would play,
+    /// so it starts the moment the current song ends
+    fn preload_next(&mut self) -> AppResult;
+    fn preload_again(&mut self) -> AppResult;
+    /// The player has moved on to the preloaded song
+    fn play_preloaded(&mut self) -> AppResult;
+
     fn pause(&mut self) -> AppResult;
     fn resume(&mut self) -> AppResult;
     fn stop(&mut self) -> AppResult;
     fn toggle_play(&mut self) -> AppResult<PlayerStatus>;
 
-    fn fast_forward(&mut self, duration: time::Duration) -> AppResult;
-    fn rewind(&mut self, duration: time::Duration) -> AppResult;
+    /// Moves to `position` in the current song
+    fn seek(&mut self, position: time::Duration) -> AppResult;
+    /// Moves forward by `offset_ms` milliseconds, or backward if negative
+    fn seek_by(&mut self, offset_ms: i64) -> AppResult;
+
+    /// Slowly fades towards silence while playback carries on
+    fn fade_out(&mut self, fade_out: FadeOut) -> AppResult;
+    /// Back to full volume after `fade_out`
+    fn fade_in(&mut self) -> AppResult;
 
     fn get_volume(&self) -> usize;
     fn set_volume(&mut self, volume: usize) -> AppResult;
 
+    /// Moves one end of the A–B loop to `position`, or to where playback is if `None`.
+    /// The player state is updated once the stream reports the new loop
+    fn set_loop_point(&mut self, point: LoopPoint, position: Option<time::Duration>) -> AppResult;
+    fn clear_loop(&mut self) -> AppResult;
+    /// Records the loop the stream is playing
+    fn set_ab_loop(&mut self, ab_loop: AbLoop);
+
+    /// Switches the current song to its audio track with id `track_id`
+    fn select_track(&mut self, track_id: u32) -> AppResult;
+
+    fn get_speed(&self) -> f32;
+    /// Plays at `speed` times normal speed, clamped to the supported range
+    fn set_speed(&mut self, speed: f32) -> AppResult;
+
+    /// Name of the active equalizer preset
+    fn get_equalizer(&self) -> Option<&str>;
+    /// Switches to the equalizer preset called `preset`, or turns the equalizer off
+    fn set_equalizer(&mut self, preset: Option<&str>) -> AppResult;
+    /// Names of all configured equalizer presets, in order
+    fn equalizer_presets(&self) -> Vec<String>;
+
+    /// Output devices audio can be played on
+    fn audio_devices(&self) -> AppResult<Vec<AudioDevice>>;
+    /// Moves playback to the output device called `name`, continuing from the same position
+    fn set_audio_device(&mut self, name: &str) -> AppResult<OutputFormat>;
+    /// Rebuilds the stream after the output device failed, on the same device if it's back
+    /// or the default one otherwise, continuing from the same position
+    fn reopen_audio_device(&mut self) -> AppResult<OutputFormat>;
+
     fn next_enabled(&self) -> bool;
     fn repeat_enabled(&self) -> bool;
     fn shuffle_enabled(&self) -> bool;
@@ -0,0 +1,10 @@
+/// Compares every byte, so how long it takes doesn't give away
+/// how much of the token was right
+pub fn tokens_match(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
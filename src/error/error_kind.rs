@@ -31,6 +31,11 @@ pub enum DiziErrorKind {
 
     UnrecognizedArgument,
     UnrecognizedCommand,
+
+    // client and server can't talk to each other
+    IncompatibleProtocol,
+    // client didn't give the right token
+    Unauthorized,
 }
 
 impl From<io::ErrorKind> for DiziErrorKind {
@@ -1,6 +1,7 @@
 pub mod error;
 pub mod player;
 pub mod playlist;
+pub mod protocol;
 pub mod request;
 pub mod response;
 pub mod song;
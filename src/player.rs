@@ -27,6 +27,76 @@ impl std::fmt::Display for PlayerStatus {
     }
 }
 
+/// Format audio is converted to before it is sent to the audio device
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputFormat {
+    pub channels: usize,
+    pub sample_rate: u32,
+    pub sample_format: String,
+}
+
+/// A range of formats an audio device can play
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioDeviceConfig {
+    pub channels: usize,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    pub sample_format: String,
+}
+
+/// An audio output device of the server's audio host
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioDevice {
+    /// identifies the device when selecting it
+    pub name: String,
+    /// human readable name
+    pub description: String,
+    /// whether this is the host's default device
+    pub default: bool,
+    pub configs: Vec<AudioDeviceConfig>,
+}
+
+/// Lowest level a spectrum or meter reports, in dBFS
+pub const SPECTRUM_FLOOR_DB: f32 = -90.0;
+
+/// Frequency spectrum and levels of the audio going to the output device,
+/// for visualizers. Everything is in dBFS, down to `SPECTRUM_FLOOR_DB`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Spectrum {
+    /// logarithmically spaced frequency bands, lowest first
+    pub bands: Vec<f32>,
+    /// peak level of each channel since the last update
+    pub peak: Vec<f32>,
+    /// RMS level of each channel since the last update
+    pub rms: Vec<f32>,
+}
+
+/// When the player is going to stop by itself
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SleepTimer {
+    /// stops once this much time has passed, fading out towards the end
+    Timer { remaining: time::Duration },
+    /// stops at the end of this many songs, counting the current one
+    Tracks { remaining: usize },
+}
+
+impl std::fmt::Display for SleepTimer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Timer { remaining } => {
+                let secs = remaining.as_secs();
+                if secs >= 3600 {
+                    write!(f, "{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+                } else {
+                    write!(f, "{:02}:{:02}", secs / 60, secs % 60)
+                }
+            }
+            Self::Tracks { remaining: 1 } => f.write_str("after this song"),
+            Self::Tracks { remaining } => write!(f, "after {} songs", remaining),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PlayerState {
     pub song: Option<DiziAudioFile>,
@@ -35,15 +105,30 @@ pub struct PlayerState {
     pub status: PlayerStatus,
     pub playlist_status: PlaylistType,
 
+    /// start and end of the section of the song being repeated
+    pub loop_a: Option<time::Duration>,
+    pub loop_b: Option<time::Duration>,
+
     pub volume: usize,
+    /// playback speed, 1.0 being normal speed
+    pub speed: f32,
+    /// ReplayGain applied to the current song in dB
+    pub replaygain: Option<f32>,
+    /// name of the active equalizer preset
+    pub equalizer: Option<String>,
 
     pub next: bool,
     pub repeat: bool,
     pub shuffle: bool,
+    /// sleep timer, if one is set
+    pub sleep: Option<SleepTimer>,
 
     pub playlist: FilePlaylist,
 
     pub audio_host: String,
+    /// name of the output device playing the audio
+    pub audio_device: Option<String>,
+    pub output_format: Option<OutputFormat>,
 }
 
 impl PlayerState {
@@ -78,6 +163,24 @@ impl PlayerState {
             "player.volume".to_string(),
             format!("{}", player_state.volume),
         );
+        vars.insert(
+            "player.speed".to_string(),
+            format!("{:.2}", player_state.speed),
+        );
+        vars.insert(
+            "player.replaygain".to_string(),
+            match player_state.replaygain {
+                Some(gain) => format!("{:+.2} dB", gain),
+                None => "off".to_string(),
+            },
+        );
+        vars.insert(
+            "player.equalizer".to_string(),
+            player_state
+                .equalizer
+                .clone()
+                .unwrap_or_else(|| "off".to_string()),
+        );
         vars.insert("player.next".to_string(), format!("{}", player_state.next));
         vars.insert(
             "player.repeat".to_string(),
@@ -87,6 +190,13 @@ impl PlayerState {
             "player.shuffle".to_string(),
             format!("{}", player_state.shuffle),
         );
+        vars.insert(
+            "player.sleep".to_string(),
+            match player_state.sleep {
+                Some(sleep) => sleep.to_string(),
+                None => "off".to_string(),
+            },
+        );
         vars.insert(
             "playlist.status".to_string(),
             player_state.playlist_status.to_string(),
@@ -100,6 +210,23 @@ impl PlayerState {
             format!("{}", player_state.playlist.len()),
         );
         vars.insert("audio.host".to_string(), player_state.audio_host.clone());
+        if let Some(audio_device) = player_state.audio_device.as_ref() {
+            vars.insert("audio.device".to_string(), audio_device.clone());
+        }
+        if let Some(output_format) = player_state.output_format.as_ref() {
+            vars.insert(
+                "audio.channels".to_string(),
+                output_format.channels.to_string(),
+            );
+            vars.insert(
+                "audio.sample_rate".to_string(),
+                output_format.sample_rate.to_string(),
+            );
+            vars.insert(
+                "audio.sample_format".to_string(),
+                output_format.sample_format.clone(),
+            );
+        }
     }
 
     fn load_song_query_vars(vars: &mut HashMap<String, String>, song: &DiziAudioFile) {
@@ -114,6 +241,20 @@ impl PlayerState {
                 value.to_string(),
             );
         }
+        if let Some(cue) = song.file.cue.as_ref() {
+            vars.insert(
+                "song.cue_sheet".to_string(),
+                cue.sheet.to_string_lossy().to_string(),
+            );
+            vars.insert("song.cue_track".to_string(), cue.number.to_string());
+        }
+        if let Some(track) = song.audio_metadata.track() {
+            vars.insert("song.audio_track".to_string(), track.id.to_string());
+            vars.insert("song.audio_track.codec".to_string(), track.codec.clone());
+            if let Some(language) = track.language.as_ref() {
+                vars.insert("song.audio_track.language".to_string(), language.clone());
+            }
+        }
         if let Some(total_duration) = song.audio_metadata.total_duration.as_ref() {
             vars.insert(
                 "song.total_duration".to_string(),
@@ -130,12 +271,20 @@ impl std::default::Default for PlayerState {
             status: PlayerStatus::Stopped,
             playlist_status: PlaylistType::PlaylistFile,
             elapsed: time::Duration::from_secs(0),
+            loop_a: None,
+            loop_b: None,
             volume: 50,
+            speed: 1.0,
+            replaygain: None,
+            equalizer: None,
             next: true,
             repeat: false,
             shuffle: false,
+            sleep: None,
             playlist: FilePlaylist::new(),
             audio_host: "UNKNOWN".to_string(),
+            audio_device: None,
+            output_format: None,
         }
     }
 }
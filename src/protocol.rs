@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+
+/// Version of the socket protocol, bumped whenever requests or events change
+/// in a way the other side can't just ignore
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest version of the other side we can still talk to
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Optional parts of the protocol, agreed on in the `/server/hello` exchange
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// `PlayerSpectrum` events.
+    ///
+    /// A client that says hello without it can't subscribe to `Topic::Spectrum`,
+    /// `/player/spectrum/subscribe` fails for it with an `IncompatibleProtocol` error.
+    /// Clients that never say hello can still subscribe, like before the hello existed
+    Spectrum,
+    /// whatever a newer peer has that we don't know about
+    #[serde(other)]
+    Unknown,
+}
+
+/// Kinds of events a client can subscribe to with `/client/subscribe`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    /// playback, volume, output device and other player changes
+    Player,
+    /// playlist changes
+    Playlist,
+    /// where playback is at, several times a second
+    Progress,
+    /// problems the server ran into on its own
+    Errors,
+    /// background scans of the music files
+    Library,
+    /// levels for visualizers, many times a second
+    Spectrum,
+    /// whatever a newer peer has that we don't know about
+    #[serde(other)]
+    Unknown,
+}
+
+impl Topic {
+    /// What clients are subscribed to when they connect,
+    /// everything but the spectrum
+    pub const DEFAULT: &'static [Topic] = &[
+        Topic::Player,
+        Topic::Playlist,
+        Topic::Progress,
+        Topic::Errors,
+        Topic::Library,
+    ];
+}
+
+/// Features this build supports
+pub const FEATURES: &[Feature] = &[Feature::Spectrum];
+
+/// Whether we can talk to a peer speaking `version`,
+/// a newer peer is expected to talk down to us
+pub fn is_compatible(version: u32) -> bool {
+    version >= MIN_PROTOCOL_VERSION
+}
+
+/// The versions we can talk, like "1" or "1 to 3"
+pub fn supported_versions() -> String {
+    if MIN_PROTOCOL_VERSION == PROTOCOL_VERSION {
+        PROTOCOL_VERSION.to_string()
+    } else {
+        format!("{MIN_PROTOCOL_VERSION} to {PROTOCOL_VERSION}")
+    }
+}
@@ -1,7 +1,10 @@
+use std::fmt;
 use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
+use crate::protocol::{Feature, Topic};
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(tag = "api")]
 pub enum ClientRequest {
@@ -12,10 +15,25 @@ pub enum ClientRequest {
     ServerQuery { query: String },
     #[serde(rename = "/server/query_all")]
     ServerQueryAll,
+    // first thing sent on connecting, answered with the server's own hello
+    #[serde(rename = "/server/hello")]
+    ServerHello {
+        version: u32,
+        #[serde(default)]
+        features: Vec<Feature>,
+    },
 
     // client left
     #[serde(rename = "/client/leave")]
     ClientLeave { uuid: String },
+    // first thing sent over TCP, with the token from the server's config
+    #[serde(rename = "/client/auth")]
+    ClientAuth { token: AuthToken },
+    // choose which events to get
+    #[serde(rename = "/client/subscribe")]
+    ClientSubscribe { topics: Vec<Topic> },
+    #[serde(rename = "/client/unsubscribe")]
+    ClientUnsubscribe { topics: Vec<Topic> },
 
     // player requests
     #[serde(rename = "/player/state")]
@@ -39,6 +57,22 @@ pub enum ClientRequest {
     PlayerRewind { amount: usize },
     #[serde(rename = "/player/fast_forward")]
     PlayerFastForward { amount: usize },
+    #[serde(rename = "/player/seek")]
+    PlayerSeek { position_ms: u64 },
+    #[serde(rename = "/player/seek/relative")]
+    PlayerSeekRelative { offset_ms: i64 },
+    #[serde(rename = "/player/seek/percent")]
+    PlayerSeekPercent { percent: usize },
+
+    #[serde(rename = "/player/track/select")]
+    PlayerTrackSelect { track_id: u32 },
+
+    #[serde(rename = "/player/loop/set_a")]
+    PlayerLoopSetA { position_ms: Option<u64> },
+    #[serde(rename = "/player/loop/set_b")]
+    PlayerLoopSetB { position_ms: Option<u64> },
+    #[serde(rename = "/player/loop/clear")]
+    PlayerLoopClear,
 
     #[serde(rename = "/player/toggle/play")]
     PlayerTogglePlay,
@@ -54,6 +88,28 @@ pub enum ClientRequest {
     #[serde(rename = "/player/volume/decrease")]
     PlayerVolumeDown { amount: usize },
 
+    #[serde(rename = "/player/speed/set")]
+    PlayerSpeedSet { speed: f32 },
+
+    #[serde(rename = "/player/eq/set")]
+    PlayerEqSet { preset: String },
+    #[serde(rename = "/player/eq/next")]
+    PlayerEqNext,
+    #[serde(rename = "/player/eq/off")]
+    PlayerEqOff,
+
+    #[serde(rename = "/player/sleep")]
+    PlayerSleep { minutes: f32 },
+    #[serde(rename = "/player/sleep/tracks")]
+    PlayerSleepTracks { count: usize },
+    #[serde(rename = "/player/sleep/cancel")]
+    PlayerSleepCancel,
+
+    #[serde(rename = "/player/spectrum/subscribe")]
+    PlayerSpectrumSubscribe,
+    #[serde(rename = "/player/spectrum/unsubscribe")]
+    PlayerSpectrumUnsubscribe,
+
     // playlist requests
     #[serde(rename = "/playlist/state")]
     PlaylistState,
@@ -75,15 +131,110 @@ pub enum ClientRequest {
     PlaylistMoveUp { index: Option<usize> },
     #[serde(rename = "/playlist/move_down")]
     PlaylistMoveDown { index: Option<usize> },
+
+    // loudness requests
+    #[serde(rename = "/loudness/scan")]
+    LoudnessScan { path: Option<PathBuf> },
+
+    // audio output requests
+    #[serde(rename = "/audio/devices/list")]
+    AudioDevicesList,
+    #[serde(rename = "/audio/devices/select")]
+    AudioDeviceSelect { name: Option<String> },
+}
+
+/// The token a client authenticates with over TCP,
+/// kept out of `Debug` output so it doesn't end up in logs
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct AuthToken(pub String);
+
+impl fmt::Debug for AuthToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AuthToken(<redacted>)")
+    }
+}
+
+/// Picks out the server's reply to a request
+pub type RequestId = u64;
+
+/// A request as sent over the socket, with the id the server replies with.
+///
+/// The id sits alongside the request's own fields,
+/// like `{"api": "/player/pause", "id": 3}`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClientRequestMessage {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<RequestId>,
+    #[serde(flatten)]
+    pub request: ClientRequest,
 }
 
 impl ClientRequest {
+    /// Every `api` a request can have, as listed in the server's hello
+    pub const API_PATHS: &'static [&'static str] = &[
+        "/client/leave",
+        "/client/auth",
+        "/client/subscribe",
+        "/client/unsubscribe",
+        "/server/quit",
+        "/server/query",
+        "/server/query_all",
+        "/server/hello",
+        "/player/state",
+        "/player/play/file",
+        "/player/play/next",
+        "/player/play/previous",
+        "/player/pause",
+        "/player/resume",
+        "/player/volume/get",
+        "/player/rewind",
+        "/player/fast_forward",
+        "/player/seek",
+        "/player/seek/relative",
+        "/player/seek/percent",
+        "/player/track/select",
+        "/player/loop/set_a",
+        "/player/loop/set_b",
+        "/player/loop/clear",
+        "/player/toggle/play",
+        "/player/toggle/next",
+        "/player/toggle/repeat",
+        "/player/toggle/shuffle",
+        "/player/volume/increase",
+        "/player/volume/decrease",
+        "/player/speed/set",
+        "/player/eq/set",
+        "/player/eq/next",
+        "/player/eq/off",
+        "/player/sleep",
+        "/player/sleep/tracks",
+        "/player/sleep/cancel",
+        "/player/spectrum/subscribe",
+        "/player/spectrum/unsubscribe",
+        "/playlist/state",
+        "/playlist/open",
+        "/playlist/play",
+        "/playlist/append",
+        "/playlist/remove",
+        "/playlist/clear",
+        "/playlist/move_up",
+        "/playlist/move_down",
+        "/loudness/scan",
+        "/audio/devices/list",
+        "/audio/devices/select",
+    ];
+
     pub fn api_path(&self) -> &'static str {
         match self {
             Self::ClientLeave { .. } => "/client/leave",
+            Self::ClientAuth { .. } => "/client/auth",
+            Self::ClientSubscribe { .. } => "/client/subscribe",
+            Self::ClientUnsubscribe { .. } => "/client/unsubscribe",
             Self::ServerQuit => "/server/quit",
             Self::ServerQuery { .. } => "/server/query",
             Self::ServerQueryAll => "/server/query_all",
+            Self::ServerHello { .. } => "/server/hello",
 
             Self::PlayerState => "/player/state",
             Self::PlayerFilePlay { .. } => "/player/play/file",
@@ -94,12 +245,28 @@ impl ClientRequest {
             Self::PlayerGetVolume => "/player/volume/get",
             Self::PlayerRewind { .. } => "/player/rewind",
             Self::PlayerFastForward { .. } => "/player/fast_forward",
+            Self::PlayerSeek { .. } => "/player/seek",
+            Self::PlayerSeekRelative { .. } => "/player/seek/relative",
+            Self::PlayerSeekPercent { .. } => "/player/seek/percent",
+            Self::PlayerTrackSelect { .. } => "/player/track/select",
+            Self::PlayerLoopSetA { .. } => "/player/loop/set_a",
+            Self::PlayerLoopSetB { .. } => "/player/loop/set_b",
+            Self::PlayerLoopClear => "/player/loop/clear",
             Self::PlayerTogglePlay => "/player/toggle/play",
             Self::PlayerToggleNext => "/player/toggle/next",
             Self::PlayerToggleRepeat => "/player/toggle/repeat",
             Self::PlayerToggleShuffle => "/player/toggle/shuffle",
             Self::PlayerVolumeUp { .. } => "/player/volume/increase",
             Self::PlayerVolumeDown { .. } => "/player/volume/decrease",
+            Self::PlayerSpeedSet { .. } => "/player/speed/set",
+            Self::PlayerEqSet { .. } => "/player/eq/set",
+            Self::PlayerEqNext => "/player/eq/next",
+            Self::PlayerEqOff => "/player/eq/off",
+            Self::PlayerSleep { .. } => "/player/sleep",
+            Self::PlayerSleepTracks { .. } => "/player/sleep/tracks",
+            Self::PlayerSleepCancel => "/player/sleep/cancel",
+            Self::PlayerSpectrumSubscribe => "/player/spectrum/subscribe",
+            Self::PlayerSpectrumUnsubscribe => "/player/spectrum/unsubscribe",
 
             Self::PlaylistState => "/playlist/state",
             Self::PlaylistOpen { .. } => "/playlist/open",
@@ -111,6 +278,10 @@ impl ClientRequest {
 
             Self::PlaylistMoveUp { .. } => "/playlist/move_up",
             Self::PlaylistMoveDown { .. } => "/playlist/move_down",
+
+            Self::LoudnessScan { .. } => "/loudness/scan",
+            Self::AudioDevicesList => "/audio/devices/list",
+            Self::AudioDeviceSelect { .. } => "/audio/devices/select",
         }
     }
 }
@@ -1,9 +1,12 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::time;
 
 use serde::{Deserialize, Serialize};
 
-use crate::player::PlayerState;
+use crate::player::{AudioDevice, OutputFormat, PlayerState, SleepTimer, Spectrum};
+use crate::protocol::{Feature, Topic};
+use crate::request::client::RequestId;
 use crate::song::DiziAudioFile;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -19,6 +22,12 @@ pub enum ServerBroadcastEvent {
     ServerQueryAll {
         query_items: HashMap<String, String>,
     },
+    // answer to a client's hello
+    ServerHello {
+        version: u32,
+        apis: Vec<String>,
+        features: Vec<Feature>,
+    },
 
     // player status updates
     PlayerState {
@@ -49,6 +58,28 @@ pub enum ServerBroadcastEvent {
     PlayerProgressUpdate {
         elapsed: time::Duration,
     },
+    PlayerEqualizer {
+        preset: Option<String>,
+    },
+    PlayerSpeed {
+        speed: f32,
+    },
+    /// switched to another audio track of the current song
+    PlayerTrack {
+        track_id: u32,
+    },
+    PlayerLoop {
+        a: Option<time::Duration>,
+        b: Option<time::Duration>,
+    },
+    /// sleep timer changed or ticked, none once it's cancelled or done
+    PlayerSleep {
+        sleep: Option<SleepTimer>,
+    },
+    /// only sent to clients subscribed to `Topic::Spectrum`
+    PlayerSpectrum {
+        spectrum: Spectrum,
+    },
 
     // playlist
     PlaylistOpen {
@@ -68,4 +99,71 @@ pub enum ServerBroadcastEvent {
         index2: usize,
     },
     PlaylistClear,
+
+    // audio output
+    AudioDevices {
+        devices: Vec<AudioDevice>,
+    },
+    AudioDeviceChanged {
+        name: String,
+        output_format: OutputFormat,
+    },
+
+    // loudness analysis
+    LoudnessScanProgress {
+        path: PathBuf,
+        // files scanned so far, including this one
+        scanned: usize,
+        total: usize,
+        // integrated loudness in LUFS, none if the file couldn't be scanned
+        integrated: Option<f32>,
+    },
+
+    /// only sent to the client that made the request, once it's been handled.
+    /// Requests for information get their answer back in `result`,
+    /// anything a request changes is still broadcast to everyone
+    Reply {
+        id: Option<RequestId>,
+        result: Result<Option<Box<ServerBroadcastEvent>>, String>,
+    },
+}
+
+impl ServerBroadcastEvent {
+    /// What a client has to be subscribed to for this event,
+    /// none for events every client gets
+    pub fn topic(&self) -> Option<Topic> {
+        match self {
+            Self::ServerError { .. } => Some(Topic::Errors),
+            Self::PlayerProgressUpdate { .. } => Some(Topic::Progress),
+            Self::PlayerSpectrum { .. } => Some(Topic::Spectrum),
+            Self::PlayerState { .. }
+            | Self::PlayerFilePlay { .. }
+            | Self::PlayerPause
+            | Self::PlayerResume
+            | Self::PlayerStop
+            | Self::PlayerRepeat { .. }
+            | Self::PlayerShuffle { .. }
+            | Self::PlayerNext { .. }
+            | Self::PlayerVolumeUpdate { .. }
+            | Self::PlayerEqualizer { .. }
+            | Self::PlayerSpeed { .. }
+            | Self::PlayerTrack { .. }
+            | Self::PlayerLoop { .. }
+            | Self::PlayerSleep { .. }
+            | Self::AudioDevices { .. }
+            | Self::AudioDeviceChanged { .. } => Some(Topic::Player),
+            Self::PlaylistOpen { .. }
+            | Self::PlaylistPlay { .. }
+            | Self::PlaylistAppend { .. }
+            | Self::PlaylistRemove { .. }
+            | Self::PlaylistSwapMove { .. }
+            | Self::PlaylistClear => Some(Topic::Playlist),
+            Self::LoudnessScanProgress { .. } => Some(Topic::Library),
+            Self::ServerQuit
+            | Self::ServerQuery { .. }
+            | Self::ServerQueryAll { .. }
+            | Self::ServerHello { .. }
+            | Self::Reply { .. } => None,
+        }
+    }
 }
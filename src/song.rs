@@ -4,7 +4,7 @@ use std::time;
 
 use symphonia::core::codecs::CodecParameters;
 use symphonia::core::formats::probe::Hint;
-use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::formats::{FormatOptions, FormatReader, Track};
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::{MetadataOptions, MetadataRevision, RawValue};
 
@@ -38,6 +38,13 @@ impl DiziSongEntry {
             Self::Loaded(s) => &s.file.file_name,
         }
     }
+
+    pub fn file(&self) -> &DiziFile {
+        match self {
+            Self::Unloaded(s) => s,
+            Self::Loaded(s) => &s.file,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -45,6 +52,9 @@ pub struct DiziFile {
     pub file_name: String,
     pub file_path: PathBuf,
     pub file_ext: Option<String>,
+    /// set for tracks of a CUE sheet, which only play part of `file_path`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cue: Option<CueTrack>,
 }
 
 impl DiziFile {
@@ -63,9 +73,21 @@ impl DiziFile {
             file_name,
             file_path: path.to_path_buf(),
             file_ext,
+            cue: None,
         }
     }
 
+    /// Turns this file into `track` of a CUE sheet, named after the track
+    pub fn with_cue_track(mut self, track: CueTrack) -> Self {
+        let title = track
+            .title
+            .clone()
+            .unwrap_or_else(|| format!("Track {}", track.number));
+        self.file_name = format!("{:02} - {}", track.number, title);
+        self.cue = Some(track);
+        self
+    }
+
     pub fn get_probe_result(&self) -> AppResult<Box<dyn FormatReader>> {
         let mut hint = Hint::new();
         if let Some(ext) = self.file_ext.as_ref() {
@@ -111,13 +133,16 @@ impl TryFrom<DiziFile> for DiziAudioFile {
         // get probe
         let mut probed = symphonia::default::get_probe().probe(&hint, mss, fmt_opts, meta_opts)?;
 
-        let audio_metadata = AudioMetadata::from_format_reader(probed.as_ref())?;
+        let mut audio_metadata = AudioMetadata::from_format_reader(probed.as_ref())?;
 
-        let music_metadata = probed
+        let mut music_metadata = probed
             .metadata()
             .skip_to_latest()
             .map(MusicMetadata::from)
             .unwrap_or_default();
+        if let Some(track) = value.cue.as_ref() {
+            track.apply(&mut audio_metadata, &mut music_metadata);
+        }
         Ok(Self {
             file: value,
             audio_metadata,
@@ -144,8 +169,79 @@ impl DiziAudioFile {
     }
 }
 
+/// A track listed in a CUE sheet, played as a song of its own
+/// out of the section of the audio file between `start` and `end`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CueTrack {
+    /// the `.cue` file, or the audio file the sheet is embedded in
+    pub sheet: PathBuf,
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub album: Option<String>,
+    pub start: time::Duration,
+    /// `None` if the track plays to the end of the file
+    pub end: Option<time::Duration>,
+}
+
+impl CueTrack {
+    /// Narrows metadata read from the whole file down to this track
+    fn apply(&self, audio_metadata: &mut AudioMetadata, music_metadata: &mut MusicMetadata) {
+        let end = self.end.or(audio_metadata.total_duration);
+        audio_metadata.total_duration = end.map(|end| end.saturating_sub(self.start));
+
+        let tags = &mut music_metadata.standard_tags;
+        tags.insert("TrackNumber".to_string(), self.number.to_string());
+        if let Some(title) = self.title.as_ref() {
+            tags.insert("TrackTitle".to_string(), title.clone());
+        }
+        if let Some(performer) = self.performer.as_ref() {
+            tags.insert("Artist".to_string(), performer.clone());
+        }
+        if let Some(album) = self.album.as_ref() {
+            tags.insert("Album".to_string(), album.clone());
+        }
+    }
+}
+
+/// An audio track of a file, files like videos can have several
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AudioTrack {
+    pub id: u32,
+    pub language: Option<String>,
+    /// short name of the codec, or its id if no decoder is available for it
+    pub codec: String,
+    pub decodable: bool,
+    pub bit_depth: u32,
+    pub channels: Option<usize>,
+    pub sample_rate: Option<u32>,
+}
+
+impl AudioTrack {
+    /// Returns `None` for tracks that aren't audio
+    fn from_track(track: &Track) -> Option<Self> {
+        let Some(CodecParameters::Audio(params)) = track.codec_params.as_ref() else {
+            return None;
+        };
+        let decoder = symphonia::default::get_codecs().get_audio_decoder(params.codec);
+        let codec = decoder
+            .map(|decoder| decoder.codec.info.short_name.to_string())
+            .unwrap_or_else(|| params.codec.to_string());
+        Some(Self {
+            id: track.id,
+            language: track.language.clone(),
+            codec,
+            decodable: decoder.is_some(),
+            bit_depth: params.bits_per_sample.unwrap_or(16),
+            channels: params.channels.as_ref().map(|c| c.count()),
+            sample_rate: params.sample_rate,
+        })
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct AudioMetadata {
+    /// id of the audio track being played
     #[serde(rename = "track_id")]
     pub track_id: u32,
     #[serde(rename = "bit_depth")]
@@ -156,6 +252,8 @@ pub struct AudioMetadata {
     pub sample_rate: Option<u32>,
     #[serde(rename = "total_duration")]
     pub total_duration: Option<time::Duration>,
+    #[serde(rename = "tracks", default)]
+    pub tracks: Vec<AudioTrack>,
 }
 
 impl AudioMetadata {
@@ -172,47 +270,70 @@ impl AudioMetadata {
                     tracing::error!(?time_base, ?start_timestamp, ?duration, "{error_msg}");
                     DiziError::new(DiziErrorKind::ParseError, error_msg.to_string())
                 })?;
-                let duration = time::Duration::from_secs(unit_time.as_secs() as u64);
+                let duration = time::Duration::from_millis(unit_time.as_millis().max(0) as u64);
                 Some(duration)
             }
             _ => None,
         };
 
-        let track = reader.tracks().first().ok_or_else(|| {
-            let error_msg = "No tracks found";
-            tracing::error!("{error_msg}");
-            DiziError::new(DiziErrorKind::ParseError, error_msg.to_string())
-        })?;
-
-        let track_id = track.id;
-        let codec_parameters = track.codec_params.as_ref().ok_or_else(|| {
-            let error_msg = "No codec parameters found";
+        // containers like mkv and mp4 often have a video track first
+        let tracks: Vec<AudioTrack> = reader
+            .tracks()
+            .iter()
+            .filter_map(AudioTrack::from_track)
+            .collect();
+        let track = tracks.iter().find(|track| track.decodable).ok_or_else(|| {
+            let error_msg = if tracks.is_empty() {
+                "No audio tracks found"
+            } else {
+                "No decodable audio tracks found"
+            };
             tracing::error!("{error_msg}");
             DiziError::new(DiziErrorKind::ParseError, error_msg.to_string())
         })?;
 
-        let audio_codec_params = match codec_parameters {
-            CodecParameters::Audio(params) => params,
-            _ => {
-                let error_msg = "Codec not audio";
-                tracing::error!("{error_msg}");
-                let err = DiziError::new(DiziErrorKind::ParseError, error_msg.to_string());
-                return Err(err);
-            }
-        };
-
-        let channels = audio_codec_params.channels.as_ref().map(|c| c.count());
-        let sample_rate = audio_codec_params.sample_rate;
-
-        let bit_depth = audio_codec_params.bits_per_sample.unwrap_or(16);
         Ok(Self {
-            track_id,
-            bit_depth,
-            channels,
-            sample_rate,
+            track_id: track.id,
+            bit_depth: track.bit_depth,
+            channels: track.channels,
+            sample_rate: track.sample_rate,
             total_duration,
+            tracks,
         })
     }
+
+    /// The audio track being played
+    pub fn track(&self) -> Option<&AudioTrack> {
+        self.tracks.iter().find(|track| track.id == self.track_id)
+    }
+
+    /// Switches to the audio track with id `track_id`
+    pub fn select_track(&mut self, track_id: u32) -> AppResult {
+        let track = self
+            .tracks
+            .iter()
+            .find(|track| track.id == track_id)
+            .ok_or_else(|| {
+                DiziError::new(
+                    DiziErrorKind::InvalidParameters,
+                    format!("No audio track with id {track_id}"),
+                )
+            })?;
+        if !track.decodable {
+            return Err(DiziError::new(
+                DiziErrorKind::InvalidParameters,
+                format!(
+                    "Audio track {track_id} uses an unsupported codec ({})",
+                    track.codec
+                ),
+            ));
+        }
+        self.track_id = track.id;
+        self.bit_depth = track.bit_depth;
+        self.channels = track.channels;
+        self.sample_rate = track.sample_rate;
+        Ok(())
+    }
 }
 
 impl std::default::Default for AudioMetadata {
@@ -223,6 +344,7 @@ impl std::default::Default for AudioMetadata {
             channels: None,
             sample_rate: None,
             total_duration: None,
+            tracks: Vec::new(),
         }
     }
 }
@@ -233,6 +355,12 @@ pub struct MusicMetadata {
     pub tags: HashMap<String, String>,
 }
 
+impl MusicMetadata {
+    pub fn album(&self) -> Option<&str> {
+        self.standard_tags.get("Album").map(|s| s.as_str())
+    }
+}
+
 impl std::convert::From<&MetadataRevision> for MusicMetadata {
     fn from(metadata: &MetadataRevision) -> Self {
         let standard_tags: HashMap<String, String> = metadata
@@ -245,7 +373,10 @@ impl std::convert::From<&MetadataRevision> for MusicMetadata {
                     RawValue::String(s) => Some(s.to_string()),
                     _ => None,
                 }?;
-                Some((format!("{:?}", std_key), tag_value))
+                // only keep the variant name, e.g. `Album("...")` -> `Album`
+                let std_key = format!("{:?}", std_key);
+                let std_key = std_key.split('(').next().unwrap_or_default().to_string();
+                Some((std_key, tag_value))
             })
             .collect();
         let tags: HashMap<String, String> = metadata